@@ -0,0 +1,29 @@
+//! Times the phases of importing a glTF file that don't need the GPU. Mesh
+//! and texture creation go through a `GraphicsContext`, which needs a window
+//! to be created, so they aren't covered yet.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use ayude::import_gltf;
+
+const FIXTURE: &str = "benches/fixtures/grid.gltf";
+
+fn import(c: &mut Criterion) {
+    let bytes = std::fs::read(FIXTURE).unwrap();
+    c.bench_function("parse document", |b| {
+        b.iter(|| gltf::Gltf::from_slice(&bytes).unwrap())
+    });
+
+    // reads the buffers and goes through every triangle
+    c.bench_function("validate", |b| {
+        b.iter(|| assert!(import_gltf::validate(FIXTURE).is_empty()))
+    });
+
+    let image = std::fs::read("benches/fixtures/grid.png").unwrap();
+    c.bench_function("decode image", |b| {
+        b.iter(|| image::load_from_memory_with_format(&image, image::ImageFormat::Png).unwrap())
+    });
+}
+
+criterion_group!(benches, import);
+criterion_main!(benches);
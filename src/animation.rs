@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+
+use glam::{Quat, Vec3};
+
+use crate::{
+    transform::{Transform, Trs},
+    NodeId, Scene,
+};
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// Imported animations without a name in the glTF are named after their
+    /// index, like `animation_0`.
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    /// Time of the last keyframe of any channel, in seconds.
+    pub duration: f32,
+    /// Named parts of the timeline, such as an attack within a combined
+    /// clip, see `AnimationPlayer::play_by_name`.
+    pub ranges: Vec<AnimationRange>,
+    /// Application specific data from the glTF `extras` of the animation.
+    pub extras: Option<serde_json::Value>,
+}
+
+/// A named part of an animation, from `start` to `end` seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationRange {
+    pub name: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub node: NodeId,
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds, in increasing order.
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    /// Values are stored as (in tangent, value, out tangent) triplets, and
+    /// sampled as a cubic Hermite spline between each value and the next,
+    /// with their out and in tangents scaled by the time between them, as
+    /// glTF specifies. The first in tangent and last out tangent are unused.
+    CubicSpline,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChannelValues {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    /// All morph target weights of a keyframe, one keyframe after another.
+    /// With `Interpolation::CubicSpline`, each keyframe has the in tangents
+    /// of every target, then the weights, then the out tangents.
+    MorphWeights(Vec<f32>),
+}
+
+/// The animated state of every node of a scene.
+#[derive(Debug, Clone)]
+pub struct Pose {
+    pub trs: HashMap<NodeId, Trs>,
+    pub morph_weights: HashMap<NodeId, Vec<f32>>,
+}
+
+impl Pose {
+    /// The pose the scene was imported with.
+    pub fn rest(scene: &Scene) -> Self {
+        Self {
+            trs: scene
+                .nodes
+                .iter_with_id()
+                .map(|(id, it)| (*id, it.trs))
+                .collect(),
+            morph_weights: scene
+                .nodes
+                .iter_with_id()
+                .map(|(id, it)| (*id, it.morph_weights.clone()))
+                .collect(),
+        }
+    }
+
+    /// Interpolates every node in both poses from `a` to `b`, slerping
+    /// rotations.
+    pub fn blend(a: &Pose, b: &Pose, weight: f32) -> Pose {
+        let trs = a
+            .trs
+            .iter()
+            .filter_map(|(id, a)| Some((*id, a, b.trs.get(id)?)))
+            .map(|(id, a, b)| {
+                let trs = Trs {
+                    translation: a.translation.lerp(b.translation, weight),
+                    rotation: a.rotation.slerp(b.rotation, weight),
+                    scale: a.scale.lerp(b.scale, weight),
+                };
+                (id, trs)
+            })
+            .collect();
+        let morph_weights = a
+            .morph_weights
+            .iter()
+            .filter_map(|(id, a)| Some((*id, a, b.morph_weights.get(id)?)))
+            .map(|(id, a, b)| {
+                let weights = a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| a + (b - a) * weight)
+                    .collect();
+                (id, weights)
+            })
+            .collect();
+        Pose { trs, morph_weights }
+    }
+
+    /// Writes the pose into the nodes of `scene`, which should be the scene
+    /// (or a duplicate of the scene) the pose was created for.
+    pub fn apply(&self, scene: &mut Scene) {
+        for (id, node) in scene.nodes.iter_with_id_mut() {
+            if let Some(trs) = self.trs.get(id) {
+                node.trs = *trs;
+                node.transform = Transform::from(*trs);
+            }
+            if let Some(weights) = self.morph_weights.get(id) {
+                node.morph_weights.clone_from(weights);
+            }
+        }
+    }
+}
+
+impl Animation {
+    /// Overwrites the channels this animation targets with their value at
+    /// `time`, leaving every other node untouched.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            channel.sample(time, pose);
+        }
+    }
+}
+
+impl Channel {
+    fn sample(&self, time: f32, pose: &mut Pose) {
+        let trs = match pose.trs.get_mut(&self.node) {
+            Some(trs) if !self.times.is_empty() => trs,
+            _ => return,
+        };
+
+        // (previous keyframe, next keyframe, factor between them, time between them)
+        let last = self.times.len() - 1;
+        let (prev, next, t, dt) = match self.times.iter().position(|&it| it > time) {
+            Some(0) => (0, 0, 0.0, 0.0),
+            Some(next) => {
+                let prev = next - 1;
+                let dt = self.times[next] - self.times[prev];
+                (prev, next, (time - self.times[prev]) / dt, dt)
+            }
+            None => (last, last, 0.0, 0.0),
+        };
+
+        match &self.values {
+            ChannelValues::Translation(values) => {
+                trs.translation = self.interpolate(values, prev, next, t, dt, Vec3::lerp)
+            }
+            ChannelValues::Scale(values) => {
+                trs.scale = self.interpolate(values, prev, next, t, dt, Vec3::lerp)
+            }
+            ChannelValues::Rotation(values) => {
+                trs.rotation = self
+                    .interpolate(values, prev, next, t, dt, Quat::slerp)
+                    .normalize()
+            }
+            ChannelValues::MorphWeights(values) => {
+                let stride = match self.interpolation {
+                    Interpolation::CubicSpline => 3,
+                    _ => 1,
+                };
+                let count = values.len() / (self.times.len() * stride);
+                let weights = pose.morph_weights.entry(self.node).or_default();
+                weights.resize(count, 0.0);
+                for (i, weight) in weights.iter_mut().enumerate() {
+                    let target = values
+                        .iter()
+                        .skip(i)
+                        .step_by(count)
+                        .copied()
+                        .collect::<Vec<_>>();
+                    *weight =
+                        self.interpolate(&target, prev, next, t, dt, |a, b, t| a + (b - a) * t);
+                }
+            }
+        }
+    }
+
+    fn interpolate<T>(
+        &self,
+        values: &[T],
+        prev: usize,
+        next: usize,
+        t: f32,
+        dt: f32,
+        lerp: impl Fn(T, T, f32) -> T,
+    ) -> T
+    where
+        T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+    {
+        match self.interpolation {
+            Interpolation::Step => values[prev],
+            Interpolation::Linear => lerp(values[prev], values[next], t),
+            Interpolation::CubicSpline => {
+                let p0 = values[prev * 3 + 1];
+                let m0 = values[prev * 3 + 2] * dt;
+                let p1 = values[next * 3 + 1];
+                let m1 = values[next * 3] * dt;
+                let t2 = t * t;
+                let t3 = t2 * t;
+                p0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + m0 * (t3 - 2.0 * t2 + t)
+                    + p1 * (-2.0 * t3 + 3.0 * t2)
+                    + m1 * (t3 - t2)
+            }
+        }
+    }
+}
+
+/// An animation being played, at `time` seconds between `start` and `end`.
+#[derive(Debug, Clone, Copy)]
+struct Clip {
+    index: usize,
+    time: f32,
+    start: f32,
+    /// `None` plays until the end of the animation.
+    end: Option<f32>,
+}
+
+impl Clip {
+    fn whole(index: usize) -> Self {
+        Self {
+            index,
+            time: 0.0,
+            start: 0.0,
+            end: None,
+        }
+    }
+
+    /// The animation or range called `name`, animations first.
+    fn named(scene: &Scene, name: &str) -> Option<Self> {
+        let animations = &scene.animations;
+        if let Some(index) = animations
+            .iter()
+            .position(|it| it.name.as_deref() == Some(name))
+        {
+            return Some(Self::whole(index));
+        }
+        animations
+            .iter()
+            .enumerate()
+            .find_map(|(index, animation)| {
+                let range = animation.ranges.iter().find(|it| it.name == name)?;
+                Some(Self {
+                    index,
+                    time: range.start,
+                    start: range.start,
+                    end: Some(range.end),
+                })
+            })
+    }
+}
+
+/// Plays the animations of a scene on a pose of its own, so several
+/// instances of the same scene can play different clips.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    rest: Pose,
+    pose: Pose,
+    current: Option<Clip>,
+    fading_out: Option<Clip>,
+    fade: (f32, f32), // elapsed, duration
+    looping: bool,
+    speed: f32,
+}
+
+impl AnimationPlayer {
+    pub fn new(scene: &Scene) -> Self {
+        let rest = Pose::rest(scene);
+        Self {
+            pose: rest.clone(),
+            rest,
+            current: None,
+            fading_out: None,
+            fade: (0.0, 0.0),
+            looping: true,
+            speed: 1.0,
+        }
+    }
+
+    /// Starts playing the animation with the given index from the beginning.
+    pub fn play(&mut self, index: usize) {
+        self.current = Some(Clip::whole(index));
+        self.fading_out = None;
+    }
+
+    /// Starts playing the animation of `scene` called `name` from the
+    /// beginning, or else the first range called that, looping within it.
+    /// Returns whether there was one.
+    pub fn play_by_name(&mut self, scene: &Scene, name: &str) -> bool {
+        match Clip::named(scene, name) {
+            Some(clip) => {
+                self.current = Some(clip);
+                self.fading_out = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Starts playing the animation with the given index, blending from the
+    /// current one over `duration` seconds.
+    pub fn crossfade(&mut self, index: usize, duration: f32) {
+        self.fading_out = self.current;
+        self.current = Some(Clip::whole(index));
+        self.fade = (0.0, duration);
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.fading_out = None;
+    }
+
+    /// Stops playing and puts the pose back to the one the scene was
+    /// imported with, for `Pose::apply` to snap the nodes back to their rest
+    /// transforms and morph weights.
+    pub fn reset(&mut self) {
+        self.stop();
+        self.pose = self.rest.clone();
+    }
+
+    pub fn set_loop(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.current.map(|it| it.index)
+    }
+
+    pub fn pose(&self) -> &Pose {
+        &self.pose
+    }
+
+    /// Moves time forward by `delta` seconds and samples the playing
+    /// animations of `scene` into the pose.
+    pub fn advance(&mut self, scene: &Scene, delta: f32) {
+        let delta = delta * self.speed;
+        let looping = self.looping;
+        let step = |clip: &mut Option<Clip>| {
+            if let Some(clip) = clip {
+                let end = clip.end.unwrap_or_else(|| {
+                    scene
+                        .animations
+                        .get(clip.index)
+                        .map_or(0.0, |it| it.duration)
+                });
+                clip.time += delta;
+                if looping && end > clip.start {
+                    clip.time = clip.start + (clip.time - clip.start).rem_euclid(end - clip.start);
+                } else {
+                    clip.time = clip.time.min(end);
+                }
+            }
+        };
+        step(&mut self.current);
+        step(&mut self.fading_out);
+
+        let current = self.sample(scene, self.current.map(|it| (it.index, it.time)));
+
+        self.pose = match self.fading_out {
+            Some(_) => {
+                self.fade.0 += delta.abs();
+                if self.fade.0 >= self.fade.1 {
+                    self.fading_out = None;
+                    current
+                } else {
+                    let previous =
+                        self.sample(scene, self.fading_out.map(|it| (it.index, it.time)));
+                    Pose::blend(&previous, &current, self.fade.0 / self.fade.1)
+                }
+            }
+            None => current,
+        };
+    }
+
+    /// Samples two animations at the same phase and interpolates between
+    /// them, with `weight` 0 giving `clip_a` and 1 giving `clip_b`. `phase` is
+    /// normalized time, where 0 is the start and 1 the end of each clip, so
+    /// clips of different durations (a walk and a run cycle) stay in step.
+    pub fn blend(
+        &self,
+        scene: &Scene,
+        clip_a: usize,
+        clip_b: usize,
+        weight: f32,
+        phase: f32,
+    ) -> Pose {
+        let phase = if self.looping {
+            phase.rem_euclid(1.0)
+        } else {
+            phase.clamp(0.0, 1.0)
+        };
+        let time = |index: usize| {
+            let duration = scene.animations.get(index).map_or(0.0, |it| it.duration);
+            phase * duration
+        };
+        let a = self.sample(scene, Some((clip_a, time(clip_a))));
+        let b = self.sample(scene, Some((clip_b, time(clip_b))));
+        Pose::blend(&a, &b, weight.clamp(0.0, 1.0))
+    }
+
+    fn sample(&self, scene: &Scene, clip: Option<(usize, f32)>) -> Pose {
+        let mut pose = self.rest.clone();
+        if let Some((index, time)) = clip {
+            if let Some(animation) = scene.animations.get(index) {
+                animation.sample(time, &mut pose);
+            }
+        }
+        pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Catalog, Node};
+
+    #[test]
+    fn cubic_spline_scales_tangents_by_the_key_interval() {
+        // x³ from (0, 0) to (2, 8), whose slope is 0 at the start and 12 at
+        // the end
+        let node = Catalog::<Node>::new().reserve();
+        let channel = Channel {
+            node,
+            interpolation: Interpolation::CubicSpline,
+            times: vec![0.0, 2.0],
+            values: ChannelValues::Translation(vec![
+                Vec3::ZERO,
+                Vec3::ZERO,
+                Vec3::ZERO,
+                Vec3::splat(12.0),
+                Vec3::splat(8.0),
+                Vec3::ZERO,
+            ]),
+        };
+        let rest = Trs {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        };
+        let mut pose = Pose {
+            trs: vec![(node, rest)].into_iter().collect(),
+            morph_weights: HashMap::new(),
+        };
+
+        channel.sample(1.0, &mut pose);
+        let translation = pose.trs[&node].translation;
+        assert!(
+            translation.abs_diff_eq(Vec3::ONE, 1.0e-5),
+            "{}",
+            translation
+        );
+    }
+}
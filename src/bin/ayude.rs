@@ -1,14 +1,24 @@
 use ayude::{
-    camera::Camera,
-    graphics::{self, GraphicsContext, Material, TextureDescription},
+    camera::{Camera, DEFAULT_DEPTH_RATIO},
+    graphics::{
+        self, AutoExposure, BillboardMode, DebugView, DynamicResolution, GraphicsContext, Material,
+        PassOps, Texture, Tonemap,
+    },
     import_gltf,
+    particles::{Emitter, ParticleSystem},
+    render_graph::{PassTarget, RenderGraph, RenderNode},
     transform::Transform,
     Scene,
 };
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use rusttype::{Font, Scale};
 use std::{
-    sync::Arc,
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 use winit::{
@@ -18,21 +28,54 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+/// Time simulated by each call to `World::update`, whatever the frame rate.
+const FIXED_TIMESTEP: Duration = Duration::from_millis(10);
+/// Longest frame time taken into account. After a longer stall (dragging the
+/// window, a breakpoint) the world only moves forward by this much, instead of
+/// the camera jumping across the scene or ever longer frames being spent
+/// catching up.
+const MAX_FRAME_DELTA: Duration = Duration::from_millis(100);
+
 pub struct World {
     camera: Camera,
+    /// The camera before the last update, to interpolate from when rendering.
+    previous_camera: Camera,
+    /// How far between the last two updates the rendered frame is, from 0 to 1.
+    interpolation: f32,
+    /// Drawn on the right half of the window when set, next to `camera`, to
+    /// compare two angles.
+    pinned_camera: Option<Camera>,
+
+    focused: bool,
+    /// Whether time stops while the window isn't focused. Input is ignored
+    /// either way.
+    pause_when_unfocused: bool,
 
     movement: [f32; 2], // stores WASD input
 
     the_scene: Scene,
-    _the_sphere: Scene,
+    the_scene_file_name: String,
+    scene_watcher: Option<SceneWatcher>,
+    the_sphere: Scene,
 
-    the_scene_skin_visualization: Vec<(graphics::UniformBuffer, Material, Scene, usize)>,
+    the_scene_skin_visualization: Vec<JointMarker>,
     visualization_depth: usize,
 
+    font: Font<'static>,
     test_font_texture: graphics::Texture,
     test_font_uniform_buffer: graphics::UniformBuffer,
 
     rendering_skin: bool,
+    render_stats: graphics::RenderStats,
+
+    crosshair: Crosshair,
+
+    sparks: ParticleSystem,
+    spark_texture: graphics::Texture,
+    decal: graphics::Decal,
+    /// The last ray cast on click and the boxes it was tested against, empty
+    /// when hidden.
+    picking_lines: graphics::DebugLines,
 
     graphics: GraphicsContext,
 }
@@ -81,19 +124,309 @@ fn create_texture_for_text(
         }
     }
 
-    graphics.create_texture(&TextureDescription::new(
+    Texture::builder(
         &pixel_data,
         width as u32,
         pixel_height as u32,
         wgpu::TextureFormat::Rgba8Unorm,
-    ))
+    )
+    .build(graphics)
+}
+
+/// A sphere on a joint with its name above it, and the joint's depth in the
+/// skeleton.
+type JointMarker = (graphics::UniformBuffer, Material, Scene, usize);
+
+fn create_skin_visualization(
+    scene: &Scene,
+    sphere: &Scene,
+    font: &Font,
+    graphics: &GraphicsContext,
+) -> Vec<JointMarker> {
+    let mut res = vec![];
+    for node in scene.nodes.iter() {
+        let skin = match node.skin.as_ref() {
+            Some(skin) => skin,
+            None => continue,
+        };
+
+        for (joint_index, &joint_id) in skin.joints.iter().enumerate() {
+            let joint = match scene.nodes.get(joint_id) {
+                Some(joint) => joint,
+                None => continue,
+            };
+
+            let mut depth = 0;
+
+            let mut transform = joint.transform.mat4().clone();
+            let mut current = joint;
+            'transform: loop {
+                match scene.nodes.get_opt(current.parent) {
+                    Some(parent) => {
+                        current = parent;
+                        depth += 1;
+                    }
+                    None => break 'transform,
+                }
+                transform = transform * current.transform.mat4();
+            }
+
+            let ibm = skin.inverse_bind_matrices[joint_index].mat4();
+
+            let mut joint_scene = sphere.duplicate(graphics);
+            joint_scene.transform = Transform::from(transform
+                    * ibm.inverse()
+                    * Mat4::from_scale(Vec3::new(0.25, 0.25, 0.25)),
+            );
+
+            let name = joint.name.clone().unwrap_or(format!("{:?}", joint_id));
+            let name_tex = create_texture_for_text(font, graphics, &name);
+
+            let mat = Material {
+                base_diffuse_color: [0.0, 0.0, 0.0, 1.0],
+                diffuse: Some(name_tex),
+                normal: None,
+                orm: None,
+                metallic_factor: 1.0,
+                roughness_factor: 1.0,
+                occlusion_strength: 1.0,
+                shaded: false,
+                alpha_blend: false,
+                transmission_factor: 0.0,
+                transmission_texture: None,
+                ior: 1.5,
+                clearcoat_factor: 0.0,
+                clearcoat_roughness_factor: 0.0,
+                clearcoat_texture: None,
+                clearcoat_roughness_texture: None,
+                specular_factor: 1.0,
+                specular_color_factor: [1.0; 3],
+                specular_texture: None,
+                specular_color_texture: None,
+                sampler: None,
+                depth_bias: None,
+                name: None,
+                extras: None,
+            };
+
+            let ub = graphics.create_uniform_buffer();
+
+            res.push((ub, mat, joint_scene, depth));
+        }
+    }
+    res
+}
+
+/// A white disc fading out towards its edge.
+fn create_spark_texture(graphics: &GraphicsContext) -> graphics::Texture {
+    let size = 32;
+    let mut pixel_data = vec![0u8; size * size * 4];
+    for y in 0..size {
+        for x in 0..size {
+            let center = size as f32 / 2.0;
+            let distance = Vec2::new(x as f32 + 0.5 - center, y as f32 + 0.5 - center).length();
+            let alpha = (1.0 - distance / center).max(0.0);
+            let i = (y * size + x) * 4;
+            pixel_data[i..i + 4].copy_from_slice(&[255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+
+    Texture::builder(
+        &pixel_data,
+        size as u32,
+        size as u32,
+        wgpu::TextureFormat::Rgba8Unorm,
+    )
+    .build(graphics)
+}
+
+struct Crosshair {
+    visible: bool,
+    size: f32,      // pixels from end to end
+    thickness: f32, // pixels
+    color: [f32; 4],
+    texture: graphics::Texture,
+    horizontal: graphics::ScreenQuad,
+    vertical: graphics::ScreenQuad,
+}
+
+impl Crosshair {
+    fn new(graphics: &GraphicsContext) -> Self {
+        Self {
+            visible: true,
+            size: 16.0,
+            thickness: 2.0,
+            color: [1.0, 1.0, 1.0, 0.8],
+            texture: graphics.empty_texture().clone(),
+            horizontal: graphics.create_screen_quad(),
+            vertical: graphics.create_screen_quad(),
+        }
+    }
+
+    fn render<'a>(&'a self, pass: &mut graphics::Pass<'a, 'a>, screen_size: (u32, u32)) {
+        if !self.visible {
+            return;
+        }
+
+        let center_x = (screen_size.0 / 2) as f32;
+        let center_y = (screen_size.1 / 2) as f32;
+        let (long, short) = (self.size, self.thickness);
+        let uv_rect = [0.0, 0.0, 1.0, 1.0];
+        pass.draw_screen_quad(
+            &self.horizontal,
+            &self.texture,
+            [center_x - long / 2.0, center_y - short / 2.0, long, short],
+            uv_rect,
+            self.color,
+        );
+        pass.draw_screen_quad(
+            &self.vertical,
+            &self.texture,
+            [center_x - short / 2.0, center_y - long / 2.0, short, long],
+            uv_rect,
+            self.color,
+        );
+    }
+}
+
+struct SceneWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    files: Vec<PathBuf>,
+}
+
+impl SceneWatcher {
+    fn new(file_name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (sender, events) = channel();
+        let mut watcher: RecommendedWatcher = Watcher::new(sender, Duration::from_millis(250))?;
+
+        // exporters usually replace files instead of writing them in place, so
+        // watch the containing directories rather than the files themselves
+        let mut files = vec![];
+        for file in import_gltf::referenced_files(file_name)? {
+            let directory = match file.parent() {
+                Some(it) if it.as_os_str().is_empty() => PathBuf::from("."),
+                Some(it) => it.to_path_buf(),
+                None => PathBuf::from("."),
+            }
+            .canonicalize()?;
+            watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+            if let Some(name) = file.file_name() {
+                files.push(directory.join(name));
+            }
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            files,
+        })
+    }
+
+    fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            let path = match &event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                _ => continue,
+            };
+            changed |= self.files.iter().any(|it| it == path);
+        }
+        changed
+    }
+}
+
+/// Draws the scene, or the skin visualization instead, in the scene pass.
+struct MainPass<'w> {
+    scene: &'w Scene,
+    /// Drawn instead of `scene` when given, up to the given depth.
+    skin_visualization: Option<(&'w [JointMarker], usize)>,
+    label: (&'w graphics::UniformBuffer, &'w Material),
+    sparks: &'w ParticleSystem,
+    spark_texture: &'w Texture,
+    perspective: Mat4,
+    view: Mat4,
+    camera_position: Vec3,
+    /// See `Pass::set_viewport`.
+    viewport: [f32; 4],
+}
+
+impl RenderNode for MainPass<'_> {
+    fn target(&self) -> PassTarget {
+        PassTarget::Scene
+    }
+
+    fn draw<'a>(&'a self, pass: &mut graphics::Pass<'a, 'a>) {
+        let (perspective, view) = (self.perspective, self.view);
+        pass.set_viewport(self.viewport);
+
+        match self.skin_visualization {
+            None => {
+                self.scene.render(pass, perspective, view);
+                let translation = Vec3::new(-1.0, -1.0, 0.0);
+                pass.render_billboard(
+                    self.label.0,
+                    self.label.1,
+                    perspective,
+                    view,
+                    translation,
+                    self.camera_position,
+                    BillboardMode::Spherical,
+                );
+                self.sparks
+                    .render(pass, perspective, view, Some(self.spark_texture));
+            }
+            Some((visualization, max_depth)) => {
+                for (ub, name, scene, depth) in visualization {
+                    if max_depth >= *depth {
+                        scene.render(pass, perspective, view);
+
+                        let s = scene.transform.scale().y;
+                        let pos = scene.transform.position() + Vec3::new(0.0, s * 2.0, 0.0);
+
+                        pass.render_billboard(
+                            ub,
+                            name,
+                            perspective,
+                            view,
+                            pos,
+                            self.camera_position,
+                            BillboardMode::Spherical,
+                        );
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl World {
     fn new(graphics: GraphicsContext) -> Self {
         let gltf_file_name = "samples/knight/knight.gltf";
         // let gltf_file_name = "samples/principito_y_el_aviador/scene.gltf";
-        let the_scene = import_gltf::import_default_scene(gltf_file_name, &graphics).unwrap();
+        for issue in import_gltf::validate(gltf_file_name) {
+            eprintln!("warning: {}", issue);
+        }
+        let options = import_gltf::ImportOptions {
+            stream_textures: true,
+            ..Default::default()
+        };
+        let (the_scene, report) =
+            import_gltf::import_default_scene_with_report(gltf_file_name, &graphics, &options)
+                .unwrap();
+        if let Some((before, after)) = report.decimated {
+            println!("Decimated from {} to {} triangles", before, after);
+        }
+        if let Some((before, after)) = report.merged {
+            println!(
+                "Merged {} meshes into {} by material, saving {} draws",
+                before,
+                after,
+                before - after
+            );
+        }
 
         let the_sphere =
             import_gltf::import_default_scene("samples/sphere.gltf", &graphics).unwrap();
@@ -109,74 +442,64 @@ impl World {
         let test_font_texture = create_texture_for_text(&font, &graphics, "RIGHT NOW.");
         let test_font_uniform_buffer = graphics.create_uniform_buffer();
 
-        let the_scene_skin_visualization = {
-            let mut res = vec![];
-            let scene = &the_scene;
-            for node in &scene.nodes {
-                let skin = match node.skin.as_ref() {
-                    Some(skin) => skin,
-                    None => continue,
-                };
-
-                for (joint_index, &node_index) in skin.joints.iter().enumerate() {
-                    let joint = &scene.nodes[usize::from(node_index)];
-
-                    let mut depth = 0;
-
-                    let mut transform = joint.transform.mat4().clone();
-                    let mut current = joint;
-                    'transform: loop {
-                        match current.parent {
-                            Some(index) => {
-                                current = &scene.nodes[usize::from(index)];
-                                depth += 1;
-                            }
-                            None => break 'transform,
-                        }
-                        transform = transform * current.transform.mat4();
-                    }
-
-                    let ibm = skin.inverse_bind_matrices[joint_index].mat4();
-
-                    let mut joint_scene = the_sphere.duplicate(&graphics);
-                    joint_scene.transform = Transform::from(transform
-                            * ibm.inverse()
-                            * Mat4::from_scale(Vec3::new(0.25, 0.25, 0.25)),
-                    );
-
-                    let name = joint.name.clone().unwrap_or(format!("{}", node_index));
-                    let name_tex = create_texture_for_text(&font, &graphics, &name);
-
-                    let mat = Material {
-                        base_diffuse_color: [0.0, 0.0, 0.0, 1.0],
-                        diffuse: Some(name_tex),
-                        normal: None,
-                        shaded: false,
-                    };
-
-                    let ub = graphics.create_uniform_buffer();
+        let the_scene_skin_visualization =
+            create_skin_visualization(&the_scene, &the_sphere, &font, &graphics);
+
+        let crosshair = Crosshair::new(&graphics);
+
+        let sparks = ParticleSystem::new(
+            &graphics,
+            Emitter {
+                position: Vec3::new(0.0, 0.0, 0.0),
+                rate: 60.0,
+                lifetime: 1.5,
+                velocity: Vec3::new(0.0, 6.0, 0.0),
+                velocity_spread: Vec3::new(2.0, 1.0, 2.0),
+                size: 0.3,
+                color: Vec4::new(4.0, 1.6, 0.4, 1.0),
+                gravity: Vec3::new(0.0, -9.8, 0.0),
+            },
+            256,
+        );
+        let spark_texture = create_spark_texture(&graphics);
+        let decal = graphics.create_decal();
+        let picking_lines = graphics.create_debug_lines(4096);
 
-                    res.push((ub, mat, joint_scene, depth));
-                }
-            }
-            res
-        };
+        let scene_watcher = SceneWatcher::new(gltf_file_name)
+            .map_err(|e| eprintln!("Hot reloading disabled: {}", e))
+            .ok();
 
         let world = World {
+            previous_camera: camera.clone(),
+            pinned_camera: None,
+            interpolation: 1.0,
+            focused: true,
+            pause_when_unfocused: true,
             camera,
 
             movement: [0.0, 0.0],
 
             the_scene,
-            _the_sphere: the_sphere,
+            the_scene_file_name: gltf_file_name.to_string(),
+            scene_watcher,
+            the_sphere,
 
             the_scene_skin_visualization,
             visualization_depth: 0,
 
+            font,
             test_font_texture,
             test_font_uniform_buffer,
 
             rendering_skin: false,
+            render_stats: graphics::RenderStats::default(),
+
+            crosshair,
+
+            sparks,
+            spark_texture,
+            decal,
+            picking_lines,
 
             graphics,
         };
@@ -184,69 +507,244 @@ impl World {
         world
     }
 
+    /// Returns whether the scene was replaced.
+    fn reload_scene_if_changed(&mut self) -> bool {
+        let changed = match self.scene_watcher.as_ref() {
+            Some(watcher) => watcher.changed(),
+            None => false,
+        };
+        if !changed {
+            return false;
+        }
+
+        let reloaded = match import_gltf::reimport(
+            &self.the_scene_file_name,
+            &self.the_scene,
+            &self.graphics,
+            &import_gltf::ImportOptions::default(),
+        ) {
+            Ok((scene, diff)) => {
+                self.the_scene_skin_visualization =
+                    create_skin_visualization(&scene, &self.the_sphere, &self.font, &self.graphics);
+                self.the_scene = scene;
+                println!(
+                        "Reloaded {}: {} nodes added, {} removed, {} moved, {} with new geometry, {} with new materials",
+                        self.the_scene_file_name,
+                        diff.added.len(),
+                        diff.removed.len(),
+                        diff.moved.len(),
+                        diff.geometry_changed.len(),
+                        diff.materials_changed.len(),
+                    );
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to reload {}: {}", self.the_scene_file_name, e);
+                false
+            }
+        };
+
+        // the set of referenced files may have changed with the new export
+        match SceneWatcher::new(&self.the_scene_file_name) {
+            Ok(watcher) => self.scene_watcher = Some(watcher),
+            Err(e) => eprintln!("Failed to watch {}: {}", self.the_scene_file_name, e),
+        }
+
+        reloaded
+    }
+
+    /// Casts a ray from the camera, straight ahead, and shows it along with
+    /// the boxes it hit in green and those it missed in red. Hides them
+    /// instead if they're showing.
+    fn toggle_picking_debug(&mut self) {
+        if !self.picking_lines.is_empty() {
+            self.picking_lines.clear();
+            return;
+        }
+        let transform = self.camera.transform();
+        let (origin, direction) = (transform.position(), transform.forward());
+        let tests = self.the_scene.raycast_boxes(origin, direction);
+        // ends at the far plane
+        self.picking_lines
+            .line(origin, origin + direction * 1024.0, [1.0, 1.0, 0.0, 1.0]);
+        for test in &tests {
+            let color = match test.distance {
+                Some(_) => [0.0, 1.0, 0.0, 1.0],
+                None => [1.0, 0.0, 0.0, 1.0],
+            };
+            self.picking_lines.aabb(test.min, test.max, color);
+        }
+        match self.the_scene.raycast(origin, direction) {
+            Some((node, distance)) => println!("Picked {:?} at {}", node, distance),
+            None => println!("Picked nothing, {} boxes tested", tests.len()),
+        }
+    }
+
+    /// The scene's file and the tool that exported it.
+    fn window_title(&self) -> String {
+        let memory = self.the_scene.gpu_memory_estimate();
+        let counts = format!(
+            "{} triangles, {} vertices, {:.1} MiB ({:.1} MiB in {} textures)",
+            self.the_scene.triangle_count(),
+            self.the_scene.vertex_count(),
+            memory.total_bytes() as f64 / (1024.0 * 1024.0),
+            memory.texture_bytes as f64 / (1024.0 * 1024.0),
+            memory.texture_count
+        );
+        match &self.the_scene.asset.generator {
+            Some(generator) => format!(
+                "a.yude - {} ({}) - {}",
+                self.the_scene_file_name, generator, counts
+            ),
+            None => format!("a.yude - {} - {}", self.the_scene_file_name, counts),
+        }
+    }
+
     fn update(&mut self, delta: Duration) {
         let mov = Vec2::from(self.movement) * delta.as_secs_f32();
+        self.previous_camera = self.camera.clone();
         self.camera.drive(mov);
+        self.sparks.update(delta.as_secs_f32());
     }
 
     fn render(&mut self, window_dimensions: (i32, i32)) {
+        let screen_size = self.graphics.screen_size();
         let mut frame = self.graphics.get_current_frame();
 
-        let perspective = glam::Mat4::perspective_rh_gl(
-            std::f32::consts::PI / 3.0,
-            window_dimensions.0 as f32 / window_dimensions.1 as f32,
-            0.1,
-            1024.0,
-        );
+        let camera = self
+            .camera
+            .interpolated(&self.previous_camera, self.interpolation);
+        let (width, height) = (screen_size.0 as f32, screen_size.1 as f32);
+        let views = match &self.pinned_camera {
+            None => vec![(camera, [0.0, 0.0, width, height])],
+            Some(pinned) => vec![
+                (camera, [0.0, 0.0, width / 2.0, height]),
+                (pinned.clone(), [width / 2.0, 0.0, width / 2.0, height]),
+            ],
+        };
 
-        let view = self.camera.view();
+        let aspect_ratio =
+            window_dimensions.0 as f32 / window_dimensions.1 as f32 / views.len() as f32;
+        let bounds = self.the_scene.bounding_sphere();
 
         let text_material = graphics::Material {
             base_diffuse_color: [0.0, 0.0, 0.0, 1.0],
             diffuse: Some(self.test_font_texture.clone()),
             normal: None,
+            orm: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            occlusion_strength: 1.0,
             shaded: false,
+            alpha_blend: false,
+            transmission_factor: 0.0,
+            transmission_texture: None,
+            ior: 1.5,
+            clearcoat_factor: 0.0,
+            clearcoat_roughness_factor: 0.0,
+            clearcoat_texture: None,
+            clearcoat_roughness_texture: None,
+            specular_factor: 1.0,
+            specular_color_factor: [1.0; 3],
+            specular_texture: None,
+            specular_color_texture: None,
+            sampler: None,
+            depth_bias: None,
+            name: None,
+            extras: None,
         };
 
-        {
-            let mut pass = frame.begin_render_pass();
+        self.render_stats = graphics::RenderStats::default();
+        for (i, (camera, viewport)) in views.iter().enumerate() {
+            if i > 0 {
+                // the uniforms written for the previous view are only read
+                // once submitted, writing them again would overwrite them
+                frame.flush();
+            }
+            let view = camera.view();
+            let (near, far) = match bounds {
+                Some(bounds) => camera.clip_planes(bounds, DEFAULT_DEPTH_RATIO),
+                None => (0.1, 1024.0),
+            };
+            let perspective =
+                glam::Mat4::perspective_rh_gl(std::f32::consts::PI / 3.0, aspect_ratio, near, far);
+
+            let main_pass = MainPass {
+                scene: &self.the_scene,
+                skin_visualization: if self.rendering_skin {
+                    Some((&self.the_scene_skin_visualization, self.visualization_depth))
+                } else {
+                    None
+                },
+                label: (&self.test_font_uniform_buffer, &text_material),
+                sparks: &self.sparks,
+                spark_texture: &self.spark_texture,
+                perspective,
+                view,
+                camera_position: camera.transform().position(),
+                viewport: *viewport,
+            };
+            let mut graph = RenderGraph::new();
+            graph.add(&main_pass);
+            let stats = graph.execute(&mut frame);
+            self.render_stats.draw_calls += stats.draw_calls;
+            self.render_stats.state_changes += stats.state_changes;
+
+            if !self.picking_lines.is_empty() {
+                let mut pass = frame.begin_render_pass(PassOps::default());
+                pass.set_viewport(*viewport);
+                pass.draw_debug_lines(&self.picking_lines, perspective, view);
+                let stats = pass.stats();
+                self.render_stats.draw_calls += stats.draw_calls;
+                self.render_stats.state_changes += stats.state_changes;
+            }
 
             if !self.rendering_skin {
-                self.the_scene.render(&mut pass, perspective, view);
-                let translation = Vec3::new(-1.0, -1.0, 0.0);
-                pass.render_billboard(
-                    &self.test_font_uniform_buffer,
-                    &text_material,
+                // a glowing spot thrown at the scene from the front
+                let mut pass = frame.begin_decal_pass();
+                pass.set_viewport(*viewport);
+                pass.draw_decal(
+                    &self.decal,
+                    &self.spark_texture,
+                    Mat4::from_scale(Vec3::new(3.0, 3.0, 20.0)),
+                    [0.2, 0.8, 4.0, 1.0],
                     perspective,
                     view,
-                    translation,
-                    self.camera.transform().position(),
                 );
-            } else {
-                for (ub, name, scene, depth) in &self.the_scene_skin_visualization {
-                    if self.visualization_depth >= *depth {
-                        scene.render(&mut pass, perspective, view);
-
-                        let s = scene.transform.scale().y;
-                        let pos = scene.transform.position() + Vec3::new(0.0, s * 2.0, 0.0);
-
-                        pass.render_billboard(
-                            ub,
-                            &name,
-                            perspective,
-                            view,
-                            pos,
-                            self.camera.transform().position(),
-                        );
-                    }
+                let stats = pass.stats();
+                self.render_stats.draw_calls += stats.draw_calls;
+                self.render_stats.state_changes += stats.state_changes;
+            }
+            if let Some(mut pass) = frame.begin_transparent_pass() {
+                pass.set_viewport(*viewport);
+                if !self.rendering_skin {
+                    self.the_scene.render(&mut pass, perspective, view);
                 }
-            };
+                let stats = pass.stats();
+                self.render_stats.draw_calls += stats.draw_calls;
+                self.render_stats.state_changes += stats.state_changes;
+            }
+        }
+        {
+            let mut pass = frame.begin_overlay_pass();
+            self.crosshair.render(&mut pass, screen_size);
         }
         frame.submit();
     }
 }
 
 fn main() {
+    // `--info <file>` prints the structure of a glTF file instead of opening it
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("--info") {
+        let file_name = args.next().expect("usage: ayude --info <file>");
+        match import_gltf::describe(&file_name) {
+            Ok(description) => print!("{}", description),
+            Err(error) => eprintln!("error: {}", error),
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("a.yude")
@@ -265,11 +763,13 @@ fn main() {
 }
 
 async fn async_main(event_loop: EventLoop<()>, window: Arc<Window>) {
-    let renderer = GraphicsContext::new(&window).await;
+    let renderer = GraphicsContext::new(&window, wgpu::PresentMode::Fifo).await;
 
     let mut game = World::new(renderer);
+    window.set_title(&game.window_title());
 
     let mut previous_frame_time = Instant::now();
+    let mut accumulated_time = Duration::ZERO;
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -282,13 +782,30 @@ async fn async_main(event_loop: EventLoop<()>, window: Arc<Window>) {
                 WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                 }
+                WindowEvent::Focused(focused) => {
+                    game.focused = focused;
+                    // keys released while away would never be seen
+                    game.movement = [0.0, 0.0];
+                    // the time away shouldn't show up as one long frame
+                    previous_frame_time = Instant::now();
+                    let _ = window.set_cursor_grab(focused);
+                    window.set_cursor_visible(!focused);
+                }
                 _ => return,
             },
+            // device events arrive even when the window isn't focused
+            Event::DeviceEvent { .. } if !game.focused => return,
             Event::DeviceEvent { event, .. } => match event {
                 DeviceEvent::MouseMotion { delta } => {
                     game.camera
                         .rotate(Vec2::new(delta.0 as f32, delta.1 as f32) / 150.0);
                 }
+                DeviceEvent::Button {
+                    state: ElementState::Pressed,
+                    ..
+                } => {
+                    game.toggle_picking_debug();
+                }
                 DeviceEvent::Key(input) => match input.virtual_keycode {
                     Some(VirtualKeyCode::W) => {
                         if input.state == ElementState::Pressed {
@@ -321,6 +838,106 @@ async fn async_main(event_loop: EventLoop<()>, window: Arc<Window>) {
                     Some(VirtualKeyCode::Tab) if input.state == ElementState::Pressed => {
                         game.rendering_skin = !game.rendering_skin;
                     }
+                    Some(VirtualKeyCode::F) if input.state == ElementState::Pressed => {
+                        println!(
+                            "{} draw calls, {} state changes",
+                            game.render_stats.draw_calls, game.render_stats.state_changes
+                        );
+                    }
+                    Some(VirtualKeyCode::X) if input.state == ElementState::Pressed => {
+                        game.crosshair.visible = !game.crosshair.visible;
+                    }
+                    Some(VirtualKeyCode::B) if input.state == ElementState::Pressed => {
+                        game.pinned_camera = match game.pinned_camera {
+                            None => Some(game.camera.clone()),
+                            Some(_) => None,
+                        };
+                    }
+                    Some(VirtualKeyCode::M) if input.state == ElementState::Pressed => {
+                        // moves the reflection probe to the camera and captures
+                        // the scene from there
+                        let position = game.camera.transform().position();
+                        game.graphics.set_reflection_probe(Some(position));
+                        game.graphics.update_reflection_probe(&game.the_scene);
+                    }
+                    Some(VirtualKeyCode::C) if input.state == ElementState::Pressed => {
+                        let enabled = game.graphics.checker_untextured();
+                        game.graphics.set_checker_untextured(!enabled);
+                    }
+                    Some(VirtualKeyCode::T) if input.state == ElementState::Pressed => {
+                        let tonemap = match game.graphics.tonemap() {
+                            Tonemap::Reinhard => Tonemap::Aces,
+                            Tonemap::Aces => Tonemap::Reinhard,
+                        };
+                        game.graphics.set_tonemap(tonemap);
+                    }
+                    Some(VirtualKeyCode::G) if input.state == ElementState::Pressed => {
+                        let enabled = game.graphics.fxaa();
+                        game.graphics.set_fxaa(!enabled);
+                    }
+                    Some(VirtualKeyCode::V) if input.state == ElementState::Pressed => {
+                        let present_mode = match game.graphics.present_mode() {
+                            wgpu::PresentMode::Fifo => wgpu::PresentMode::Immediate,
+                            _ => wgpu::PresentMode::Fifo,
+                        };
+                        game.graphics.set_present_mode(present_mode);
+                    }
+                    Some(VirtualKeyCode::R) if input.state == ElementState::Pressed => {
+                        let dynamic_resolution = match game.graphics.dynamic_resolution() {
+                            Some(_) => None,
+                            None => Some(DynamicResolution::default()),
+                        };
+                        game.graphics.set_dynamic_resolution(dynamic_resolution);
+                    }
+                    Some(VirtualKeyCode::Z) if input.state == ElementState::Pressed => {
+                        if !game.graphics.supports_wireframe_overlay() {
+                            eprintln!("Wireframe overlay isn't supported by this adapter");
+                        }
+                        let color = match game.graphics.wireframe_overlay() {
+                            Some(_) => None,
+                            None => Some([0.0, 0.0, 0.0, 1.0]),
+                        };
+                        game.graphics.set_wireframe_overlay(color);
+                    }
+                    Some(VirtualKeyCode::P) if input.state == ElementState::Pressed => {
+                        let thumbnail = game.graphics.render_thumbnail(
+                            &game.the_scene,
+                            256,
+                            [0.1, 0.1, 0.1, 1.0],
+                        );
+                        match thumbnail.save("thumbnail.png") {
+                            Ok(()) => println!("Saved thumbnail.png"),
+                            Err(error) => eprintln!("Failed to save thumbnail.png: {}", error),
+                        }
+                    }
+                    Some(VirtualKeyCode::N) if input.state == ElementState::Pressed => {
+                        let debug_view = match game.graphics.debug_view() {
+                            DebugView::Shaded => DebugView::Normals,
+                            DebugView::Normals => DebugView::TexCoords,
+                            DebugView::TexCoords => DebugView::Overdraw,
+                            DebugView::Overdraw => DebugView::Shaded,
+                        };
+                        game.graphics.set_debug_view(debug_view);
+                    }
+                    Some(VirtualKeyCode::O) if input.state == ElementState::Pressed => {
+                        let enabled = game.graphics.order_independent_transparency();
+                        game.graphics.set_order_independent_transparency(!enabled);
+                    }
+                    Some(VirtualKeyCode::E) if input.state == ElementState::Pressed => {
+                        let auto_exposure = match game.graphics.auto_exposure() {
+                            Some(_) => None,
+                            None => Some(AutoExposure::default()),
+                        };
+                        game.graphics.set_auto_exposure(auto_exposure);
+                    }
+                    Some(VirtualKeyCode::Up) if input.state == ElementState::Pressed => {
+                        let exposure = game.graphics.exposure() * 1.25;
+                        game.graphics.set_exposure(exposure);
+                    }
+                    Some(VirtualKeyCode::Down) if input.state == ElementState::Pressed => {
+                        let exposure = game.graphics.exposure() / 1.25;
+                        game.graphics.set_exposure(exposure);
+                    }
                     Some(VirtualKeyCode::Right) if input.state == ElementState::Pressed => {
                         game.visualization_depth += 1;
                     }
@@ -334,9 +951,27 @@ async fn async_main(event_loop: EventLoop<()>, window: Arc<Window>) {
                 _ => return,
             },
             Event::MainEventsCleared => {
-                let delta = previous_frame_time.elapsed();
+                if game.reload_scene_if_changed() {
+                    window.set_title(&game.window_title());
+                }
+                if !game.focused && game.pause_when_unfocused {
+                    window.request_redraw();
+                    return;
+                }
+                let delta = previous_frame_time.elapsed().min(MAX_FRAME_DELTA);
                 previous_frame_time = Instant::now();
-                game.update(delta);
+                game.graphics.update_resolution_scale(delta);
+                game.graphics.update_exposure(delta);
+                for (_, error) in game.graphics.update_streamed_textures() {
+                    eprintln!("warning: {}", error);
+                }
+
+                accumulated_time += delta;
+                while accumulated_time >= FIXED_TIMESTEP {
+                    game.update(FIXED_TIMESTEP);
+                    accumulated_time -= FIXED_TIMESTEP;
+                }
+                game.interpolation = accumulated_time.as_secs_f32() / FIXED_TIMESTEP.as_secs_f32();
                 window.request_redraw();
             }
             Event::RedrawRequested(..) => {
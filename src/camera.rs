@@ -1,8 +1,12 @@
-use glam::{EulerRot, Mat4, Vec2, Vec3};
+use glam::{EulerRot, Mat4, Quat, Vec2, Vec3};
 
 use crate::{transform::{GLOBAL_UP, Transform}};
 
 
+/// Far to near plane ratio for `Camera::clip_planes` that keeps z-fighting
+/// away with a 24 bit depth buffer.
+pub const DEFAULT_DEPTH_RATIO: f32 = 10_000.0;
+
 #[derive(Debug, Clone)]
 pub struct Camera {
     position: Vec3,
@@ -11,6 +15,9 @@ pub struct Camera {
     pitch: f32, // radians
 
     speed: f32,
+
+    /// World up, which yaw turns around.
+    up: Vec3,
 }
 
 impl Camera {
@@ -20,13 +27,25 @@ impl Camera {
             yaw,
             pitch,
             speed: 100.0,
+            up: GLOBAL_UP.into(),
         }
     }
 
     pub fn transform(&self) -> Transform {
         let rot = Mat4::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0);
+        // yaw and pitch are relative to +Y up, turned to the actual up
+        let basis = Mat4::from_quat(Quat::from_rotation_arc(GLOBAL_UP.into(), self.up));
         let tr = Mat4::from_translation(self.position);
-        Transform::from(tr * rot)
+        Transform::from(tr * basis * rot)
+    }
+
+    /// Sets the world up axis, keeping yaw and pitch relative to it.
+    pub fn set_up(&mut self, up: Vec3) {
+        self.up = up.normalize();
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.up
     }
 
     // movement.x is sideways movement, movement.y is forward/back
@@ -36,11 +55,34 @@ impl Camera {
         self.position += xform.forward() * movement.y * self.speed;
     }
 
+    /// This camera moved back toward the position of `previous`, with `t` 0
+    /// giving `previous`'s position and 1 this one's. Rotation is kept as is.
+    pub fn interpolated(&self, previous: &Camera, t: f32) -> Camera {
+        Camera {
+            position: previous.position.lerp(self.position, t),
+            ..self.clone()
+        }
+    }
+
+    /// Near and far plane distances that fit the sphere `bounds`, as given by
+    /// `Scene::bounding_sphere`, from this camera's position, so small models
+    /// seen up close aren't clipped and large ones keep their depth precision.
+    /// The near plane is pulled in to keep the far plane at most
+    /// `max_depth_ratio` times farther, such as when the camera is inside the
+    /// sphere; `DEFAULT_DEPTH_RATIO` suits a 24 bit depth buffer.
+    pub fn clip_planes(&self, bounds: (Vec3, f32), max_depth_ratio: f32) -> (f32, f32) {
+        let (center, radius) = bounds;
+        let distance = self.position.distance(center);
+        let far = (distance + radius).max(f32::EPSILON);
+        let near = (distance - radius).max(far / max_depth_ratio);
+        (near, far)
+    }
+
     pub fn view(&self) -> Mat4 {
         Mat4::look_at_rh(
             self.position,
             self.position + self.transform().forward(),
-            GLOBAL_UP.into(),
+            self.up,
         )
     }
 
@@ -1,9 +1,14 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
+/// Items referred to by ids that stay valid as other items are added and
+/// removed. Ids are never reused, so the id of a removed item doesn't refer to
+/// anything from then on instead of to whatever took its place. Items are
+/// iterated in the order their ids were handed out, the same every run.
 pub struct Catalog<T> {
-    items: HashMap<Id<T>, T>,
+    items: BTreeMap<Id<T>, T>,
     counter: u64,
 }
 
@@ -20,7 +25,7 @@ impl<T> std::iter::FromIterator<T> for Catalog<T> {
 impl<T> Catalog<T> {
     pub fn new() -> Self {
         Self {
-            items: HashMap::new(),
+            items: BTreeMap::new(),
             counter: 0,
         }
     }
@@ -38,25 +43,86 @@ impl<T> Catalog<T> {
     }
 
     pub fn add(&mut self, it: T) -> Id<T> {
-        let id = Id(self.counter, PhantomData);
+        let id = self.reserve();
         self.items.insert(id, it);
+        id
+    }
+
+    /// Hands out an id without an item, to be filled in later with `insert`.
+    /// Lets items refer to each other before all of them exist.
+    pub fn reserve(&mut self) -> Id<T> {
+        let id = Id(self.counter, PhantomData);
         self.counter += 1;
         id
     }
 
-    pub fn iter(&self) -> std::collections::hash_map::Values<'_, Id<T>, T> {
+    /// Puts `it` at an id from `reserve` (or replaces the item at an id from
+    /// `add`), returning what was there.
+    pub fn insert(&mut self, id: Id<T>, it: T) -> Option<T> {
+        debug_assert!(id.0 < self.counter, "id was not handed out by this catalog");
+        self.items.insert(id, it)
+    }
+
+    pub fn remove(&mut self, id: Id<T>) -> Option<T> {
+        self.items.remove(&id)
+    }
+
+    pub fn contains(&self, id: Id<T>) -> bool {
+        self.items.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// A catalog with the same ids, each mapped to `f` of its item.
+    pub fn map(&self, mut f: impl FnMut(&T) -> T) -> Self {
+        Self {
+            items: self.items.iter().map(|(id, it)| (*id, f(it))).collect(),
+            counter: self.counter,
+        }
+    }
+
+    pub fn iter(&self) -> std::collections::btree_map::Values<'_, Id<T>, T> {
         self.items.values()
     }
 
-    pub fn iter_ids(&mut self) -> std::collections::hash_map::Keys<'_, Id<T>, T> {
+    pub fn iter_mut(&mut self) -> std::collections::btree_map::ValuesMut<'_, Id<T>, T> {
+        self.items.values_mut()
+    }
+
+    pub fn iter_ids(&self) -> std::collections::btree_map::Keys<'_, Id<T>, T> {
         self.items.keys()
     }
 
-    pub fn iter_with_id(&mut self) -> std::collections::hash_map::IterMut<'_, Id<T>, T> {
+    pub fn iter_with_id(&self) -> std::collections::btree_map::Iter<'_, Id<T>, T> {
+        self.items.iter()
+    }
+
+    pub fn iter_with_id_mut(&mut self) -> std::collections::btree_map::IterMut<'_, Id<T>, T> {
         self.items.iter_mut()
     }
 }
 
+impl<T> IntoIterator for Catalog<T> {
+    type Item = (Id<T>, T);
+    type IntoIter = std::collections::btree_map::IntoIter<Id<T>, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Catalog<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.items.iter()).finish()
+    }
+}
+
 pub struct Id<T>(u64, PhantomData<T>);
 
 impl<T> Clone for Id<T> {
@@ -65,12 +131,27 @@ impl<T> Clone for Id<T> {
     }
 }
 impl<T> Copy for Id<T> {}
+impl<T> fmt::Debug for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
 impl<T> PartialEq for Id<T> {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
     }
 }
 impl<T> Eq for Id<T> {}
+impl<T> PartialOrd for Id<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T> Ord for Id<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
 impl<T> Hash for Id<T> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.0.hash(state);
@@ -2,4 +2,8 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AyudeError {
+    #[error("Node {0:?} is not in the scene")]
+    NodeNotFound(crate::NodeId),
+    #[error("Node {0:?} can't become a child of node {1:?}, which is itself or one of its descendants")]
+    CyclicParent(crate::NodeId, crate::NodeId),
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+use crate::graphics::{GraphicsContext, Mesh, ParticleBuffer, ParticleInstance, Pass, Texture};
+
+/// Transforms placing instances at random points on the surface of `mesh`,
+/// about `density` of them per square unit of its model space area, each
+/// with its y axis along the normal of the triangle it's on and a random
+/// rotation around it. The same `seed` always gives the same points.
+pub fn scatter(mesh: &Mesh, density: f32, seed: u32) -> Vec<Mat4> {
+    let positions = mesh.positions();
+    let triangles = mesh
+        .indices()
+        .chunks_exact(3)
+        .map(|it| {
+            let [a, b, c] = [it[0], it[1], it[2]].map(|i| positions[i as usize]);
+            (a, b, c)
+        })
+        .collect::<Vec<_>>();
+
+    // running total of the areas, to pick triangles proportionally to them
+    let mut area = 0.0;
+    let cumulative_areas = triangles
+        .iter()
+        .map(|(a, b, c)| {
+            area += (*b - *a).cross(*c - *a).length() * 0.5;
+            area
+        })
+        .collect::<Vec<_>>();
+
+    let count = (area * density).round() as usize;
+    if count == 0 {
+        return vec![];
+    }
+
+    let mut random = Random(seed.max(1));
+    (0..count)
+        .map(|_| {
+            let target = random.next() * area;
+            let index = cumulative_areas
+                .partition_point(|it| *it < target)
+                .min(triangles.len() - 1);
+            let (a, b, c) = triangles[index];
+
+            // uniform over the triangle, folding the square onto it
+            let (mut u, mut v) = (random.next(), random.next());
+            if u + v > 1.0 {
+                u = 1.0 - u;
+                v = 1.0 - v;
+            }
+            let position = a + (b - a) * u + (c - a) * v;
+
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            let tilt = if normal == Vec3::ZERO {
+                Quat::IDENTITY
+            } else {
+                Quat::from_rotation_arc(Vec3::Y, normal)
+            };
+            let yaw = Quat::from_rotation_y(random.next() * std::f32::consts::TAU);
+            Mat4::from_rotation_translation(tilt * yaw, position)
+        })
+        .collect()
+}
+
+/// Xorshift, uniformly distributed in [0, 1).
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Instances of grass or other foliage, such as the ones from `scatter`,
+/// drawn as camera-facing billboards in a single instanced draw call.
+#[derive(Debug)]
+pub struct Foliage {
+    pub instances: Vec<Mat4>,
+    /// Size in world units of an instance with a scale of 1.
+    pub size: f32,
+    pub color: Vec4,
+    buffer: ParticleBuffer,
+}
+
+impl Foliage {
+    /// Room is made for the instances given here, any added later past that
+    /// aren't drawn.
+    pub fn new(graphics: &GraphicsContext, instances: Vec<Mat4>, size: f32) -> Self {
+        Self {
+            buffer: graphics.create_particle_buffer(instances.len()),
+            instances,
+            size,
+            color: Vec4::ONE,
+        }
+    }
+
+    /// Draws the instances, with `model` placing them in the world, back to
+    /// front from the camera over whatever opaque geometry is already in
+    /// `pass`. The bottom of each billboard sits on its point, and it's
+    /// scaled by the y axis of its transform.
+    pub fn render<'a: 'pass, 'pass>(
+        &'a self,
+        pass: &'pass mut Pass<'a, 'a>,
+        model: Mat4,
+        perspective: Mat4,
+        view: Mat4,
+        texture: Option<&'a Texture>,
+    ) {
+        let camera_position = view.inverse().w_axis.truncate();
+        let camera_up = view.row(1).truncate();
+        let mut instances = self
+            .instances
+            .iter()
+            .map(|it| {
+                let transform = model * *it;
+                let size = transform.y_axis.truncate().length() * self.size;
+                let center = transform.w_axis.truncate() + camera_up * size * 0.5;
+                ParticleInstance {
+                    center_size: center.extend(size).into(),
+                    color: self.color.into(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let distance = |it: &ParticleInstance| {
+            Vec4::from(it.center_size)
+                .truncate()
+                .distance_squared(camera_position)
+        };
+        instances.sort_by(|a, b| {
+            distance(b)
+                .partial_cmp(&distance(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        pass.render_particles(&self.buffer, &instances, texture, perspective, view);
+    }
+}
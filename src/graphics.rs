@@ -1,20 +1,263 @@
-use std::{borrow::Cow, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{hash_map::DefaultHasher, HashMap},
+    future::Future,
+    hash::{Hash, Hasher},
+    pin::Pin,
+    rc::{Rc, Weak},
+    sync::mpsc::{Receiver, TryRecvError},
+    time::Duration,
+};
 
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Quat, Vec3, Vec4};
 
 use once_cell::sync::OnceCell;
 use wgpu::util::DeviceExt;
 
 use bytemuck::{Pod, Zeroable};
 
-use crate::transform::GLOBAL_UP;
+use crate::{transform::GLOBAL_UP, NodeId, Scene};
 
 #[derive(Debug, Clone)]
 pub struct Material {
     pub normal: Option<Texture>,
     pub diffuse: Option<Texture>,
     pub base_diffuse_color: [f32; 4],
+    pub orm: Option<OrmTexture>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub occlusion_strength: f32,
+    /// Whether lighting is applied; false for `KHR_materials_unlit` materials
+    /// and billboards.
     pub shaded: bool,
+    /// Whether the material is see-through (glTF's `BLEND` alpha mode). Only
+    /// makes a difference with order-independent transparency enabled.
+    pub alpha_blend: bool,
+    /// How much light goes through the surface, from `KHR_materials_transmission`.
+    /// Above 0 the material is drawn like an alpha blended one, with its
+    /// opacity reduced by this much; there's no refraction yet.
+    pub transmission_factor: f32,
+    /// Multiplies `transmission_factor` in its red channel. Kept from the
+    /// import but not sampled yet, the mesh pipeline has no bind group left
+    /// for it.
+    pub transmission_texture: Option<Texture>,
+    /// Index of refraction, from `KHR_materials_ior`, 1.5 by default like in
+    /// the spec. Sets how much of a transmissive surface reflects light
+    /// instead, more so at grazing angles.
+    pub ior: f32,
+    /// Strength of a second, glossy specular layer on top, from
+    /// `KHR_materials_clearcoat`. 0 leaves the material as it is.
+    pub clearcoat_factor: f32,
+    pub clearcoat_roughness_factor: f32,
+    /// Multiply `clearcoat_factor` in red and `clearcoat_roughness_factor` in
+    /// green. Kept from the import but not sampled yet, like
+    /// `transmission_texture`.
+    pub clearcoat_texture: Option<Texture>,
+    pub clearcoat_roughness_texture: Option<Texture>,
+    /// Strength and color of the specular reflection of non-metallic
+    /// surfaces, from `KHR_materials_specular`. 1 and white by default like
+    /// in the spec, which leave the material as it is.
+    pub specular_factor: f32,
+    pub specular_color_factor: [f32; 3],
+    /// Multiply `specular_factor` in alpha and `specular_color_factor` in
+    /// rgb. Kept from the import but not sampled yet, like
+    /// `transmission_texture`.
+    pub specular_texture: Option<Texture>,
+    pub specular_color_texture: Option<Texture>,
+    /// Filtering all the textures above were switched to by `set_sampler`,
+    /// or `None` if each keeps the one it was created with (for imported
+    /// textures, their glTF sampler).
+    pub sampler: Option<SamplerOptions>,
+    /// Drawn with no depth bias if `None`.
+    pub depth_bias: Option<DepthBias>,
+    pub name: Option<String>,
+    /// Application specific data from the glTF `extras` of the material.
+    pub extras: Option<serde_json::Value>,
+}
+
+/// Materials compare textures by identity and colors by bit pattern, so two
+/// materials are only equal if they would bind the exact same resources. A
+/// consequence is that a NaN color is equal to itself (and hashes the same)
+/// only when the NaN payloads match, while `0.0` and `-0.0` are different.
+/// `name` and `extras` don't bind anything and are ignored.
+impl PartialEq for Material {
+    fn eq(&self, other: &Self) -> bool {
+        self.normal == other.normal
+            && self.diffuse == other.diffuse
+            && color_bits(&self.base_diffuse_color) == color_bits(&other.base_diffuse_color)
+            && self.orm == other.orm
+            && self.metallic_factor.to_bits() == other.metallic_factor.to_bits()
+            && self.roughness_factor.to_bits() == other.roughness_factor.to_bits()
+            && self.occlusion_strength.to_bits() == other.occlusion_strength.to_bits()
+            && self.shaded == other.shaded
+            && self.alpha_blend == other.alpha_blend
+            && self.transmission_factor.to_bits() == other.transmission_factor.to_bits()
+            && self.transmission_texture == other.transmission_texture
+            && self.ior.to_bits() == other.ior.to_bits()
+            && self.clearcoat_factor.to_bits() == other.clearcoat_factor.to_bits()
+            && self.clearcoat_roughness_factor.to_bits()
+                == other.clearcoat_roughness_factor.to_bits()
+            && self.clearcoat_texture == other.clearcoat_texture
+            && self.clearcoat_roughness_texture == other.clearcoat_roughness_texture
+            && self.specular_factor.to_bits() == other.specular_factor.to_bits()
+            && self.specular_color_factor.map(f32::to_bits)
+                == other.specular_color_factor.map(f32::to_bits)
+            && self.specular_texture == other.specular_texture
+            && self.specular_color_texture == other.specular_color_texture
+            && self.sampler == other.sampler
+            && self.depth_bias == other.depth_bias
+    }
+}
+
+impl Eq for Material {}
+
+impl Hash for Material {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normal.hash(state);
+        self.diffuse.hash(state);
+        color_bits(&self.base_diffuse_color).hash(state);
+        self.orm.hash(state);
+        self.metallic_factor.to_bits().hash(state);
+        self.roughness_factor.to_bits().hash(state);
+        self.occlusion_strength.to_bits().hash(state);
+        self.shaded.hash(state);
+        self.alpha_blend.hash(state);
+        self.transmission_factor.to_bits().hash(state);
+        self.transmission_texture.hash(state);
+        self.ior.to_bits().hash(state);
+        self.clearcoat_factor.to_bits().hash(state);
+        self.clearcoat_roughness_factor.to_bits().hash(state);
+        self.clearcoat_texture.hash(state);
+        self.clearcoat_roughness_texture.hash(state);
+        self.specular_factor.to_bits().hash(state);
+        self.specular_color_factor.map(f32::to_bits).hash(state);
+        self.specular_texture.hash(state);
+        self.specular_color_texture.hash(state);
+        self.sampler.hash(state);
+        self.depth_bias.hash(state);
+    }
+}
+
+/// A depth offset with the pipelines that apply it, from
+/// `GraphicsContext::create_depth_bias`. Cheap to clone and share between
+/// materials.
+#[derive(Debug, Clone)]
+pub struct DepthBias {
+    /// opaque pipeline, transparent pipeline
+    inner: Rc<(wgpu::RenderPipeline, wgpu::RenderPipeline)>,
+    state: wgpu::DepthBiasState,
+}
+
+impl DepthBias {
+    pub fn constant(&self) -> i32 {
+        self.state.constant
+    }
+
+    pub fn slope_scale(&self) -> f32 {
+        self.state.slope_scale
+    }
+
+    pub fn clamp(&self) -> f32 {
+        self.state.clamp
+    }
+}
+
+/// Depth biases are equal when they share the same pipelines.
+impl PartialEq for DepthBias {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for DepthBias {}
+
+impl Hash for DepthBias {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.inner).hash(state);
+    }
+}
+
+/// A texture packing occlusion in R, roughness in G and metallic in B, as
+/// glTF lays them out. Either half may be missing, in which case the shader
+/// ignores the corresponding channels.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OrmTexture {
+    pub texture: Texture,
+    pub has_occlusion: bool,
+    pub has_metallic_roughness: bool,
+}
+
+impl Material {
+    /// Orders materials so that ones using the same pipeline, and then
+    /// binding the same textures, end up next to each other.
+    pub fn sort_key(&self) -> (usize, usize, usize, usize) {
+        let texture_key = |texture: Option<&Texture>| match texture {
+            Some(texture) => texture.handle().0 + 1,
+            None => 0,
+        };
+        let pipeline_key = match &self.depth_bias {
+            Some(bias) => Rc::as_ptr(&bias.inner) as usize,
+            None => 0,
+        };
+        (
+            pipeline_key,
+            texture_key(self.diffuse.as_ref()),
+            texture_key(self.normal.as_ref()),
+            texture_key(self.orm.as_ref().map(|it| &it.texture)),
+        )
+    }
+
+    /// Switches every texture of the material to the filters of `options`.
+    /// Each texture keeps its own wrapping, and gets a bind group of its own,
+    /// so materials sampling the same texels differently are drawn apart.
+    /// Every texture the material has, in no particular order.
+    pub fn textures(&self) -> impl Iterator<Item = &Texture> {
+        let textures = vec![
+            self.diffuse.as_ref(),
+            self.normal.as_ref(),
+            self.orm.as_ref().map(|it| &it.texture),
+            self.transmission_texture.as_ref(),
+            self.clearcoat_texture.as_ref(),
+            self.clearcoat_roughness_texture.as_ref(),
+            self.specular_texture.as_ref(),
+            self.specular_color_texture.as_ref(),
+        ];
+        textures.into_iter().flatten()
+    }
+
+    pub fn set_sampler(&mut self, graphics: &GraphicsContext, options: SamplerOptions) {
+        let textures = vec![
+            self.diffuse.as_mut(),
+            self.normal.as_mut(),
+            self.orm.as_mut().map(|it| &mut it.texture),
+            self.transmission_texture.as_mut(),
+            self.clearcoat_texture.as_mut(),
+            self.clearcoat_roughness_texture.as_mut(),
+            self.specular_texture.as_mut(),
+            self.specular_color_texture.as_mut(),
+        ];
+        for texture in textures.into_iter().flatten() {
+            let options = options.with_wrapping_of(texture.sampler_options());
+            *texture = texture.with_sampler(graphics, options);
+        }
+        self.sampler = Some(options);
+    }
+
+    /// Whether the material is see-through, either alpha blended or
+    /// transmitting light.
+    pub fn blended(&self) -> bool {
+        self.alpha_blend || self.transmission_factor > 0.0
+    }
+}
+
+fn color_bits(color: &[f32; 4]) -> [u32; 4] {
+    [
+        color[0].to_bits(),
+        color[1].to_bits(),
+        color[2].to_bits(),
+        color[3].to_bits(),
+    ]
 }
 
 pub struct GraphicsContext {
@@ -24,17 +267,128 @@ pub struct GraphicsContext {
     swap_chain_descriptor: wgpu::SwapChainDescriptor,
     queue: wgpu::Queue, // todo! not pub
     pipeline: wgpu::RenderPipeline,
+    /// Kept to create pipelines with a different depth bias later on.
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    screen_pipeline: wgpu::RenderPipeline,
+    particle_pipeline: wgpu::RenderPipeline,
+    points_pipeline: wgpu::RenderPipeline,
+    decal_pipeline: wgpu::RenderPipeline,
+    outline_mask_pipeline: wgpu::RenderPipeline,
+    outline_pipeline: wgpu::RenderPipeline,
+    /// `None` where the adapter has no pipeline statistics queries.
+    occlusion_pipeline: Option<wgpu::RenderPipeline>,
+    debug_lines_pipeline: wgpu::RenderPipeline,
+    tonemap_pipeline: wgpu::RenderPipeline,
+    fxaa_pipeline: wgpu::RenderPipeline,
+    oit_pipeline: wgpu::RenderPipeline,
+    /// `None` where the adapter can't draw polygons as lines.
+    wireframe_pipeline: Option<wgpu::RenderPipeline>,
+    overdraw_pipeline: wgpu::RenderPipeline,
+    oit_resolve_pipeline: wgpu::RenderPipeline,
+    luminance_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
     uniform_bind_group_layout: wgpu::BindGroupLayout,
+    screen_bind_group_layout: wgpu::BindGroupLayout,
     textures_bind_group_layout: wgpu::BindGroupLayout,
-    default_texture: OnceCell<Texture>,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    empty_morph_buffer: wgpu::Buffer,
+    buffer_pool: Rc<BufferPool>,
+    empty_texture: OnceCell<Texture>,
+    empty_normal_texture: OnceCell<Texture>,
+    checker_texture: OnceCell<Texture>,
+    checker_untextured: bool,
     quad_mesh: OnceCell<Mesh>,
+    cube_mesh: OnceCell<Mesh>,
     depth_view: wgpu::TextureView, // todo! not pub
+    /// The depth buffer as a texture, for decals.
+    depth_bind_group: wgpu::BindGroup,
+    hdr_target: RenderTarget,
+    ldr_target: RenderTarget,
+    /// Accumulation and revealage targets, only allocated while
+    /// order-independent transparency is enabled.
+    oit_targets: Option<(RenderTarget, RenderTarget)>,
+    post_buffer: wgpu::Buffer,
+    post_bind_group: wgpu::BindGroup,
+    exposure: f32,
+    auto_exposure: Option<AutoExposure>,
+    /// Only allocated while auto exposure is enabled.
+    luminance_chain: Option<LuminanceChain>,
+    ambient_light: AmbientLight,
+    fog: Option<Fog>,
+    tonemap: Tonemap,
+    fxaa: bool,
+    debug_view: DebugView,
+    world_up: Vec3,
+    wireframe_overlay: Option<[f32; 4]>,
+    point_size: f32,
+    resolution_scale: f32,
+    dynamic_resolution: Option<DynamicResolution>,
+    /// Cap on the anisotropy of new samplers, see `set_max_anisotropy`.
+    max_anisotropy: u8,
+    /// Highest anisotropy the adapter filters with, see `anisotropy_limit`.
+    anisotropy_limit: u8,
+    /// What every `TextureHandle` points at, see `replace_texture`.
+    textures: Rc<TextureStore>,
+    /// Waiting for their texels, see `create_streamed_texture`.
+    streamed_textures: RefCell<Vec<StreamedTexture>>,
+    /// Cube map of the scene around `reflection_probe_position`, see
+    /// `update_reflection_probe`.
+    reflection_probe: wgpu::Texture,
+    reflection_probe_view: wgpu::TextureView,
+    reflection_probe_sampler: wgpu::Sampler,
+    reflection_probe_position: Option<Vec3>,
 }
 
 impl GraphicsContext {
-    const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+    /// With stencil for outlines, see `Pass::draw_outline`.
+    const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+    /// Format the scene is rendered in before being tonemapped to the swap
+    /// chain, so lighting can go above 1.
+    const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+    /// Filterable, so each level is averaged from the one above by sampling.
+    const LUMINANCE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+    /// Size of the largest level of the luminance chain, which the scene is
+    /// sampled down to regardless of the window size.
+    const LUMINANCE_SIZE: u32 = 256;
+    /// Size of each face of the reflection probe's cube map.
+    const REFLECTION_PROBE_SIZE: u32 = 128;
+    const MESH_VERTEX_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x4,
+                offset: 0,
+                shader_location: 0,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x3,
+                offset: 4 * 4,
+                shader_location: 1,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 4 * 7,
+                shader_location: 2,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x4,
+                offset: 4 * 9,
+                shader_location: 3,
+            },
+        ],
+    };
+    /// Highest anisotropy level wgpu accepts; adapters without anisotropic
+    /// filtering silently ignore the clamp. See `anisotropy_limit` for the
+    /// level of the adapter in use.
+    pub const MAX_ANISOTROPY: u8 = 16;
 
-    pub async fn new(window: &winit::window::Window) -> Self {
+    /// `present_mode` is checked by wgpu against the modes the surface
+    /// supports, falling back to `Fifo` (always available) with a logged
+    /// warning when it isn't one of them.
+    pub async fn new(window: &winit::window::Window, present_mode: wgpu::PresentMode) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::BackendBit::all());
@@ -51,11 +405,22 @@ impl GraphicsContext {
         let adapter_info = adapter.get_info();
         println!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
 
+        // wgpu doesn't report the adapter's anisotropy level, only its type;
+        // software rasterizers are the ones that filter without anisotropy
+        let anisotropy_limit = match adapter_info.device_type {
+            wgpu::DeviceType::Cpu => 1,
+            _ => Self::MAX_ANISOTROPY,
+        };
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
+                    // only needed for the wireframe overlay and occlusion
+                    // queries, which are unavailable without them
+                    features: adapter.features()
+                        & (wgpu::Features::NON_FILL_POLYGON_MODE
+                            | wgpu::Features::PIPELINE_STATISTICS_QUERY),
                     limits: wgpu::Limits::default(),
                 },
                 None,
@@ -72,7 +437,7 @@ impl GraphicsContext {
             format: swapchain_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Mailbox,
+            present_mode,
         };
 
         let swap_chain = device.create_swap_chain(&surface, &swap_chain_descriptor);
@@ -80,16 +445,49 @@ impl GraphicsContext {
         let uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // the reflection probe, here since every other group is
+                    // taken by textures
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            comparison: false,
+                            filtering: true,
+                        },
+                        count: None,
+                    },
+                ],
             });
 
         let textures_bind_group_layout =
@@ -124,6 +522,7 @@ impl GraphicsContext {
                 &uniform_bind_group_layout,
                 &textures_bind_group_layout,
                 &textures_bind_group_layout,
+                &textures_bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
@@ -134,192 +533,1103 @@ impl GraphicsContext {
             flags: wgpu::ShaderFlags::all(),
         });
 
-        let vertex_buffers = [wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 4 * 4,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 4 * 7,
-                    shader_location: 2,
-                },
-            ],
-        }];
+        let (render_pipeline, oit_pipeline) = Self::create_mesh_pipelines(
+            &device,
+            &pipeline_layout,
+            &shader,
+            wgpu::DepthBiasState::default(),
+        );
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        // the edges of meshes already drawn, pulled towards the camera so they
+        // aren't hidden by their own faces
+        let wireframe_pipeline = if device
+            .features()
+            .contains(wgpu::Features::NON_FILL_POLYGON_MODE)
+        {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            Some(
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: None,
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[Self::MESH_VERTEX_LAYOUT],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_wireframe",
+                        targets: &[Self::HDR_FORMAT.into()],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        polygon_mode: wgpu::PolygonMode::Line,
+                        ..wgpu::PrimitiveState::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: Self::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState {
+                            constant: -2,
+                            slope_scale: -1.0,
+                            clamp: 0.0,
+                        },
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                }),
+            )
+        } else {
+            None
+        };
+
+        // adds a little light for every mesh covering a pixel, ignoring
+        // depth, so the areas drawn over the most stand out
+        let overdraw_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: "vs_main",
-                buffers: &vertex_buffers,
+                buffers: &[Self::MESH_VERTEX_LAYOUT],
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
-                entry_point: "fs_main",
-                targets: &[swapchain_format.into()],
+                entry_point: "fs_overdraw",
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: Self::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: wgpu::MultisampleState::default(),
         });
 
-        let depth_texture = Self::create_depth_texture(&&swap_chain_descriptor, &device);
+        let screen_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
 
-        Self {
-            surface,
-            device,
-            swap_chain,
-            swap_chain_descriptor,
-            queue,
-            pipeline: render_pipeline,
-            uniform_bind_group_layout,
-            textures_bind_group_layout,
-            default_texture: OnceCell::new(),
-            quad_mesh: OnceCell::new(),
-            depth_view: depth_texture,
-        }
-    }
+        let screen_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&screen_bind_group_layout, &textures_bind_group_layout],
+                push_constant_ranges: &[],
+            });
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.swap_chain_descriptor.width = width;
-        self.swap_chain_descriptor.height = height;
-        self.swap_chain = self
-            .device
-            .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
-        self.depth_view = Self::create_depth_texture(&self.swap_chain_descriptor, &self.device);
-    }
+        let screen_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader/screen.wgsl"))),
+            flags: wgpu::ShaderFlags::all(),
+        });
 
-    fn create_depth_texture(
-        sc_desc: &wgpu::SwapChainDescriptor,
-        device: &wgpu::Device,
-    ) -> wgpu::TextureView {
-        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: sc_desc.width,
-                height: sc_desc.height,
-                depth_or_array_layers: 1,
+        let screen_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&screen_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &screen_shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x4,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                }],
             },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            fragment: Some(wgpu::FragmentState {
+                module: &screen_shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: swapchain_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // drawn in the overlay pass, which has no depth buffer
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+
+        let particle_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader/particle.wgsl"))),
+            flags: wgpu::ShaderFlags::all(),
         });
 
-        depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
-    }
+        // same layout as screen quads, with the quad mesh instanced per particle
+        let particle_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&screen_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &particle_shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Vertex,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x2,
+                                offset: 4 * 7,
+                                shader_location: 2,
+                            },
+                        ],
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<ParticleInstance>()
+                            as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Instance,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: 0,
+                                shader_location: 3,
+                            },
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: 4 * 4,
+                                shader_location: 4,
+                            },
+                        ],
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &particle_shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // hidden by meshes in front, without hiding each other
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        });
 
-    pub fn create_mesh(&self, vertices: &[Vertex], indices: &[u16]) -> Mesh {
-        let vertex_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        // point clouds, with the quad mesh instanced per point like particles
+        // but opaque, taking the mesh uniforms' place in the first group
+        let points_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(vertices),
-                usage: wgpu::BufferUsage::VERTEX,
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
             });
+            let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader/points.wgsl"))),
+                flags: wgpu::ShaderFlags::all(),
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[
+                        wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                            step_mode: wgpu::InputStepMode::Vertex,
+                            attributes: &[wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: 0,
+                                shader_location: 0,
+                            }],
+                        },
+                        wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<PointVertex>() as wgpu::BufferAddress,
+                            step_mode: wgpu::InputStepMode::Instance,
+                            attributes: &[
+                                wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x3,
+                                    offset: 0,
+                                    shader_location: 1,
+                                },
+                                wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x4,
+                                    offset: 4 * 3,
+                                    shader_location: 2,
+                                },
+                            ],
+                        },
+                    ],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Self::HDR_FORMAT.into()],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Self::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+            })
+        };
 
-        let index_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        let depth_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(&indices),
-                usage: wgpu::BufferUsage::INDEX,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                }],
             });
 
-        Mesh {
-            inner: (vertex_buffer, index_buffer).into(),
-            index_count: indices.len(),
-        }
-    }
+        let decal_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    &screen_bind_group_layout,
+                    &textures_bind_group_layout,
+                    &depth_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
 
-    pub fn create_uniform_buffer(&self) -> UniformBuffer {
-        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Uniform Buffer"),
-            size: std::mem::size_of::<Uniforms>() as _,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
+        let decal_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader/decal.wgsl"))),
+            flags: wgpu::ShaderFlags::all(),
         });
 
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        // blended over the scene, reading its depth instead of testing it
+        let decal_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
-            layout: &self.uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
+            layout: Some(&decal_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &decal_shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x4,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &decal_shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Front),
+                ..wgpu::PrimitiveState::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
         });
 
-        UniformBuffer { buffer, bind_group }
-    }
+        let outline_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&screen_bind_group_layout],
+                push_constant_ranges: &[],
+            });
 
-    pub fn create_texture(&self, desc: &TextureDescription) -> Texture {
-        let texture_extent = wgpu::Extent3d {
-            width: desc.width,
-            height: desc.height,
-            depth_or_array_layers: 1,
+        let outline_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader/outline.wgsl"))),
+            flags: wgpu::ShaderFlags::all(),
+        });
+
+        // marks the mesh's pixels with 1 in the stencil, without drawing
+        let outline_mask_pipeline = Self::create_outline_pipeline(
+            &device,
+            &outline_pipeline_layout,
+            &outline_shader,
+            "vs_mask",
+            wgpu::ColorWrite::empty(),
+            wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Always,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Replace,
+            },
+        );
+
+        // the grown mesh, around the marked pixels only
+        let outline_pipeline = Self::create_outline_pipeline(
+            &device,
+            &outline_pipeline_layout,
+            &outline_shader,
+            "vs_main",
+            wgpu::ColorWrite::ALL,
+            wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::NotEqual,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Keep,
+            },
+        );
+
+        // bounding boxes tested against the depth buffer without drawing
+        // anything, counting how many of their fragments pass
+        let occlusion_pipeline = if device
+            .features()
+            .contains(wgpu::Features::PIPELINE_STATISTICS_QUERY)
+        {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+            let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                    "shader/occlusion.wgsl"
+                ))),
+                flags: wgpu::ShaderFlags::all(),
+            });
+            Some(
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: None,
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[
+                            wgpu::VertexBufferLayout {
+                                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                                step_mode: wgpu::InputStepMode::Vertex,
+                                attributes: &[wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x4,
+                                    offset: 0,
+                                    shader_location: 0,
+                                }],
+                            },
+                            wgpu::VertexBufferLayout {
+                                array_stride: std::mem::size_of::<[f32; 16]>()
+                                    as wgpu::BufferAddress,
+                                step_mode: wgpu::InputStepMode::Instance,
+                                // one column of the matrix per attribute
+                                attributes: &[
+                                    wgpu::VertexAttribute {
+                                        format: wgpu::VertexFormat::Float32x4,
+                                        offset: 0,
+                                        shader_location: 1,
+                                    },
+                                    wgpu::VertexAttribute {
+                                        format: wgpu::VertexFormat::Float32x4,
+                                        offset: 4 * 4,
+                                        shader_location: 2,
+                                    },
+                                    wgpu::VertexAttribute {
+                                        format: wgpu::VertexFormat::Float32x4,
+                                        offset: 4 * 8,
+                                        shader_location: 3,
+                                    },
+                                    wgpu::VertexAttribute {
+                                        format: wgpu::VertexFormat::Float32x4,
+                                        offset: 4 * 12,
+                                        shader_location: 4,
+                                    },
+                                ],
+                            },
+                        ],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[wgpu::ColorTargetState {
+                            format: Self::HDR_FORMAT,
+                            blend: None,
+                            write_mask: wgpu::ColorWrite::empty(),
+                        }],
+                    }),
+                    // both sides, so boxes around the camera still pass
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: Self::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                }),
+            )
+        } else {
+            None
         };
 
-        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+        // colored lines over everything, for visualizing what can't be seen
+        // otherwise, like bounding boxes
+        let debug_lines_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&screen_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                    "shader/debug_lines.wgsl"
+                ))),
+                flags: wgpu::ShaderFlags::all(),
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Vertex,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x3,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: 4 * 3,
+                                shader_location: 1,
+                            },
+                        ],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: Self::HDR_FORMAT,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrite::ALL,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..wgpu::PrimitiveState::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Self::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+            })
+        };
+
+        let post_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: None,
-            size: texture_extent,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader/post.wgsl"))),
+            flags: wgpu::ShaderFlags::all(),
+        });
+
+        // same layout as screen quads: one uniform buffer and one texture
+        let tonemap_pipeline = Self::create_post_pipeline(
+            &device,
+            &screen_pipeline_layout,
+            &post_shader,
+            "fs_tonemap",
+            swapchain_format,
+            None,
+        );
+
+        let fxaa_pipeline = Self::create_post_pipeline(
+            &device,
+            &screen_pipeline_layout,
+            &post_shader,
+            "fs_fxaa",
+            swapchain_format,
+            None,
+        );
+
+        // the scene's log luminance, halved down to a single texel
+        let luminance_pipeline = Self::create_post_pipeline(
+            &device,
+            &screen_pipeline_layout,
+            &post_shader,
+            "fs_luminance",
+            Self::LUMINANCE_FORMAT,
+            None,
+        );
+
+        let downsample_pipeline = Self::create_post_pipeline(
+            &device,
+            &screen_pipeline_layout,
+            &post_shader,
+            "fs_downsample",
+            Self::LUMINANCE_FORMAT,
+            None,
+        );
+
+        let oit_resolve_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    &screen_bind_group_layout,
+                    &textures_bind_group_layout,
+                    &textures_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let oit_resolve_pipeline = Self::create_post_pipeline(
+            &device,
+            &oit_resolve_pipeline_layout,
+            &post_shader,
+            "fs_oit_resolve",
+            Self::HDR_FORMAT,
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+        );
+
+        let post_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Processing Buffer"),
+            size: std::mem::size_of::<PostUniforms>() as _,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let post_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: post_buffer.as_entire_binding(),
+            }],
+        });
+
+        let hdr_target = Self::create_render_target(
+            &device,
+            &textures_bind_group_layout,
+            Self::HDR_FORMAT,
+            size.width,
+            size.height,
+        );
+
+        // tonemapped image, read by FXAA when enabled
+        let ldr_target = Self::create_render_target(
+            &device,
+            &textures_bind_group_layout,
+            swapchain_format,
+            size.width,
+            size.height,
+        );
+
+        let (depth_texture, depth_only) =
+            Self::create_depth_texture(&&swap_chain_descriptor, &device);
+        let depth_bind_group =
+            Self::create_depth_bind_group(&device, &depth_bind_group_layout, &depth_only);
+
+        // bound for meshes without morph targets, storage bindings can't be empty
+        let empty_morph_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Empty Morph Buffer"),
+            contents: bytemuck::cast_slice(&[MorphDelta::zeroed()]),
+            usage: wgpu::BufferUsage::STORAGE,
+        });
+
+        // black until a probe is placed and captured
+        let reflection_probe = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Reflection Probe"),
+            size: wgpu::Extent3d {
+                width: Self::REFLECTION_PROBE_SIZE,
+                height: Self::REFLECTION_PROBE_SIZE,
+                depth_or_array_layers: 6,
+            },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: desc.format,
+            format: Self::HDR_FORMAT,
             usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
         });
+        let reflection_probe_view = reflection_probe.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let reflection_probe_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
 
-        self.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            desc.texels,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(std::num::NonZeroU32::new(desc.width * 4).unwrap()),
-                rows_per_image: None,
-            },
-            texture_extent,
+        Self {
+            surface,
+            device,
+            swap_chain,
+            swap_chain_descriptor,
+            queue,
+            pipeline: render_pipeline,
+            pipeline_layout,
+            shader,
+            screen_pipeline,
+            particle_pipeline,
+            points_pipeline,
+            decal_pipeline,
+            outline_mask_pipeline,
+            outline_pipeline,
+            occlusion_pipeline,
+            debug_lines_pipeline,
+            tonemap_pipeline,
+            fxaa_pipeline,
+            oit_pipeline,
+            wireframe_pipeline,
+            overdraw_pipeline,
+            oit_resolve_pipeline,
+            luminance_pipeline,
+            downsample_pipeline,
+            uniform_bind_group_layout,
+            screen_bind_group_layout,
+            textures_bind_group_layout,
+            depth_bind_group_layout,
+            empty_morph_buffer,
+            buffer_pool: Rc::default(),
+            empty_texture: OnceCell::new(),
+            empty_normal_texture: OnceCell::new(),
+            checker_texture: OnceCell::new(),
+            checker_untextured: false,
+            quad_mesh: OnceCell::new(),
+            cube_mesh: OnceCell::new(),
+            depth_view: depth_texture,
+            depth_bind_group,
+            hdr_target,
+            ldr_target,
+            oit_targets: None,
+            post_buffer,
+            post_bind_group,
+            exposure: 1.0,
+            auto_exposure: None,
+            luminance_chain: None,
+            ambient_light: AmbientLight::default(),
+            fog: None,
+            tonemap: Tonemap::Reinhard,
+            fxaa: false,
+            debug_view: DebugView::default(),
+            world_up: GLOBAL_UP.into(),
+            wireframe_overlay: None,
+            point_size: 0.05,
+            resolution_scale: 1.0,
+            dynamic_resolution: None,
+            max_anisotropy: anisotropy_limit,
+            anisotropy_limit,
+            textures: Rc::new(RefCell::new(vec![])),
+            streamed_textures: RefCell::new(vec![]),
+            reflection_probe,
+            reflection_probe_view,
+            reflection_probe_sampler,
+            reflection_probe_position: None,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.swap_chain_descriptor.width = width;
+        self.swap_chain_descriptor.height = height;
+        self.swap_chain = self
+            .device
+            .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
+        let (depth_view, depth_only) =
+            Self::create_depth_texture(&self.swap_chain_descriptor, &self.device);
+        self.depth_view = depth_view;
+        self.depth_bind_group =
+            Self::create_depth_bind_group(&self.device, &self.depth_bind_group_layout, &depth_only);
+        self.hdr_target = Self::create_render_target(
+            &self.device,
+            &self.textures_bind_group_layout,
+            Self::HDR_FORMAT,
+            width,
+            height,
+        );
+        self.ldr_target = Self::create_render_target(
+            &self.device,
+            &self.textures_bind_group_layout,
+            self.swap_chain_descriptor.format,
+            width,
+            height,
         );
+        if self.oit_targets.is_some() {
+            self.oit_targets = Some(self.create_oit_targets());
+        }
+    }
+
+    fn create_oit_targets(&self) -> (RenderTarget, RenderTarget) {
+        let (width, height) = self.screen_size();
+        let target = |format| {
+            Self::create_render_target(
+                &self.device,
+                &self.textures_bind_group_layout,
+                format,
+                width,
+                height,
+            )
+        };
+        (target(Self::HDR_FORMAT), target(Self::REVEALAGE_FORMAT))
+    }
+
+    fn create_luminance_chain(&self) -> LuminanceChain {
+        let level_count = 32 - Self::LUMINANCE_SIZE.leading_zeros();
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: Self::LUMINANCE_SIZE,
+                height: Self::LUMINANCE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::LUMINANCE_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT
+                | wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::COPY_SRC,
+            label: Some("Luminance Texture"),
+        });
 
+        // halving the size, each texel is sampled between four of the level
+        // above, averaging them
         let sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
             label: None,
-            address_mode_u: desc.wrap_s,
-            address_mode_v: desc.wrap_t,
-            mag_filter: desc.mag_filter,
-            min_filter: desc.min_filter,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
 
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        let levels = (0..level_count)
+            .map(|level| {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                });
+                let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.textures_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                });
+                RenderTarget { view, bind_group }
+            })
+            .collect();
+
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Luminance Readback Buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as _,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        LuminanceChain {
+            texture,
+            levels,
+            readback,
+            copied: Cell::new(false),
+            mapping: None,
+            average: None,
+        }
+    }
+
+    /// `Fifo` waits for vertical sync, `Immediate` and `Mailbox` don't, and
+    /// are replaced by `Fifo` on surfaces that lack them.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        self.swap_chain_descriptor.present_mode = present_mode;
+        self.swap_chain = self
+            .device
+            .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
+    }
+
+    /// The requested present mode, which may not be the one in use if the
+    /// surface doesn't support it.
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.swap_chain_descriptor.present_mode
+    }
+
+    /// Pipelines drawing meshes with shader.wgsl, the opaque one and the one
+    /// for order-independent transparency, offsetting depth by `bias`.
+    fn create_mesh_pipelines(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        bias: wgpu::DepthBiasState,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
-            layout: &self.textures_bind_group_layout,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Self::MESH_VERTEX_LAYOUT],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Self::HDR_FORMAT.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias,
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        });
+
+        // transparent meshes, tested against the depth of opaque ones but not
+        // writing it, adding up into the accumulation targets
+        let oit_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Self::MESH_VERTEX_LAYOUT],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_oit",
+                targets: &[
+                    wgpu::ColorTargetState {
+                        format: Self::HDR_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrite::ALL,
+                    },
+                    wgpu::ColorTargetState {
+                        format: Self::REVEALAGE_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrite::ALL,
+                    },
+                ],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias,
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        });
+
+        (render_pipeline, oit_pipeline)
+    }
+
+    /// Pipeline drawing a mesh for an outline through the vertex shader
+    /// `entry_point`, over everything in the scene and tested against the
+    /// stencil with `stencil`, both sides.
+    fn create_outline_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+        write_mask: wgpu::ColorWrite,
+        stencil: wgpu::StencilFaceState,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point,
+                buffers: &[Self::MESH_VERTEX_LAYOUT],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: stencil,
+                    back: stencil,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        })
+    }
+
+    /// Pipeline drawing the quad mesh over a whole target, reading the
+    /// previous target through the fragment shader `entry_point`.
+    fn create_post_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x4,
+                            offset: 0,
+                            shader_location: 0,
+                        },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x2,
+                            offset: 4 * 7,
+                            shader_location: 2,
+                        },
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        })
+    }
+
+    fn create_render_target(
+        device: &wgpu::Device,
+        textures_bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> RenderTarget {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            label: None,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: textures_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
+                    resource: wgpu::BindingResource::TextureView(&view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
@@ -328,224 +1638,3484 @@ impl GraphicsContext {
             ],
         });
 
-        Texture {
-            bind_group: bind_group.into(),
-            width: desc.width,
-            height: desc.height,
-        }
+        RenderTarget { view, bind_group }
+    }
+
+    /// The view to draw to, and one with only depth to sample, which a
+    /// texture with stencil needs.
+    fn create_depth_texture(
+        sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
+    ) -> (wgpu::TextureView, wgpu::TextureView) {
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            // sampled by decals
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            label: None,
+        });
+
+        (
+            depth_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            depth_texture.create_view(&wgpu::TextureViewDescriptor {
+                aspect: wgpu::TextureAspect::DepthOnly,
+                ..Default::default()
+            }),
+        )
+    }
+
+    fn create_depth_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(depth_view),
+            }],
+        })
+    }
+
+    pub fn create_mesh(&self, vertices: &[Vertex], indices: &[u16]) -> Mesh {
+        self.create_morph_mesh(vertices, indices, vec![])
+    }
+
+    /// Creates one mesh for each `(vertices, indices)` pair, all in the same
+    /// vertex and index buffers, so they take fewer allocations and drawing
+    /// them one after another doesn't bind buffers again.
+    pub fn create_meshes(&self, primitives: &[(&[Vertex], &[u16])]) -> Vec<Mesh> {
+        if primitives.is_empty() {
+            return vec![];
+        }
+
+        let vertices = primitives
+            .iter()
+            .flat_map(|(vertices, _)| vertices.iter().copied())
+            .collect::<Vec<_>>();
+        let indices = primitives
+            .iter()
+            .flat_map(|(_, indices)| indices.iter().copied())
+            .collect::<Vec<_>>();
+
+        let vertex_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            });
+
+        let index_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsage::INDEX,
+            });
+
+        let inner = Rc::new((vertex_buffer, index_buffer));
+        let (mut first_index, mut base_vertex) = (0, 0);
+        primitives
+            .iter()
+            .map(|(vertices, indices)| {
+                let positions = vertex_positions(vertices);
+                let mesh = Mesh {
+                    inner: inner.clone(),
+                    topology: Topology::Triangles,
+                    first_index,
+                    base_vertex,
+                    index_count: indices.len(),
+                    vertex_count: vertices.len(),
+                    vertex_bytes: std::mem::size_of_val(*vertices) as u64,
+                    index_bytes: std::mem::size_of_val(*indices) as u64,
+                    bounding_sphere: bounding_sphere(&positions, &[]),
+                    bounding_box: bounding_box(&positions),
+                    morph_targets: Rc::new(vec![]),
+                    morph_buffer: None,
+                    positions: Rc::new(positions),
+                    indices: Rc::new(indices.to_vec()),
+                    content_hash: None,
+                };
+                first_index += indices.len() as u32;
+                base_vertex += vertices.len() as i32;
+                mesh
+            })
+            .collect()
+    }
+
+    /// Creates a mesh whose vertices are displaced in the vertex shader by the
+    /// given morph targets, weighted by what's passed to `render_mesh`.
+    pub fn create_morph_mesh(
+        &self,
+        vertices: &[Vertex],
+        indices: &[u16],
+        morph_targets: Vec<MorphTarget>,
+    ) -> Mesh {
+        let vertex_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            });
+
+        let index_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsage::INDEX,
+            });
+
+        let morph_buffer = if morph_targets.is_empty() {
+            None
+        } else {
+            // laid out target by target, each with one delta per vertex
+            let deltas = morph_targets
+                .iter()
+                .flat_map(|target| target.positions.iter().zip(target.normals.iter()))
+                .map(|(position, normal)| MorphDelta {
+                    position: [position[0], position[1], position[2], 0.0],
+                    normal: [normal[0], normal[1], normal[2], 0.0],
+                })
+                .collect::<Vec<_>>();
+            let buffer = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Morph Buffer"),
+                    contents: bytemuck::cast_slice(&deltas),
+                    usage: wgpu::BufferUsage::STORAGE,
+                });
+            Some(Rc::new(buffer))
+        };
+
+        let positions = vertex_positions(vertices);
+        Mesh {
+            inner: (vertex_buffer, index_buffer).into(),
+            topology: Topology::Triangles,
+            first_index: 0,
+            base_vertex: 0,
+            index_count: indices.len(),
+            vertex_count: vertices.len(),
+            vertex_bytes: std::mem::size_of_val(vertices) as u64,
+            index_bytes: std::mem::size_of_val(indices) as u64,
+            bounding_sphere: bounding_sphere(&positions, &morph_targets),
+            bounding_box: bounding_box(&positions),
+            morph_targets: Rc::new(morph_targets),
+            morph_buffer,
+            positions: Rc::new(positions),
+            indices: Rc::new(indices.to_vec()),
+            content_hash: None,
+        }
+    }
+
+    /// Creates a point cloud, drawn by `Pass::render_mesh` as a dot of
+    /// `point_size` facing the camera at each point, opaque and unlit, in the
+    /// points' colors times the material's base color. It has no indices.
+    pub fn create_point_mesh(&self, points: &[PointVertex]) -> Mesh {
+        let vertex_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(points),
+                usage: wgpu::BufferUsage::VERTEX,
+            });
+
+        // never bound, only there so every mesh has one
+        let index_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &[],
+                usage: wgpu::BufferUsage::INDEX,
+            });
+
+        let positions = points
+            .iter()
+            .map(|it| Vec3::from(it.position))
+            .collect::<Vec<_>>();
+        Mesh {
+            inner: (vertex_buffer, index_buffer).into(),
+            topology: Topology::Points,
+            first_index: 0,
+            base_vertex: 0,
+            index_count: 0,
+            vertex_count: points.len(),
+            vertex_bytes: std::mem::size_of_val(points) as u64,
+            index_bytes: 0,
+            bounding_sphere: bounding_sphere(&positions, &[]),
+            bounding_box: bounding_box(&positions),
+            morph_targets: Rc::new(vec![]),
+            morph_buffer: None,
+            positions: Rc::new(positions),
+            indices: Rc::new(vec![]),
+            content_hash: None,
+        }
+    }
+
+    pub fn create_uniform_buffer(&self) -> UniformBuffer {
+        self.create_uniform_buffer_with_morph(&self.empty_morph_buffer)
+    }
+
+    /// Uniform buffer for drawing `mesh`, which also binds its morph targets.
+    pub fn create_mesh_uniform_buffer(&self, mesh: &Mesh) -> UniformBuffer {
+        match &mesh.morph_buffer {
+            Some(morph_buffer) => self.create_uniform_buffer_with_morph(morph_buffer),
+            None => self.create_uniform_buffer(),
+        }
+    }
+
+    /// A buffer of at least `size` bytes from the pool, see `BufferPool`.
+    fn pooled_buffer(&self, label: &str, size: u64, usage: wgpu::BufferUsage) -> PooledBuffer {
+        self.buffer_pool.take(&self.device, label, size, usage)
+    }
+
+    fn create_uniform_buffer_with_morph(&self, morph_buffer: &wgpu::Buffer) -> UniformBuffer {
+        let buffer = self.pooled_buffer(
+            "Uniform Buffer",
+            std::mem::size_of::<Uniforms>() as u64,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: morph_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.reflection_probe_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.reflection_probe_sampler),
+                },
+            ],
+        });
+
+        UniformBuffer { buffer, bind_group }
+    }
+
+    pub fn create_screen_quad(&self) -> ScreenQuad {
+        let buffer = self.pooled_buffer(
+            "Screen Quad Buffer",
+            std::mem::size_of::<ScreenQuadUniforms>() as u64,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.binding(),
+            }],
+        });
+
+        ScreenQuad { buffer, bind_group }
+    }
+
+    /// Instance buffer for drawing up to `capacity` particles at once, along
+    /// with their uniforms.
+    pub fn create_particle_buffer(&self, capacity: usize) -> ParticleBuffer {
+        let instances = self.pooled_buffer(
+            "Particle Instance Buffer",
+            (capacity.max(1) * std::mem::size_of::<ParticleInstance>()) as u64,
+            wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let uniforms = self.pooled_buffer(
+            "Particle Uniform Buffer",
+            std::mem::size_of::<ParticleUniforms>() as u64,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms.binding(),
+            }],
+        });
+
+        ParticleBuffer {
+            instances,
+            capacity,
+            uniforms,
+            bind_group,
+        }
+    }
+
+    /// Queries for testing up to `capacity` bounding boxes a frame, `None`
+    /// where the adapter has no pipeline statistics queries to count their
+    /// fragments with.
+    pub fn create_occlusion_queries(&self, capacity: usize) -> Option<OcclusionQueries> {
+        self.occlusion_pipeline.as_ref()?;
+        let capacity = capacity.clamp(1, wgpu::QUERY_SET_MAX_QUERIES as usize);
+
+        let query_set = self.device.create_query_set(&wgpu::QuerySetDescriptor {
+            ty: wgpu::QueryType::PipelineStatistics(
+                wgpu::PipelineStatisticsTypes::FRAGMENT_SHADER_INVOCATIONS,
+            ),
+            count: capacity as u32,
+        });
+
+        let boxes = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Box Buffer"),
+            size: (capacity * std::mem::size_of::<[f32; 16]>()) as _,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let results = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Result Buffer"),
+            size: (capacity * wgpu::QUERY_SIZE as usize) as _,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(OcclusionQueries {
+            query_set,
+            boxes,
+            results,
+            capacity,
+            tested: Vec::with_capacity(capacity),
+            resolved: vec![],
+            mapping: None,
+            visible: HashMap::new(),
+        })
+    }
+
+    /// Renders `scene` on its own into a `size` by `size` image over
+    /// `background`, framed around its bounding sphere from the front, a bit
+    /// to the side and above. The current exposure and tonemap are applied,
+    /// but not FXAA or order-independent transparency. Blocks until the image
+    /// is read back from the GPU.
+    pub fn render_thumbnail(
+        &self,
+        scene: &Scene,
+        size: u32,
+        background: [f32; 4],
+    ) -> image::RgbaImage {
+        let (center, radius) = scene.bounding_sphere().unwrap_or((Vec3::ZERO, 1.0));
+        let radius = radius.max(0.001);
+        let fov = std::f32::consts::FRAC_PI_4;
+        let distance = radius / (fov * 0.5).sin();
+        let direction = Quat::from_rotation_arc(GLOBAL_UP.into(), self.world_up)
+            * Vec3::new(1.0, 0.6, 2.0).normalize();
+        let view = Mat4::look_at_rh(center + direction * distance, center, self.world_up);
+        // unlike the GL one, this projection keeps the whole depth range
+        // wgpu clips to, the model can be close to the near plane
+        let perspective = Mat4::perspective_rh(
+            fov,
+            1.0,
+            (distance - radius).max(distance * 0.01),
+            distance + radius,
+        );
+
+        let hdr = Self::create_render_target(
+            &self.device,
+            &self.textures_bind_group_layout,
+            Self::HDR_FORMAT,
+            size,
+            size,
+        );
+        let (depth_view, _) = Self::create_depth_texture(
+            &wgpu::SwapChainDescriptor {
+                width: size,
+                height: size,
+                ..self.swap_chain_descriptor.clone()
+            },
+            &self.device,
+        );
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        };
+        // the tonemap pipeline writes in the swap chain format
+        let format = self.swap_chain_descriptor.format;
+        let ldr = self.device.create_texture(&wgpu::TextureDescriptor {
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        });
+        let ldr_view = ldr.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        let textures = self.texture_snapshot();
+        {
+            let [r, g, b, a] = background;
+            let pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &hdr.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: r as f64,
+                            g: g as f64,
+                            b: b as f64,
+                            a: a as f64,
+                        }),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            let mut pass = Pass {
+                graphics: self,
+                textures: &textures,
+                pass,
+                kind: PassKind::Scene,
+                viewport: [0.0, 0.0, size as f32, size as f32],
+                bound: BoundState::default(),
+                stats: RenderStats::default(),
+            };
+            scene.render(&mut pass, perspective, view);
+        }
+
+        let uniforms = PostUniforms {
+            exposure: self.exposure,
+            tonemap: match self.tonemap {
+                Tonemap::Reinhard => 0,
+                Tonemap::Aces => 1,
+            },
+            texel_size: [1.0 / size as f32; 2],
+            render_scale: [1.0; 2],
+            _padding: [0.0; 2],
+        };
+        self.queue
+            .write_buffer(&self.post_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+        Frame::draw_post_pass(&mut encoder, self, &self.tonemap_pipeline, &hdr, &ldr_view);
+
+        // rows of a copy have to be aligned
+        let row_size = size * 4;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_size = (row_size + alignment - 1) / alignment * alignment;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Thumbnail Buffer"),
+            size: (padded_row_size * size) as _,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &ldr,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_size),
+                    rows_per_image: None,
+                },
+            },
+            extent,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).expect("Failed to read back the thumbnail.");
+
+        let bgra = matches!(
+            format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        let mut pixels = Vec::with_capacity((row_size * size) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_size as usize) {
+            for texel in row[..row_size as usize].chunks(4) {
+                if bgra {
+                    pixels.extend_from_slice(&[texel[2], texel[1], texel[0], texel[3]]);
+                } else {
+                    pixels.extend_from_slice(texel);
+                }
+            }
+        }
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(size, size, pixels).expect("pixels should fill the image")
+    }
+
+    /// Places the reflection probe that metallic materials reflect, or
+    /// removes it with `None`. Nothing is captured until
+    /// `update_reflection_probe`.
+    pub fn set_reflection_probe(&mut self, position: Option<Vec3>) {
+        self.reflection_probe_position = position;
+    }
+
+    pub fn reflection_probe(&self) -> Option<Vec3> {
+        self.reflection_probe_position
+    }
+
+    /// Captures `scene` around the reflection probe into its cube map, one
+    /// face at a time. It draws the scene six times, so it's meant to be
+    /// called when the scene around the probe changes rather than every
+    /// frame. The faces hold HDR color without order-independent
+    /// transparency. Does nothing without a probe.
+    pub fn update_reflection_probe(&self, scene: &Scene) {
+        let position = match self.reflection_probe_position {
+            Some(position) => position,
+            None => return,
+        };
+
+        let size = Self::REFLECTION_PROBE_SIZE;
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        };
+        // drawn to and copied into each face, since the cube map can't be a
+        // target while the meshes drawn sample it
+        let face = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::HDR_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let face_view = face.create_view(&wgpu::TextureViewDescriptor::default());
+        let (depth_view, _) = Self::create_depth_texture(
+            &wgpu::SwapChainDescriptor {
+                width: size,
+                height: size,
+                ..self.swap_chain_descriptor.clone()
+            },
+            &self.device,
+        );
+
+        let perspective = Mat4::perspective_infinite_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.01);
+        // forward and up of each face in wgpu's order (+X, -X, +Y, -Y, +Z,
+        // -Z). Cube maps are looked up left-handed, so the shader flips z
+        // and the z faces look the other way
+        let faces = [
+            (Vec3::X, Vec3::Y),
+            (-Vec3::X, Vec3::Y),
+            (Vec3::Y, Vec3::Z),
+            (-Vec3::Y, -Vec3::Z),
+            (-Vec3::Z, Vec3::Y),
+            (Vec3::Z, Vec3::Y),
+        ];
+
+        let textures = self.texture_snapshot();
+        for (layer, (forward, up)) in faces.iter().enumerate() {
+            let view = Mat4::look_at_rh(position, position + *forward, *up);
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &face_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(self.clear_color()),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: false,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+                let mut pass = Pass {
+                    graphics: self,
+                    textures: &textures,
+                    pass,
+                    kind: PassKind::Scene,
+                    viewport: [0.0, 0.0, size as f32, size as f32],
+                    bound: BoundState::default(),
+                    stats: RenderStats::default(),
+                };
+                scene.render(&mut pass, perspective, view);
+            }
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &face,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &self.reflection_probe,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                },
+                extent,
+            );
+            // the uniforms of every mesh are written again for each face, and
+            // only the last write before a submit is seen
+            self.queue.submit(std::iter::once(encoder.finish()));
+        }
+    }
+
+    /// What the scene is cleared to, the fog color when there's fog.
+    fn clear_color(&self) -> wgpu::Color {
+        match self.fog {
+            Some(Fog {
+                color: [r, g, b], ..
+            }) => wgpu::Color {
+                r: r as f64,
+                g: g as f64,
+                b: b as f64,
+                a: 1.0,
+            },
+            None => wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+        }
+    }
+
+    /// Size of the surface being rendered to, in pixels.
+    pub fn screen_size(&self) -> (u32, u32) {
+        (
+            self.swap_chain_descriptor.width,
+            self.swap_chain_descriptor.height,
+        )
+    }
+
+    pub fn create_texture(&self, desc: &TextureDescription) -> Texture {
+        let resources = self.create_texture_resources(desc);
+        Texture {
+            slot: self.store_texture(resources, desc.sampler, None),
+            sampler_options: desc.sampler,
+            uv_transform: UvTransform::default(),
+            width: desc.width,
+            height: desc.height,
+            format: desc.format,
+            // the only level `create_texture_resources` creates
+            mip_level_count: 1,
+            content_hash: None,
+        }
+    }
+
+    /// Points `handle`, and every copy of its texture in materials or
+    /// elsewhere, at the texels of `desc`, sampled with its sampler. Copies
+    /// from `Texture::with_sampler` follow with their own samplers. Frames
+    /// that already began keep drawing the previous texels. The `width` and
+    /// `height` of existing copies aren't updated.
+    pub fn replace_texture(&self, handle: TextureHandle, desc: &TextureDescription) {
+        let resources = Rc::new(self.create_texture_resources(desc));
+        let mut textures = self.textures.borrow_mut();
+        for stored in textures.iter_mut().flatten() {
+            if stored.source == Some(handle) {
+                let (_, _, view) = resources.as_ref();
+                let (bind_group, sampler) = self.create_texture_bind_group(view, stored.sampler);
+                stored.resources = Rc::new((bind_group, sampler, view.clone()));
+            }
+        }
+        if let Some(stored) = textures.get_mut(handle.0).and_then(Option::as_mut) {
+            stored.resources = resources;
+            stored.sampler = desc.sampler;
+        }
+    }
+
+    /// Puts `resources` in the first free entry of the store.
+    fn store_texture(
+        &self,
+        resources: TextureResources,
+        sampler: SamplerOptions,
+        source: Option<Rc<TextureSlot>>,
+    ) -> Rc<TextureSlot> {
+        let stored = StoredTexture {
+            resources: Rc::new(resources),
+            sampler,
+            source: source.as_ref().map(|it| it.handle),
+        };
+        let mut textures = self.textures.borrow_mut();
+        let index = match textures.iter().position(Option::is_none) {
+            Some(index) => {
+                textures[index] = Some(stored);
+                index
+            }
+            None => {
+                textures.push(Some(stored));
+                textures.len() - 1
+            }
+        };
+        Rc::new(TextureSlot {
+            handle: TextureHandle(index),
+            source,
+            store: Rc::downgrade(&self.textures),
+        })
+    }
+
+    /// What the store points at as a frame begins, for its passes to borrow
+    /// bind groups from while it can still change. The default textures are
+    /// created first, since passes bind them as they draw.
+    fn texture_snapshot(&self) -> Vec<Option<Rc<TextureResources>>> {
+        self.empty_texture();
+        self.empty_normal_texture();
+        self.checker_texture();
+        self.textures
+            .borrow()
+            .iter()
+            .map(|it| it.as_ref().map(|it| it.resources.clone()))
+            .collect()
+    }
+
+    /// A `width` by `height` texture that shows `placeholder`, usually a
+    /// single texel, until its texels arrive through `texels`, e.g. from a
+    /// thread decoding them, and `update_streamed_textures` uploads them.
+    /// They're read with the format and sampler of `placeholder`. If an error
+    /// arrives instead, or the sender is dropped without sending, the
+    /// placeholder stays.
+    pub fn create_streamed_texture(
+        &self,
+        placeholder: &TextureDescription,
+        width: u32,
+        height: u32,
+        texels: Receiver<Result<Vec<u8>, String>>,
+    ) -> Texture {
+        let texture = Texture {
+            width,
+            height,
+            ..self.create_texture(placeholder)
+        };
+        self.streamed_textures.borrow_mut().push(StreamedTexture {
+            texels,
+            width,
+            height,
+            format: placeholder.format,
+            sampler: placeholder.sampler,
+            slot: Rc::downgrade(&texture.slot),
+        });
+        texture
+    }
+
+    /// Uploads the texels that arrived for streamed textures, which switch
+    /// to them from their placeholder. Meant to be called once a frame.
+    /// Returns the errors that arrived instead, along with the handle of the
+    /// texture each one keeps on its placeholder.
+    pub fn update_streamed_textures(&self) -> Vec<(TextureHandle, String)> {
+        let mut errors = vec![];
+        self.streamed_textures.borrow_mut().retain(|it| {
+            let slot = match it.slot.upgrade() {
+                Some(slot) => slot,
+                // every copy of the texture was dropped
+                None => return false,
+            };
+            match it.texels.try_recv() {
+                Ok(Ok(texels)) => {
+                    let desc = TextureDescription::new(&texels, it.width, it.height, it.format)
+                        .sampler(it.sampler);
+                    self.replace_texture(slot.handle, &desc);
+                    false
+                }
+                Ok(Err(error)) => {
+                    errors.push((slot.handle, error));
+                    false
+                }
+                Err(TryRecvError::Empty) => true,
+                Err(TryRecvError::Disconnected) => false,
+            }
+        });
+        errors
+    }
+
+    fn create_texture_resources(&self, desc: &TextureDescription) -> TextureResources {
+        let texture_extent = wgpu::Extent3d {
+            width: desc.width,
+            height: desc.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: texture_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            desc.texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(std::num::NonZeroU32::new(desc.width * 4).unwrap()),
+                rows_per_image: None,
+            },
+            texture_extent,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let (bind_group, sampler) = self.create_texture_bind_group(&view, desc.sampler);
+        (bind_group, sampler, Rc::new(view))
+    }
+
+    /// Caps the anisotropic filtering of samplers created from now on, e.g.
+    /// for weak hardware. Requests above it, like
+    /// `TextureDescription::anisotropy`, are lowered to it instead of relying
+    /// on the driver to clamp them. Rounded down to a power of two no greater
+    /// than `anisotropy_limit`, so returns the level set, lower than `level`
+    /// when that rounded it.
+    pub fn set_max_anisotropy(&mut self, level: u8) -> u8 {
+        self.max_anisotropy =
+            Self::anisotropy_clamp(level, self.anisotropy_limit).map_or(1, |it| it.get());
+        self.max_anisotropy
+    }
+
+    pub fn max_anisotropy(&self) -> u8 {
+        self.max_anisotropy
+    }
+
+    /// The highest anisotropy the adapter filters with, read when the
+    /// context is created, `MAX_ANISOTROPY` unless it's a software one.
+    pub fn anisotropy_limit(&self) -> u8 {
+        self.anisotropy_limit
+    }
+
+    fn create_texture_bind_group(
+        &self,
+        view: &wgpu::TextureView,
+        options: SamplerOptions,
+    ) -> (wgpu::BindGroup, wgpu::Sampler) {
+        let sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: options.wrap_s,
+            address_mode_v: options.wrap_t,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            mipmap_filter: options.mipmap_filter,
+            anisotropy_clamp: Self::anisotropy_clamp(options.anisotropy, self.max_anisotropy),
+            lod_min_clamp: options.lod_min_clamp,
+            lod_max_clamp: options.lod_max_clamp,
+            ..Default::default()
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.textures_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        (bind_group, sampler)
+    }
+
+    /// Rounds the requested level down to a power of two no greater than
+    /// `max`, returning `None` when filtering is off.
+    fn anisotropy_clamp(level: u8, max: u8) -> Option<std::num::NonZeroU8> {
+        let level = level.min(max);
+        if level <= 1 {
+            return None;
+        }
+        std::num::NonZeroU8::new(1 << (7 - level.leading_zeros()))
+    }
+
+    pub fn get_current_frame<'gfx>(&'gfx mut self) -> Frame<'gfx> {
+        // the last frame was submitted, what it dropped is free to rewrite
+        self.buffer_pool.recycle();
+
+        let frame = match self.swap_chain.get_current_frame() {
+            Ok(frame) => frame,
+            Err(_) => {
+                self.swap_chain = self
+                    .device
+                    .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
+                self.swap_chain
+                    .get_current_frame()
+                    .expect("Failed to acquire next swap chain texture!")
+            }
+        };
+
+        let encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        Frame {
+            textures: self.texture_snapshot(),
+            graphics: self,
+            frame,
+            encoder,
+            scene_drawn: false,
+            transparency_drawn: false,
+            post_processed: false,
+        }
+    }
+
+    fn get_quad_mesh(&self) -> &Mesh {
+        self.quad_mesh.get_or_init(|| {
+            macro_rules! v {
+                ($pos:expr, $norm:expr, $uv:expr) => {
+                    Vertex {
+                        position: $pos,
+                        normal: $norm,
+                        tex_coord: $uv,
+                        tangent: [0.0; 4],
+                    }
+                };
+            }
+            let vertices = [
+                v!([-1.0, -1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0]),
+                v!([1.0, -1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0]),
+                v!([-1.0, 1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 0.0]),
+                v!([1.0, 1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 0.0]),
+            ];
+            let indices = [0, 1, 2, 3, 2, 1];
+            let mesh = self.create_mesh(&vertices, &indices);
+            mesh
+        })
+    }
+
+    /// Cube from -1 to 1 in every axis, facing out.
+    fn get_cube_mesh(&self) -> &Mesh {
+        self.cube_mesh.get_or_init(|| {
+            let vertices = (0..8)
+                .map(|i| {
+                    let corner = |bit: usize| if i & bit != 0 { 1.0 } else { -1.0 };
+                    Vertex {
+                        position: [corner(1), corner(2), corner(4), 1.0],
+                        normal: [0.0, 0.0, 0.0],
+                        tex_coord: [0.0, 0.0],
+                        tangent: [0.0; 4],
+                    }
+                })
+                .collect::<Vec<_>>();
+            let indices = [
+                0, 6, 2, 0, 4, 6, // -x
+                1, 3, 7, 1, 7, 5, // +x
+                0, 1, 5, 0, 5, 4, // -y
+                2, 7, 3, 2, 6, 7, // +y
+                0, 3, 1, 0, 2, 3, // -z
+                4, 5, 7, 4, 7, 6, // +z
+            ];
+            self.create_mesh(&vertices, &indices)
+        })
+    }
+
+    /// Uniforms for drawing a decal with `Pass::draw_decal`.
+    pub fn create_decal(&self) -> Decal {
+        let buffer = self.pooled_buffer(
+            "Decal Buffer",
+            std::mem::size_of::<DecalUniforms>() as u64,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.binding(),
+            }],
+        });
+
+        Decal { buffer, bind_group }
+    }
+
+    /// Room for `capacity` lines drawn with `Pass::draw_debug_lines`.
+    pub fn create_debug_lines(&self, capacity: usize) -> DebugLines {
+        let buffer = self.pooled_buffer(
+            "Debug Lines Buffer",
+            std::mem::size_of::<[f32; 16]>() as u64,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.binding(),
+            }],
+        });
+
+        let vertices = self.pooled_buffer(
+            "Debug Lines Vertex Buffer",
+            (capacity.max(1) * 2 * std::mem::size_of::<LineVertex>()) as u64,
+            wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        );
+
+        DebugLines {
+            buffer,
+            bind_group,
+            vertices,
+            capacity,
+            lines: Vec::with_capacity(capacity * 2),
+        }
+    }
+
+    /// Uniforms for outlining a mesh with `Pass::draw_outline`.
+    pub fn create_outline(&self) -> Outline {
+        let buffer = self.pooled_buffer(
+            "Outline Buffer",
+            std::mem::size_of::<OutlineUniforms>() as u64,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.binding(),
+            }],
+        });
+
+        Outline { buffer, bind_group }
+    }
+
+    /// 1x1 white texture bound in place of a missing diffuse texture.
+    pub fn empty_texture(&self) -> &Texture {
+        self.empty_texture.get_or_init(|| {
+            let pixels = [255, 255, 255, 255u8];
+            Texture::builder(&pixels, 1, 1, wgpu::TextureFormat::Rgba8Unorm).build(self)
+        })
+    }
+
+    /// 1x1 flat normal map bound in place of a missing normal texture.
+    pub fn empty_normal_texture(&self) -> &Texture {
+        self.empty_normal_texture.get_or_init(|| {
+            let pixels = [128, 128, 255, 255u8];
+            Texture::builder(&pixels, 1, 1, wgpu::TextureFormat::Rgba8Unorm).build(self)
+        })
+    }
+
+    /// 2x2 magenta/black checker, repeated across the mesh to make UVs visible.
+    pub fn checker_texture(&self) -> &Texture {
+        self.checker_texture.get_or_init(|| {
+            let pixels = [
+                255, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 255, 255u8,
+            ];
+            Texture::builder(&pixels, 2, 2, wgpu::TextureFormat::Rgba8Unorm)
+                .wrap_s(wgpu::AddressMode::Repeat)
+                .wrap_t(wgpu::AddressMode::Repeat)
+                .min_filter(wgpu::FilterMode::Nearest)
+                .mag_filter(wgpu::FilterMode::Nearest)
+                .build(self)
+        })
+    }
+
+    /// When enabled, materials without a diffuse texture are drawn with
+    /// `checker_texture` instead of their base color.
+    pub fn set_checker_untextured(&mut self, enabled: bool) {
+        self.checker_untextured = enabled;
+    }
+
+    pub fn checker_untextured(&self) -> bool {
+        self.checker_untextured
+    }
+
+    /// Multiplies the scene color before tonemapping; above 1 brightens.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure.max(0.0);
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// With `Some`, `update_exposure` drives the exposure towards making the
+    /// average luminance of the scene the target one, overriding
+    /// `set_exposure`.
+    pub fn set_auto_exposure(&mut self, auto_exposure: Option<AutoExposure>) {
+        self.auto_exposure = auto_exposure;
+        match auto_exposure {
+            Some(_) if self.luminance_chain.is_none() => {
+                self.luminance_chain = Some(self.create_luminance_chain());
+            }
+            Some(_) => {}
+            None => self.luminance_chain = None,
+        }
+    }
+
+    pub fn auto_exposure(&self) -> Option<AutoExposure> {
+        self.auto_exposure
+    }
+
+    /// Reads back the average luminance of a previous frame if it's ready,
+    /// without waiting for it, and moves the exposure `delta` seconds
+    /// towards the one for the last luminance read. Does nothing without
+    /// auto exposure.
+    pub fn update_exposure(&mut self, delta: Duration) {
+        let (settings, chain) = match (self.auto_exposure, &mut self.luminance_chain) {
+            (Some(settings), Some(chain)) => (settings, chain),
+            _ => return,
+        };
+
+        if chain.copied.get() {
+            // copied by a frame that has been submitted by now
+            let readback = &chain.readback;
+            let mapping = chain
+                .mapping
+                .get_or_insert_with(|| Box::pin(readback.slice(..).map_async(wgpu::MapMode::Read)));
+            self.device.poll(wgpu::Maintain::Poll);
+
+            let waker = noop_waker();
+            let mut context = std::task::Context::from_waker(&waker);
+            if let std::task::Poll::Ready(result) = mapping.as_mut().poll(&mut context) {
+                chain.mapping = None;
+                chain.copied.set(false);
+                if result.is_ok() {
+                    let mapped = chain.readback.slice(..).get_mapped_range();
+                    let log_luminance = f16_to_f32(u16::from_le_bytes([mapped[0], mapped[1]]));
+                    chain.average = Some(log_luminance.exp());
+                    drop(mapped);
+                    chain.readback.unmap();
+                }
+            }
+        }
+
+        let average = match chain.average {
+            Some(average) => average,
+            None => return,
+        };
+        let clamp = |it: f32| it.clamp(settings.min_exposure, settings.max_exposure);
+        let wanted = clamp(settings.target_luminance / average.max(1e-4)).ln();
+        let current = clamp(self.exposure).ln();
+        // adapted in log space, so brightening and darkening take as long
+        let t = 1.0 - (-settings.speed * delta.as_secs_f32()).exp();
+        self.exposure = (current + (wanted - current) * t).exp();
+    }
+
+    /// Light added to every surface regardless of its direction, so the sides
+    /// facing away from the light aren't black. A low gray by default.
+    pub fn set_ambient_light(&mut self, ambient: AmbientLight) {
+        self.ambient_light = AmbientLight {
+            intensity: ambient.intensity.max(0.0),
+            ..ambient
+        };
+    }
+
+    pub fn ambient_light(&self) -> AmbientLight {
+        self.ambient_light
+    }
+
+    /// Also clears the scene to the fog color, so fogged surfaces blend into
+    /// the background.
+    pub fn set_fog(&mut self, fog: Option<Fog>) {
+        self.fog = fog;
+    }
+
+    pub fn fog(&self) -> Option<Fog> {
+        self.fog
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        self.tonemap = tonemap;
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        self.tonemap
+    }
+
+    /// Smooths edges of the tonemapped image, a cheaper alternative to MSAA
+    /// that blurs some texture detail.
+    pub fn set_fxaa(&mut self, enabled: bool) {
+        self.fxaa = enabled;
+    }
+
+    pub fn fxaa(&self) -> bool {
+        self.fxaa
+    }
+
+    /// Replaces the shading of meshes with a visualization of their data.
+    /// Everything is drawn in the scene pass while it's on, transparent
+    /// materials included.
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        self.debug_view = view;
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+
+    /// Whether blended materials are drawn in the transparent pass, rather
+    /// than with everything else.
+    fn draws_transparent_pass(&self) -> bool {
+        self.oit_targets.is_some() && self.debug_view == DebugView::Shaded
+    }
+
+    /// Depth offset for materials drawn over coplanar surfaces, such as
+    /// decals. `constant` is in units of the smallest depth difference,
+    /// `slope_scale` multiplies the depth slope of each triangle and `clamp`
+    /// limits the total offset unless it's zero. Negative values bring
+    /// surfaces closer to the camera.
+    pub fn create_depth_bias(&self, constant: i32, slope_scale: f32, clamp: f32) -> DepthBias {
+        let state = wgpu::DepthBiasState {
+            constant,
+            slope_scale,
+            clamp,
+        };
+        let pipelines =
+            Self::create_mesh_pipelines(&self.device, &self.pipeline_layout, &self.shader, state);
+        DepthBias {
+            inner: Rc::new(pipelines),
+            state,
+        }
+    }
+
+    /// Axis that cylindrical billboards stay upright along, `GLOBAL_UP` by
+    /// default. Should match the camera's, see `Camera::set_up`.
+    pub fn set_world_up(&mut self, up: Vec3) {
+        self.world_up = up.normalize();
+    }
+
+    pub fn world_up(&self) -> Vec3 {
+        self.world_up
+    }
+
+    /// Draws the edges of opaque meshes over them in `color`, to review their
+    /// topology. Does nothing if `supports_wireframe_overlay` is false.
+    pub fn set_wireframe_overlay(&mut self, color: Option<[f32; 4]>) {
+        self.wireframe_overlay = color;
+    }
+
+    pub fn wireframe_overlay(&self) -> Option<[f32; 4]> {
+        self.wireframe_overlay
+    }
+
+    /// Width of the squares, rounded off, that points of point clouds are
+    /// drawn as, in world units. 0.05 by default.
+    pub fn set_point_size(&mut self, size: f32) {
+        self.point_size = size.max(0.0);
+    }
+
+    pub fn point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    /// Whether the adapter can draw polygons as lines, which the wireframe
+    /// overlay needs.
+    pub fn supports_wireframe_overlay(&self) -> bool {
+        self.wireframe_pipeline.is_some()
+    }
+
+    /// Draws blended materials (see `Material::blended`) in a pass of their own from
+    /// `Frame::begin_transparent_pass`, blending them correctly whatever
+    /// order they're drawn in. Takes two extra screen-sized targets.
+    pub fn set_order_independent_transparency(&mut self, enabled: bool) {
+        if enabled == self.oit_targets.is_some() {
+            return;
+        }
+        self.oit_targets = if enabled {
+            Some(self.create_oit_targets())
+        } else {
+            None
+        };
+    }
+
+    pub fn order_independent_transparency(&self) -> bool {
+        self.oit_targets.is_some()
+    }
+
+    /// With `Some`, the scene is rendered to a part of the HDR target as
+    /// small as `min_scale` of the window and stretched over it when
+    /// tonemapping, as driven by `update_resolution_scale`. With `None` it's
+    /// back to full resolution.
+    pub fn set_dynamic_resolution(&mut self, dynamic_resolution: Option<DynamicResolution>) {
+        self.dynamic_resolution = dynamic_resolution;
+        if dynamic_resolution.is_none() {
+            self.resolution_scale = 1.0;
+        }
+    }
+
+    pub fn dynamic_resolution(&self) -> Option<DynamicResolution> {
+        self.dynamic_resolution
+    }
+
+    /// Moves the resolution scale toward the one that would make frames take
+    /// the target time, given how long the last one took. Does nothing
+    /// without dynamic resolution.
+    pub fn update_resolution_scale(&mut self, frame_time: Duration) {
+        let settings = match self.dynamic_resolution {
+            Some(settings) => settings,
+            None => return,
+        };
+        let ratio = settings.target_frame_time.as_secs_f32() / frame_time.as_secs_f32().max(1e-4);
+        // leave small variations alone, vsync alone makes frame times jitter
+        if (ratio - 1.0).abs() < DynamicResolution::TOLERANCE {
+            return;
+        }
+        // rendering cost goes with the pixel count, the square of the scale
+        let wanted = self.resolution_scale * ratio.sqrt();
+        let scale = self.resolution_scale + (wanted - self.resolution_scale) * 0.1;
+        self.resolution_scale = scale.clamp(settings.min_scale, settings.max_scale);
+    }
+
+    /// Fraction of the window size the scene is currently rendered at.
+    pub fn resolution_scale(&self) -> f32 {
+        self.resolution_scale
+    }
+
+    /// Size of the part of the HDR target the scene is rendered to.
+    fn render_size(&self) -> (u32, u32) {
+        let (width, height) = self.screen_size();
+        let scale = |it: u32| ((it as f32 * self.resolution_scale).ceil() as u32).clamp(1, it);
+        (scale(width), scale(height))
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 4],
+    pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
+    /// xyz is the tangent and w the handedness of the bitangent, 1 or -1 as
+    /// in glTF. All zeros when the mesh has no tangents, in which case the
+    /// shader derives them from the texture coordinates.
+    pub tangent: [f32; 4],
+}
+
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    /// vertex_buffer, index_buffer, possibly shared with other meshes, see
+    /// `GraphicsContext::create_meshes`
+    inner: Rc<(wgpu::Buffer, wgpu::Buffer)>,
+    topology: Topology,
+    /// Where this mesh's part of the buffers starts.
+    first_index: u32,
+    base_vertex: i32,
+    /// Indices and vertices of this mesh's part of the buffers, set from what
+    /// it was created with.
+    pub index_count: usize,
+    pub vertex_count: usize,
+    /// Bytes taken by this mesh's part of the vertex and index buffers.
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    /// At most `MorphTarget::MAX_COUNT` targets, kept on the CPU.
+    pub morph_targets: Rc<Vec<MorphTarget>>,
+    morph_buffer: Option<Rc<wgpu::Buffer>>,
+    bounding_sphere: (Vec3, f32),
+    bounding_box: (Vec3, Vec3),
+    /// Model space positions and the triangles' indices, kept on the CPU to
+    /// scatter things over the surface.
+    positions: Rc<Vec<Vec3>>,
+    indices: Rc<Vec<u16>>,
+    /// Hash of the data the importer created this mesh from, for
+    /// `import_gltf::reimport` to find it again. `None` for meshes made
+    /// otherwise.
+    pub(crate) content_hash: Option<u64>,
+}
+
+/// How the vertex buffer of a `Mesh` is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /// `Vertex`es joined into triangles by the indices.
+    Triangles,
+    /// Unconnected `PointVertex`es, see `GraphicsContext::create_point_mesh`.
+    Points,
+}
+
+/// A point of a point cloud, in model space, and its linear color.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PointVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// Per-vertex displacements of a blend shape, one entry per vertex of the mesh.
+#[derive(Debug, Clone)]
+pub struct MorphTarget {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+}
+
+impl MorphTarget {
+    /// Targets past this count are dropped on import.
+    pub const MAX_COUNT: usize = 8;
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct MorphDelta {
+    position: [f32; 4],
+    normal: [f32; 4],
+}
+
+impl Mesh {
+    /// Identifies the GPU buffers of this mesh, for sorting draws.
+    pub fn sort_key(&self) -> usize {
+        Rc::as_ptr(&self.inner) as usize
+    }
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+    pub fn vertex(&self) -> &wgpu::Buffer {
+        let (vertex, _) = self.inner.as_ref();
+        vertex
+    }
+    pub fn index(&self) -> &wgpu::Buffer {
+        let (_, index) = self.inner.as_ref();
+        index
+    }
+
+    /// The part of `index` to draw, with `base_vertex` added to each index.
+    pub fn index_range(&self) -> std::ops::Range<u32> {
+        self.first_index..self.first_index + self.index_count as u32
+    }
+    pub fn base_vertex(&self) -> i32 {
+        self.base_vertex
+    }
+
+    /// Center and radius of a sphere around every vertex in model space, also
+    /// covering the morph targets at any weights up to 1. Found with Ritter's
+    /// algorithm, so it's a bit larger than the smallest possible one.
+    pub fn bounding_sphere(&self) -> (Vec3, f32) {
+        self.bounding_sphere
+    }
+
+    /// Smallest and largest coordinates of the vertices in model space,
+    /// without morph targets applied. Both are zero for a mesh without
+    /// vertices.
+    pub fn bounding_box(&self) -> (Vec3, Vec3) {
+        self.bounding_box
+    }
+
+    /// Vertex positions in model space, without morph targets applied.
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    /// Three per triangle, indexing `positions`. Empty for point clouds.
+    pub fn indices(&self) -> &[u16] {
+        &self.indices
+    }
+
+    /// Bytes taken by the morph targets on the GPU, one delta per target
+    /// and vertex.
+    pub fn morph_byte_size(&self) -> u64 {
+        match self.morph_buffer {
+            Some(_) => {
+                (self.morph_targets.len() * self.vertex_count * std::mem::size_of::<MorphDelta>())
+                    as u64
+            }
+            None => 0,
+        }
+    }
+}
+
+pub(crate) fn vertex_positions(vertices: &[Vertex]) -> Vec<Vec3> {
+    vertices
+        .iter()
+        .map(|it| Vec4::from(it.position).truncate())
+        .collect()
+}
+
+/// Skips NaN and infinite positions, which would poison the bounds and get
+/// the mesh culled for good, see `NonFiniteVertices`.
+pub(crate) fn bounding_box(positions: &[Vec3]) -> (Vec3, Vec3) {
+    let mut positions = positions.iter().copied().filter(|it| it.is_finite());
+    let first = match positions.next() {
+        Some(first) => first,
+        None => return (Vec3::ZERO, Vec3::ZERO),
+    };
+    positions.fold((first, first), |(min, max), it| (min.min(it), max.max(it)))
+}
+
+/// Skips non-finite positions and deltas, like `bounding_box`.
+pub(crate) fn bounding_sphere(positions: &[Vec3], morph_targets: &[MorphTarget]) -> (Vec3, f32) {
+    let positions = positions
+        .iter()
+        .copied()
+        .filter(|it| it.is_finite())
+        .collect::<Vec<_>>();
+    let farthest_from = |from: Vec3| {
+        positions
+            .iter()
+            .copied()
+            .max_by(|a, b| {
+                from.distance_squared(*a)
+                    .partial_cmp(&from.distance_squared(*b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(from)
+    };
+
+    let first = match positions.first() {
+        Some(&position) => position,
+        None => return (Vec3::ZERO, 0.0),
+    };
+    let a = farthest_from(first);
+    let b = farthest_from(a);
+    let mut center = (a + b) * 0.5;
+    let mut radius = a.distance(b) * 0.5;
+    for &point in &positions {
+        let distance = point.distance(center);
+        if distance > radius {
+            // grow just enough to cover both the old sphere and the point
+            let new_radius = (radius + distance) * 0.5;
+            center += (point - center) * ((new_radius - radius) / distance);
+            radius = new_radius;
+        }
+    }
+
+    let morph_reach: f32 = morph_targets
+        .iter()
+        .map(|target| {
+            target
+                .positions
+                .iter()
+                .map(|it| Vec3::from(*it).length())
+                .filter(|it| it.is_finite())
+                .fold(0.0, f32::max)
+        })
+        .sum();
+
+    (center, radius + morph_reach)
+}
+
+/// bind_group, sampler, view (shared by `with_sampler` copies)
+type TextureResources = (wgpu::BindGroup, wgpu::Sampler, Rc<wgpu::TextureView>);
+
+type TextureStore = RefCell<Vec<Option<StoredTexture>>>;
+
+/// An entry in the texture store of a `GraphicsContext`, shared by every
+/// copy of a `Texture`. Replacing what it points at, with
+/// `GraphicsContext::replace_texture`, changes them all at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+#[derive(Debug)]
+struct StoredTexture {
+    resources: Rc<TextureResources>,
+    sampler: SamplerOptions,
+    /// The entry whose texels this one samples differently, for
+    /// `Texture::with_sampler` copies, which follow it when it's replaced.
+    source: Option<TextureHandle>,
+}
+
+/// Frees the entry of a handle once the last copy of its texture is gone.
+#[derive(Debug)]
+struct TextureSlot {
+    handle: TextureHandle,
+    /// Keeps the source entry alive, see `StoredTexture::source`.
+    source: Option<Rc<TextureSlot>>,
+    store: Weak<TextureStore>,
+}
+
+impl Drop for TextureSlot {
+    fn drop(&mut self) {
+        if let Some(store) = self.store.upgrade() {
+            // a texture dropped while the store is being changed leaks its
+            // entry, nothing else is waiting on it
+            if let Ok(mut textures) = store.try_borrow_mut() {
+                textures[self.handle.0] = None;
+            }
+        }
+    }
+}
+
+/// A handle to a texture in the store of a `GraphicsContext`, together with
+/// how it's sampled. Copies share the handle.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    slot: Rc<TextureSlot>,
+    sampler_options: SamplerOptions,
+    /// Applied to the texture coordinates of meshes sampling this texture.
+    /// Part of the reference rather than of the texels, see
+    /// `with_uv_transform`.
+    pub uv_transform: UvTransform,
+    /// Size and format when created, see `GraphicsContext::replace_texture`.
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub mip_level_count: u32,
+    /// Like `Mesh::content_hash`, from `TextureDescription::content_hash`.
+    pub(crate) content_hash: Option<u64>,
+}
+
+impl Texture {
+    pub fn builder<'a>(
+        texels: &'a [u8],
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> TextureDescription<'a> {
+        TextureDescription::new(texels, width, height, format)
+    }
+    pub fn handle(&self) -> TextureHandle {
+        self.slot.handle
+    }
+
+    /// The handle of the texels this texture samples, the same for copies
+    /// from `with_sampler`, which don't have texels of their own.
+    pub fn texels_handle(&self) -> TextureHandle {
+        match &self.slot.source {
+            Some(source) => source.handle,
+            None => self.slot.handle,
+        }
+    }
+
+    /// Bytes of GPU memory taken by the texels of every mip level, from the
+    /// size and format. Textures have an uncompressed format, so each texel
+    /// is a block.
+    pub fn byte_size(&self) -> u64 {
+        let block_size = self.format.describe().block_size as u64;
+        (0..self.mip_level_count)
+            .map(|level| {
+                let width = (self.width >> level).max(1) as u64;
+                let height = (self.height >> level).max(1) as u64;
+                width * height * block_size
+            })
+            .sum()
+    }
+    pub fn sampler_options(&self) -> SamplerOptions {
+        self.sampler_options
+    }
+
+    /// The same texture, sharing the texels and sampler, with its texture
+    /// coordinates transformed by `transform`.
+    pub fn with_uv_transform(&self, transform: UvTransform) -> Texture {
+        Texture {
+            uv_transform: transform,
+            ..self.clone()
+        }
+    }
+
+    /// The same texture sampled with `options`, sharing the texels but not
+    /// equal to this one, since it has a handle of its own to bind a
+    /// different sampler. It follows this one when it's replaced.
+    pub fn with_sampler(&self, graphics: &GraphicsContext, options: SamplerOptions) -> Texture {
+        if options == self.sampler_options {
+            return self.clone();
+        }
+        let source = self
+            .slot
+            .source
+            .clone()
+            .unwrap_or_else(|| self.slot.clone());
+        let view = match &graphics.textures.borrow()[source.handle.0] {
+            Some(stored) => stored.resources.2.clone(),
+            None => unreachable!("texture handles are freed after their last copy"),
+        };
+        let (bind_group, sampler) = graphics.create_texture_bind_group(&view, options);
+        Texture {
+            slot: graphics.store_texture((bind_group, sampler, view), options, Some(source)),
+            sampler_options: options,
+            uv_transform: self.uv_transform,
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            mip_level_count: self.mip_level_count,
+            content_hash: None,
+        }
+    }
+}
+
+/// Waiting for the texels of a texture from `create_streamed_texture`.
+#[derive(Debug)]
+struct StreamedTexture {
+    texels: Receiver<Result<Vec<u8>, String>>,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    sampler: SamplerOptions,
+    /// Weak so textures that were dropped aren't uploaded.
+    slot: Weak<TextureSlot>,
+}
+
+/// Textures are equal when they share the same handle and sample it with the
+/// same UV transform, compared by bit pattern like material colors.
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        self.handle() == other.handle() && self.uv_transform.bits() == other.uv_transform.bits()
+    }
+}
+
+impl Eq for Texture {}
+
+impl Hash for Texture {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle().hash(state);
+        self.uv_transform.bits().hash(state);
+    }
+}
+
+/// Offset, rotation and scale of texture coordinates, from glTF's
+/// `KHR_texture_transform`. Scaled first, then rotated, then offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvTransform {
+    pub offset: [f32; 2],
+    /// Radians, counter-clockwise in UV space.
+    pub rotation: f32,
+    pub scale: [f32; 2],
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        }
+    }
+}
+
+impl UvTransform {
+    /// Rows of the 2x3 matrix doing the transform, w unused, as the shader
+    /// takes it.
+    fn rows(&self) -> [[f32; 4]; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let [x, y] = self.scale;
+        [
+            [cos * x, sin * y, self.offset[0], 0.0],
+            [-sin * x, cos * y, self.offset[1], 0.0],
+        ]
+    }
+
+    fn bits(&self) -> [u32; 5] {
+        [
+            self.offset[0].to_bits(),
+            self.offset[1].to_bits(),
+            self.rotation.to_bits(),
+            self.scale[0].to_bits(),
+            self.scale[1].to_bits(),
+        ]
+    }
+}
+
+/// How a texture is wrapped and filtered when sampled.
+#[derive(Debug, Clone, Copy)]
+pub struct SamplerOptions {
+    pub wrap_s: wgpu::AddressMode,
+    pub wrap_t: wgpu::AddressMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    /// See `TextureDescription::anisotropy`.
+    pub anisotropy: u8,
+    /// Range of mip levels sampled, see `TextureDescription::lod_clamp`.
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+    /// See `TextureDescription::lod_bias`.
+    pub lod_bias: f32,
+}
+
+/// Compared by bit pattern, like material colors, so they can be hashed.
+impl PartialEq for SamplerOptions {
+    fn eq(&self, other: &Self) -> bool {
+        (
+            self.wrap_s,
+            self.wrap_t,
+            self.min_filter,
+            self.mag_filter,
+            self.mipmap_filter,
+            self.anisotropy,
+        ) == (
+            other.wrap_s,
+            other.wrap_t,
+            other.min_filter,
+            other.mag_filter,
+            other.mipmap_filter,
+            other.anisotropy,
+        ) && self.lod_bits() == other.lod_bits()
+    }
+}
+
+impl Eq for SamplerOptions {}
+
+impl Hash for SamplerOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.wrap_s.hash(state);
+        self.wrap_t.hash(state);
+        self.min_filter.hash(state);
+        self.mag_filter.hash(state);
+        self.mipmap_filter.hash(state);
+        self.anisotropy.hash(state);
+        self.lod_bits().hash(state);
+    }
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            wrap_s: wgpu::AddressMode::ClampToEdge,
+            wrap_t: wgpu::AddressMode::ClampToEdge,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            anisotropy: 1,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: f32::MAX,
+            lod_bias: 0.0,
+        }
+    }
+}
+
+impl SamplerOptions {
+    /// Blocky texels, for pixel art.
+    pub fn nearest() -> Self {
+        Self {
+            min_filter: wgpu::FilterMode::Nearest,
+            mag_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Self::default()
+        }
+    }
+
+    /// Linear filtering between texels and mip levels, anisotropic at
+    /// `anisotropy` above 1, for surfaces seen at grazing angles like
+    /// terrain.
+    pub fn trilinear(anisotropy: u8) -> Self {
+        Self {
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy,
+            ..Self::default()
+        }
+    }
+
+    /// These filters with the wrapping of `other`, so textures made to tile
+    /// keep tiling.
+    pub fn with_wrapping_of(self, other: SamplerOptions) -> Self {
+        Self {
+            wrap_s: other.wrap_s,
+            wrap_t: other.wrap_t,
+            ..self
+        }
+    }
+
+    fn lod_bits(&self) -> [u32; 3] {
+        [
+            self.lod_min_clamp.to_bits(),
+            self.lod_max_clamp.to_bits(),
+            self.lod_bias.to_bits(),
+        ]
+    }
+}
+
+pub struct TextureDescription<'a> {
+    texels: &'a [u8],
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    sampler: SamplerOptions,
+}
+
+impl<'a> TextureDescription<'a> {
+    pub fn new(texels: &'a [u8], width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        Self {
+            texels,
+            width,
+            height,
+            format,
+            sampler: SamplerOptions::default(),
+        }
+    }
+    pub fn wrap_s(mut self, mode: wgpu::AddressMode) -> Self {
+        self.sampler.wrap_s = mode;
+        self
+    }
+    pub fn wrap_t(mut self, mode: wgpu::AddressMode) -> Self {
+        self.sampler.wrap_t = mode;
+        self
+    }
+    pub fn min_filter(mut self, mode: wgpu::FilterMode) -> Self {
+        self.sampler.min_filter = mode;
+        self
+    }
+    pub fn mag_filter(mut self, mode: wgpu::FilterMode) -> Self {
+        self.sampler.mag_filter = mode;
+        self
+    }
+    pub fn mipmap_filter(mut self, mode: wgpu::FilterMode) -> Self {
+        self.sampler.mipmap_filter = mode;
+        self
+    }
+    /// Anisotropic filtering level (1, 2, 4, 8 or 16); 1 disables it. Only
+    /// has a visible effect on textures with mipmaps.
+    pub fn anisotropy(mut self, level: u8) -> Self {
+        self.sampler.anisotropy = level;
+        self
+    }
+    /// Limits sampling to mip levels `min` to `max`, e.g. a `max` of 0 to
+    /// keep distant textures sharp. Unlimited by default.
+    pub fn lod_clamp(mut self, min: f32, max: f32) -> Self {
+        self.sampler.lod_min_clamp = min;
+        self.sampler.lod_max_clamp = max;
+        self
+    }
+    /// Added to the mip level picked when sampling, negative for sharper
+    /// and positive for blurrier textures. wgpu samplers have no bias, so
+    /// it's applied by the mesh shader and ignored elsewhere.
+    pub fn lod_bias(mut self, bias: f32) -> Self {
+        self.sampler.lod_bias = bias;
+        self
+    }
+    /// Replaces everything set by the other sampler methods.
+    pub fn sampler(mut self, options: SamplerOptions) -> Self {
+        self.sampler = options;
+        self
+    }
+    pub fn build(&self, graphics: &GraphicsContext) -> Texture {
+        graphics.create_texture(self)
+    }
+
+    /// Hash of the texels, size, format and sampler, which are all the
+    /// texture would be created from.
+    pub(crate) fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.texels.hash(&mut hasher);
+        (self.width, self.height, self.format, self.sampler).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Fades surfaces towards `color` with their depth from the camera, hiding
+/// where the scene ends at the far plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    pub color: [f32; 3],
+    pub falloff: FogFalloff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FogFalloff {
+    /// No fog before `start` units from the camera, fully fogged past `end`.
+    Linear { start: f32, end: f32 },
+    /// Visibility halves every `ln(2) / density` units.
+    Exponential { density: f32 },
+}
+
+/// Light reaching surfaces from every direction, blended from `sky` on the
+/// ones facing up (along `GraphicsContext::world_up`) to `ground` on the ones
+/// facing down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientLight {
+    pub sky: [f32; 3],
+    pub ground: [f32; 3],
+    /// Multiplies both colors.
+    pub intensity: f32,
+}
+
+impl AmbientLight {
+    /// The same light from every direction.
+    pub fn flat(color: [f32; 3], intensity: f32) -> Self {
+        Self {
+            sky: color,
+            ground: color,
+            intensity,
+        }
+    }
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        Self::flat([1.0; 3], 0.1)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct Uniforms {
+    mvp: [f32; 16],
+    transpose_inverse_modelview: [f32; 16],
+    inverse_view: [f32; 16],
+    light_direction: [f32; 4],
+    base_diffuse_color: [f32; 4],
+    tint: [f32; 4],
+    wireframe_color: [f32; 4],
+    /// w unused.
+    fog_color: [f32; 4],
+    /// Ambient colors times intensity, w unused.
+    ambient_sky: [f32; 4],
+    ambient_ground: [f32; 4],
+    /// The world up axis in view space, w unused.
+    up: [f32; 4],
+    /// Rows of each texture's UV transform, see `UvTransform::rows`.
+    diffuse_uv_transform: [[f32; 4]; 2],
+    normal_uv_transform: [[f32; 4]; 2],
+    orm_uv_transform: [[f32; 4]; 2],
+    /// `SamplerOptions::lod_bias` of the diffuse, normal and ORM textures,
+    /// w unused.
+    lod_bias: [f32; 4],
+    /// `specular_color_factor` times `specular_factor`, w unused.
+    specular_color: [f32; 4],
+    has_diffuse_texture: u32,
+    has_normal_texture: u32,
+    shaded: u32,
+    has_occlusion_texture: u32,
+    has_metallic_roughness_texture: u32,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    occlusion_strength: f32,
+    morph_target_count: u32,
+    vertex_count: u32,
+    fade: f32,
+    transmission_factor: f32,
+    ior: f32,
+    clearcoat_factor: f32,
+    clearcoat_roughness_factor: f32,
+    debug_view: u32,
+    /// 0 is no fog, 1 linear and 2 exponential.
+    fog_mode: u32,
+    fog_start: f32,
+    fog_end: f32,
+    fog_density: f32,
+    /// 1 when metallic surfaces reflect the reflection probe.
+    reflection_probe: u32,
+    _padding: [u32; 3],
+    morph_weights: [[f32; 4]; 2],
+}
+
+/// Size bucket and usage of the buffers in a `BufferPool`.
+type BufferKey = (u64, wgpu::BufferUsage);
+
+/// Uniform and instance buffers that were dropped, kept to be handed out
+/// again instead of allocating, so nodes, particle systems and the like that
+/// come and go don't churn buffers. Sizes are rounded up to a power of two
+/// so buffers of similar sizes share a bucket, and a bucket grows by
+/// allocating whenever it's empty.
+///
+/// Generic over the buffer only so the bookkeeping can be tested without a
+/// device.
+#[derive(Debug)]
+struct BufferPool<B = wgpu::Buffer> {
+    free: RefCell<HashMap<BufferKey, Vec<B>>>,
+    /// Dropped this frame, so possibly still used by its commands. Only
+    /// handed out again from the next one, see `recycle`.
+    released: RefCell<Vec<(BufferKey, B)>>,
+}
+
+impl<B> Default for BufferPool<B> {
+    fn default() -> Self {
+        Self {
+            free: RefCell::default(),
+            released: RefCell::default(),
+        }
+    }
+}
+
+impl<B> BufferPool<B> {
+    /// The bucket for `size` and `usage`, along with a free buffer from it if
+    /// there's one. Otherwise one of the bucket's size has to be created.
+    fn reuse(&self, size: u64, usage: wgpu::BufferUsage) -> (BufferKey, Option<B>) {
+        let key = (size.max(16).next_power_of_two(), usage);
+        let reused = self
+            .free
+            .borrow_mut()
+            .get_mut(&key)
+            .and_then(|buffers| buffers.pop());
+        (key, reused)
+    }
+
+    fn release(&self, key: BufferKey, buffer: B) {
+        self.released.borrow_mut().push((key, buffer));
+    }
+
+    /// Makes the buffers released during the last frame available again.
+    fn recycle(&self) {
+        let mut free = self.free.borrow_mut();
+        for (key, buffer) in self.released.borrow_mut().drain(..) {
+            free.entry(key).or_default().push(buffer);
+        }
+    }
+}
+
+impl BufferPool {
+    fn take(
+        self: &Rc<Self>,
+        device: &wgpu::Device,
+        label: &str,
+        size: u64,
+        usage: wgpu::BufferUsage,
+    ) -> PooledBuffer {
+        let (key, reused) = self.reuse(size, usage);
+        let buffer = reused.unwrap_or_else(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: key.0,
+                usage,
+                mapped_at_creation: false,
+            })
+        });
+        PooledBuffer {
+            buffer: Some(buffer),
+            key,
+            size,
+            pool: Rc::downgrade(self),
+        }
+    }
+}
+
+/// A buffer from a `BufferPool`, returned to it when dropped. Can be larger
+/// than asked for, `size` is what was.
+#[derive(Debug)]
+struct PooledBuffer {
+    buffer: Option<wgpu::Buffer>,
+    key: BufferKey,
+    size: u64,
+    pool: Weak<BufferPool>,
+}
+
+impl PooledBuffer {
+    /// Binds the size asked for rather than the whole buffer.
+    fn binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: self,
+            offset: 0,
+            size: std::num::NonZeroU64::new(self.size),
+        })
+    }
+}
+
+impl std::ops::Deref for PooledBuffer {
+    type Target = wgpu::Buffer;
+
+    fn deref(&self) -> &wgpu::Buffer {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let (Some(pool), Some(buffer)) = (self.pool.upgrade(), self.buffer.take()) {
+            pool.release(self.key, buffer);
+        }
+    }
+}
+
+/// The uniforms of a mesh as drawn by one node, rewritten on every draw. The
+/// buffer comes from the pool of the `GraphicsContext` and goes back to it
+/// when dropped, like those of particles, decals and screen quads.
+#[derive(Debug)]
+pub struct UniformBuffer {
+    buffer: PooledBuffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl UniformBuffer {
+    pub fn byte_size(&self) -> u64 {
+        std::mem::size_of::<Uniforms>() as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BillboardMode {
+    /// Always faces the camera, tilting with it when looking up or down.
+    Spherical,
+    /// Only turns around the world up axis, staying upright (trees, grass).
+    Cylindrical,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct ScreenQuadUniforms {
+    rect: [f32; 4],
+    uv_rect: [f32; 4],
+    color: [f32; 4],
+}
+
+/// Uniforms for drawing one textured quad in screen space. Like
+/// `UniformBuffer`, each quad drawn in a frame needs its own.
+#[derive(Debug)]
+pub struct ScreenQuad {
+    buffer: PooledBuffer,
+    bind_group: wgpu::BindGroup,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct DecalUniforms {
+    mvp: [f32; 16],
+    inverse_mvp: [f32; 16],
+    color: [f32; 4],
+    viewport_size: [f32; 2],
+    viewport_origin: [f32; 2],
+}
+
+/// Uniforms for projecting one decal. Like `UniformBuffer`, each decal drawn
+/// in a frame needs its own.
+#[derive(Debug)]
+pub struct Decal {
+    buffer: PooledBuffer,
+    bind_group: wgpu::BindGroup,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct OutlineUniforms {
+    mvp: [f32; 16],
+    color: [f32; 4],
+    viewport_size: [f32; 2],
+    width: f32,
+    _padding: f32,
+}
+
+/// Uniforms for outlining one mesh. Like `UniformBuffer`, each outline drawn
+/// in a frame needs its own.
+#[derive(Debug)]
+pub struct Outline {
+    buffer: PooledBuffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Lines of any color in world space, for debugging, kept from one frame to
+/// the next until cleared. Like `Outline`, each set drawn in a frame needs
+/// its own.
+#[derive(Debug)]
+pub struct DebugLines {
+    buffer: PooledBuffer,
+    bind_group: wgpu::BindGroup,
+    vertices: PooledBuffer,
+    capacity: usize,
+    /// Two per line.
+    lines: Vec<LineVertex>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl DebugLines {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// Adds a line from `from` to `to`, or nothing past the capacity.
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: [f32; 4]) {
+        if self.len() >= self.capacity {
+            return;
+        }
+        self.lines.push(LineVertex {
+            position: from.into(),
+            color,
+        });
+        self.lines.push(LineVertex {
+            position: to.into(),
+            color,
+        });
     }
 
-    pub fn get_current_frame<'gfx>(&'gfx mut self) -> Frame<'gfx> {
-        let frame = match self.swap_chain.get_current_frame() {
-            Ok(frame) => frame,
-            Err(_) => {
-                self.swap_chain = self
-                    .device
-                    .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
-                self.swap_chain
-                    .get_current_frame()
-                    .expect("Failed to acquire next swap chain texture!")
-            }
+    /// Adds the 12 edges of the axis aligned box from `min` to `max`.
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: [f32; 4]) {
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
         };
+        // corners differing in one bit share an edge
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+}
 
-        let encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DynamicResolution {
+    /// Smallest fraction of the window size the scene is rendered at.
+    pub min_scale: f32,
+    /// Largest fraction of the window size the scene is rendered at, usually 1.
+    pub max_scale: f32,
+    pub target_frame_time: Duration,
+}
 
-        Frame {
-            graphics: self,
-            frame,
-            encoder,
+impl DynamicResolution {
+    /// Relative difference from the target frame time that is ignored.
+    const TOLERANCE: f32 = 0.1;
+}
+
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        Self {
+            min_scale: 0.5,
+            max_scale: 1.0,
+            target_frame_time: Duration::from_secs_f32(1.0 / 60.0),
         }
     }
+}
 
-    fn get_quad_mesh(&self) -> &Mesh {
-        self.quad_mesh.get_or_init(|| {
-            macro_rules! v {
-                ($pos:expr, $norm:expr, $uv:expr) => {
-                    Vertex {
-                        position: $pos,
-                        normal: $norm,
-                        tex_coord: $uv,
-                    }
-                };
-            }
-            let vertices = [
-                v!([-1.0, -1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0]),
-                v!([1.0, -1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0]),
-                v!([-1.0, 1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 0.0]),
-                v!([1.0, 1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 0.0]),
-            ];
-            let indices = [0, 1, 2, 3, 2, 1];
-            let mesh = self.create_mesh(&vertices, &indices);
-            mesh
-        })
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoExposure {
+    /// Average luminance the exposed scene should have, middle gray by
+    /// default.
+    pub target_luminance: f32,
+    /// How fast the exposure adapts, as the fraction of the way to the target
+    /// covered per second is `1 - e^-speed`.
+    pub speed: f32,
+    /// Limits of the exposure, so a black or blinding frame doesn't take it
+    /// to extremes. Both should be above zero.
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            target_luminance: 0.18,
+            speed: 1.5,
+            min_exposure: 0.1,
+            max_exposure: 10.0,
+        }
     }
+}
 
-    fn get_default_texture(&self) -> &Texture {
-        self.default_texture.get_or_init(|| {
-            let pixels = [
-                255, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 255, 255u8,
-            ];
-            self.create_texture(&TextureDescription::new(
-                &pixels,
-                2,
-                2,
-                wgpu::TextureFormat::Rgba8Unorm,
-            ))
-        })
+/// The log luminance of the scene, halved down to a single texel holding its
+/// average, which is read back for auto exposure.
+struct LuminanceChain {
+    texture: wgpu::Texture,
+    /// One per mip level, from `LUMINANCE_SIZE` down to 1x1.
+    levels: Vec<RenderTarget>,
+    readback: wgpu::Buffer,
+    /// Set by the frame copying the last level to `readback`, until it's read.
+    copied: Cell<bool>,
+    mapping: Option<BufferMapping>,
+    /// The last luminance read back.
+    average: Option<f32>,
+}
+
+/// The luminance chain is half floats, without a type for them on the CPU.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
     }
 }
 
+/// One particle as drawn, a camera-facing square of `size` world units.
 #[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
-pub struct Vertex {
-    pub position: [f32; 4],
-    pub normal: [f32; 3],
-    pub tex_coord: [f32; 2],
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+pub struct ParticleInstance {
+    /// Center in world space, then size.
+    pub center_size: [f32; 4],
+    pub color: [f32; 4],
 }
 
-#[derive(Debug, Clone)]
-pub struct Mesh {
-    /// vertex_buffer, index_buffer
-    inner: Rc<(wgpu::Buffer, wgpu::Buffer)>,
-    pub index_count: usize,
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct PointUniforms {
+    model: [f32; 16],
+    view_projection: [f32; 16],
+    camera_right: [f32; 4],
+    camera_up: [f32; 4],
+    color: [f32; 4],
+    point_size: f32,
+    _padding: [f32; 3],
 }
 
-impl Mesh {
-    pub fn vertex(&self) -> &wgpu::Buffer {
-        let (vertex, _) = self.inner.as_ref();
-        vertex
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct ParticleUniforms {
+    view_projection: [f32; 16],
+    camera_right: [f32; 4],
+    camera_up: [f32; 4],
+}
+
+/// GPU side of a particle system. Like `UniformBuffer`, each one can only be
+/// drawn once per frame.
+#[derive(Debug)]
+pub struct ParticleBuffer {
+    instances: PooledBuffer,
+    capacity: usize,
+    uniforms: PooledBuffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ParticleBuffer {
+    pub fn capacity(&self) -> usize {
+        self.capacity
     }
-    pub fn index(&self) -> &wgpu::Buffer {
-        let (_, index) = self.inner.as_ref();
-        index
+}
+
+type BufferMapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>>>>;
+
+/// Hardware tests of whether nodes are hidden behind what's already in the
+/// depth buffer, by drawing their bounding boxes and checking whether any of
+/// it passed the depth test. Results are read back a frame or more later, to
+/// not wait on the GPU, so a node shows up a frame late when it stops being
+/// hidden.
+///
+/// wgpu has no occlusion queries, so these count fragment shader
+/// invocations instead, which is the same for a shader the GPU can test
+/// depth before running.
+pub struct OcclusionQueries {
+    query_set: wgpu::QuerySet,
+    /// The model-view-projection of each box tested this frame.
+    boxes: wgpu::Buffer,
+    results: wgpu::Buffer,
+    capacity: usize,
+    tested: Vec<(NodeId, Mat4)>,
+    /// Nodes whose results are in the results buffer, in query order.
+    resolved: Vec<NodeId>,
+    mapping: Option<BufferMapping>,
+    visible: HashMap<NodeId, bool>,
+}
+
+impl std::fmt::Debug for OcclusionQueries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OcclusionQueries")
+            .field("capacity", &self.capacity)
+            .field("tested", &self.tested)
+            .field("visible", &self.visible)
+            .finish()
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Texture {
-    bind_group: Rc<wgpu::BindGroup>,
-    pub width: u32,
-    pub height: u32,
+impl OcclusionQueries {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Whether any of `node`'s box passed the depth test the last time its
+    /// result was read back, and true if it never was.
+    pub fn is_visible(&self, node: NodeId) -> bool {
+        self.visible.get(&node).copied().unwrap_or(true)
+    }
+
+    /// Reads back whatever results are ready without waiting for the rest,
+    /// and forgets the boxes tested last frame. Call once per frame before
+    /// `test`.
+    pub fn update(&mut self, graphics: &GraphicsContext) {
+        self.tested.clear();
+        if self.resolved.is_empty() {
+            return;
+        }
+
+        // resolved last frame, which has been submitted by now
+        let results = &self.results;
+        let mapping = self
+            .mapping
+            .get_or_insert_with(|| Box::pin(results.slice(..).map_async(wgpu::MapMode::Read)));
+        graphics.device.poll(wgpu::Maintain::Poll);
+
+        let waker = noop_waker();
+        let mut context = std::task::Context::from_waker(&waker);
+        let ready = match mapping.as_mut().poll(&mut context) {
+            std::task::Poll::Ready(result) => result.is_ok(),
+            std::task::Poll::Pending => return,
+        };
+        self.mapping = None;
+
+        if ready {
+            let mapped = self.results.slice(..).get_mapped_range();
+            let counts: &[u64] = bytemuck::cast_slice(&mapped);
+            for (node, count) in self.resolved.iter().zip(counts) {
+                self.visible.insert(*node, *count > 0);
+            }
+            drop(mapped);
+            self.results.unmap();
+        }
+        self.resolved.clear();
+    }
+
+    /// Queues a test of the bounding box of `mesh` as drawn with `mvp`, to
+    /// be drawn by `Pass::draw_occlusion_queries` after the occluders. Every
+    /// node should be tested each frame, including the ones skipped for
+    /// being hidden, so they're drawn again once they aren't. Does nothing
+    /// past the capacity, or while last results are still being read.
+    pub fn test(&mut self, node: NodeId, mesh: &Mesh, mvp: Mat4) {
+        if self.tested.len() >= self.capacity || !self.resolved.is_empty() {
+            return;
+        }
+        let (center, radius) = mesh.bounding_sphere();
+        let cube =
+            Mat4::from_scale_rotation_translation(Vec3::splat(radius), Quat::IDENTITY, center);
+        self.tested.push((node, mvp * cube));
+    }
 }
 
-impl Texture {
-    pub fn bind_group(&self) -> &wgpu::BindGroup {
-        &self.bind_group
+/// A waker that does nothing, to check on a future without blocking.
+fn noop_waker() -> std::task::Waker {
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
     }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    // Safety: none of the functions use the data pointer.
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
 }
 
-pub struct TextureDescription<'a> {
-    texels: &'a [u8],
-    width: u32,
-    height: u32,
-    format: wgpu::TextureFormat,
-    wrap_s: wgpu::AddressMode,
-    wrap_t: wgpu::AddressMode,
-    min_filter: wgpu::FilterMode,
-    mag_filter: wgpu::FilterMode,
+/// What meshes are drawn as, see `GraphicsContext::set_debug_view`. The
+/// visualizations still go through exposure and tonemapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    /// World space normals as color, from -1..1 to 0..1 in each channel.
+    Normals,
+    /// The first UV set in red and green, wrapping past 1.
+    TexCoords,
+    /// Brighter the more meshes cover each pixel, hidden ones included.
+    Overdraw,
 }
 
-impl<'a> TextureDescription<'a> {
-    pub fn new(texels: &'a [u8], width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
-        Self {
-            texels,
-            width,
-            height,
-            format,
-            wrap_s: wgpu::AddressMode::ClampToEdge,
-            wrap_t: wgpu::AddressMode::ClampToEdge,
-            min_filter: wgpu::FilterMode::Linear,
-            mag_filter: wgpu::FilterMode::Linear,
+/// Operator mapping the HDR scene color to the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+    /// `c / (1 + c)`, never clips but flattens highlights.
+    Reinhard,
+    /// Filmic curve with more contrast, clipping very bright colors.
+    Aces,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct PostUniforms {
+    exposure: f32,
+    tonemap: u32,
+    texel_size: [f32; 2],
+    /// Part of the HDR target the scene was rendered to.
+    render_scale: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// An offscreen color texture rendered to by one pass and sampled by the next.
+struct RenderTarget {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// What a pass from `Frame::begin_render_pass` does with the color and depth
+/// drawn before it and what it leaves for the passes after it. `None`
+/// clears in the first scene pass of the frame and loads in later ones,
+/// storing either way. The stencil follows the depth.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PassOps {
+    pub color: Option<wgpu::Operations<wgpu::Color>>,
+    pub depth: Option<wgpu::Operations<f32>>,
+}
+
+impl PassOps {
+    /// Keeps the color but starts from an empty depth buffer and discards
+    /// it, so what's drawn shows over the earlier passes, e.g. gizmos. The
+    /// decal and transparent passes need the scene's depth, so it comes
+    /// after them.
+    pub const ON_TOP: PassOps = PassOps {
+        color: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Load,
+            store: true,
+        }),
+        depth: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: false,
+        }),
+    };
+}
+
+/// The scene is rendered to an HDR target by `begin_render_pass`, then
+/// post-processed to the swap chain, either when the overlay pass begins or
+/// on `submit`.
+pub struct Frame<'gfx> {
+    graphics: &'gfx GraphicsContext,
+    /// Where texture handles point this frame, see `texture_snapshot`.
+    textures: Vec<Option<Rc<TextureResources>>>,
+    frame: wgpu::SwapChainFrame,
+    encoder: wgpu::CommandEncoder,
+    /// Whether a scene pass began, so the next ones keep what it drew.
+    scene_drawn: bool,
+    transparency_drawn: bool,
+    post_processed: bool,
+}
+
+impl<'gfx> Frame<'gfx> {
+    /// With the default `ops`, the first scene pass of a frame clears color
+    /// and depth, later ones draw over it, e.g. the scene from another
+    /// camera in another viewport (see `Pass::set_viewport` and `flush`).
+    pub fn begin_render_pass<'frame>(&'frame mut self, ops: PassOps) -> Pass<'gfx, 'frame> {
+        let clear = !self.scene_drawn;
+        self.scene_drawn = true;
+        let color_ops = ops.color.unwrap_or(wgpu::Operations {
+            load: if clear {
+                wgpu::LoadOp::Clear(self.graphics.clear_color())
+            } else {
+                wgpu::LoadOp::Load
+            },
+            store: true,
+        });
+        let depth_ops = ops.depth.unwrap_or(wgpu::Operations {
+            load: if clear {
+                wgpu::LoadOp::Clear(1.0)
+            } else {
+                wgpu::LoadOp::Load
+            },
+            // kept for the transparent pass
+            store: true,
+        });
+
+        let mut pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.graphics.hdr_target.view,
+                resolve_target: None,
+                ops: color_ops,
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.graphics.depth_view,
+                depth_ops: Some(depth_ops),
+                stencil_ops: Some(wgpu::Operations {
+                    load: match depth_ops.load {
+                        wgpu::LoadOp::Clear(_) => wgpu::LoadOp::Clear(0),
+                        wgpu::LoadOp::Load => wgpu::LoadOp::Load,
+                    },
+                    store: depth_ops.store,
+                }),
+            }),
+        });
+
+        let (width, height) = self.graphics.render_size();
+        pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+
+        Pass {
+            graphics: self.graphics,
+            textures: &self.textures,
+            pass,
+            kind: PassKind::Scene,
+            viewport: [0.0, 0.0, width as f32, height as f32],
+            bound: BoundState::default(),
+            stats: RenderStats::default(),
         }
     }
-    pub fn wrap_s(mut self, mode: wgpu::AddressMode) -> Self {
-        self.wrap_s = mode;
-        self
+
+    /// Pass projecting decals onto what the scene pass drew, using its depth.
+    /// It has no depth buffer, so only decals can be drawn in it, and it has
+    /// to come before the transparent pass, which discards the depth.
+    pub fn begin_decal_pass<'frame>(&'frame mut self) -> Pass<'gfx, 'frame> {
+        let mut pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.graphics.hdr_target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        let (width, height) = self.graphics.render_size();
+        pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+
+        Pass {
+            graphics: self.graphics,
+            textures: &self.textures,
+            pass,
+            kind: PassKind::Decal,
+            viewport: [0.0, 0.0, width as f32, height as f32],
+            bound: BoundState::default(),
+            stats: RenderStats::default(),
+        }
     }
-    pub fn wrap_t(mut self, mode: wgpu::AddressMode) -> Self {
-        self.wrap_t = mode;
-        self
+
+    /// Pass for the blended materials (see `Material::blended`), drawn after the scene pass
+    /// and composited over it before tonemapping. `None` when
+    /// order-independent transparency is disabled, in which case they're
+    /// drawn with everything else. Like `begin_render_pass`, only the first
+    /// one of a frame clears.
+    pub fn begin_transparent_pass<'frame>(&'frame mut self) -> Option<Pass<'gfx, 'frame>> {
+        let (accum, revealage) = self.graphics.oit_targets.as_ref()?;
+        let clear = !self.transparency_drawn;
+        self.transparency_drawn = true;
+        let load = |color| {
+            if clear {
+                wgpu::LoadOp::Clear(color)
+            } else {
+                wgpu::LoadOp::Load
+            }
+        };
+
+        let mut pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &accum.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: load(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                },
+                wgpu::RenderPassColorAttachment {
+                    view: &revealage.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: load(wgpu::Color::WHITE),
+                        store: true,
+                    },
+                },
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.graphics.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        let (width, height) = self.graphics.render_size();
+        pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+
+        Some(Pass {
+            graphics: self.graphics,
+            textures: &self.textures,
+            pass,
+            kind: PassKind::Transparent,
+            viewport: [0.0, 0.0, width as f32, height as f32],
+            bound: BoundState::default(),
+            stats: RenderStats::default(),
+        })
     }
-    pub fn min_filter(mut self, mode: wgpu::FilterMode) -> Self {
-        self.min_filter = mode;
-        self
+
+    /// Pass drawing on top of the tonemapped image, for screen quads that
+    /// shouldn't be affected by exposure. It has no depth buffer, so meshes
+    /// and billboards can't be drawn in it.
+    pub fn begin_overlay_pass<'frame>(&'frame mut self) -> Pass<'gfx, 'frame> {
+        self.post_process();
+
+        let pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.frame.output.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        let (width, height) = self.graphics.screen_size();
+        Pass {
+            graphics: self.graphics,
+            textures: &self.textures,
+            pass,
+            kind: PassKind::Overlay,
+            viewport: [0.0, 0.0, width as f32, height as f32],
+            bound: BoundState::default(),
+            stats: RenderStats::default(),
+        }
     }
-    pub fn mag_filter(mut self, mode: wgpu::FilterMode) -> Self {
-        self.mag_filter = mode;
-        self
+
+    /// Submits what was drawn so far. Buffers written while drawing, like
+    /// `UniformBuffer`s, only take the values of their last write in each
+    /// submission, so drawing the same meshes again with other matrices,
+    /// e.g. from a second camera, has to come after this.
+    pub fn flush(&mut self) {
+        let encoder = self
+            .graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let encoder = std::mem::replace(&mut self.encoder, encoder);
+        self.graphics.queue.submit(Some(encoder.finish()));
     }
-}
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
-struct Uniforms {
-    mvp: [f32; 16],
-    transpose_inverse_modelview: [f32; 16],
-    light_direction: [f32; 4],
-    base_diffuse_color: [f32; 4],
-    has_diffuse_texture: u32,
-    has_normal_texture: u32,
-    shaded: u32,
-}
+    /// Copies the results of the boxes `Pass::draw_occlusion_queries` drew
+    /// this frame to be read back by the next `OcclusionQueries::update`.
+    pub fn resolve_occlusion_queries(&mut self, queries: &mut OcclusionQueries) {
+        if queries.tested.is_empty() || !queries.resolved.is_empty() {
+            return;
+        }
+        self.encoder.resolve_query_set(
+            &queries.query_set,
+            0..queries.tested.len() as u32,
+            &queries.results,
+            0,
+        );
+        queries.resolved = queries.tested.iter().map(|(node, _)| *node).collect();
+    }
+
+    pub fn submit(mut self) {
+        self.post_process();
+        self.graphics.queue.submit(Some(self.encoder.finish()));
+    }
+
+    /// Tonemaps the HDR target, straight to the swap chain or through FXAA,
+    /// after compositing the transparent pass over it.
+    fn post_process(&mut self) {
+        if self.post_processed {
+            return;
+        }
+        self.post_processed = true;
+
+        let graphics = self.graphics;
+        let (width, height) = graphics.screen_size();
+        let (render_width, render_height) = graphics.render_size();
+        let uniforms = PostUniforms {
+            exposure: graphics.exposure,
+            tonemap: match graphics.tonemap {
+                Tonemap::Reinhard => 0,
+                Tonemap::Aces => 1,
+            },
+            texel_size: [1.0 / width as f32, 1.0 / height as f32],
+            render_scale: [
+                render_width as f32 / width as f32,
+                render_height as f32 / height as f32,
+            ],
+            _padding: [0.0; 2],
+        };
+        graphics
+            .queue
+            .write_buffer(&graphics.post_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        let encoder = &mut self.encoder;
+        let output = &self.frame.output.view;
+        if let (true, Some((accum, revealage))) = (self.transparency_drawn, &graphics.oit_targets) {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &graphics.hdr_target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_viewport(
+                0.0,
+                0.0,
+                render_width as f32,
+                render_height as f32,
+                0.0,
+                1.0,
+            );
+
+            let mesh = graphics.get_quad_mesh();
+            pass.set_pipeline(&graphics.oit_resolve_pipeline);
+            pass.set_bind_group(0, &graphics.post_bind_group, &[]);
+            pass.set_bind_group(1, &accum.bind_group, &[]);
+            pass.set_bind_group(2, &revealage.bind_group, &[]);
+            pass.set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+            pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+            pass.draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
+        }
 
-#[derive(Debug)]
-pub struct UniformBuffer {
-    buffer: wgpu::Buffer,
-    bind_group: wgpu::BindGroup,
-}
+        if let Some(chain) = &graphics.luminance_chain {
+            // skipped while the last copy is still being read
+            if !chain.copied.get() {
+                Self::draw_post_pass(
+                    encoder,
+                    graphics,
+                    &graphics.luminance_pipeline,
+                    &graphics.hdr_target,
+                    &chain.levels[0].view,
+                );
+                for pair in chain.levels.windows(2) {
+                    Self::draw_post_pass(
+                        encoder,
+                        graphics,
+                        &graphics.downsample_pipeline,
+                        &pair[0],
+                        &pair[1].view,
+                    );
+                }
+                encoder.copy_texture_to_buffer(
+                    wgpu::ImageCopyTexture {
+                        texture: &chain.texture,
+                        mip_level: chain.levels.len() as u32 - 1,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    wgpu::ImageCopyBuffer {
+                        buffer: &chain.readback,
+                        layout: wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: std::num::NonZeroU32::new(
+                                wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+                            ),
+                            rows_per_image: None,
+                        },
+                    },
+                    wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                );
+                chain.copied.set(true);
+            }
+        }
 
-pub struct Frame<'gfx> {
-    graphics: &'gfx GraphicsContext,
-    frame: wgpu::SwapChainFrame,
-    encoder: wgpu::CommandEncoder,
-}
+        if graphics.fxaa {
+            Self::draw_post_pass(
+                encoder,
+                graphics,
+                &graphics.tonemap_pipeline,
+                &graphics.hdr_target,
+                &graphics.ldr_target.view,
+            );
+            Self::draw_post_pass(
+                encoder,
+                graphics,
+                &graphics.fxaa_pipeline,
+                &graphics.ldr_target,
+                output,
+            );
+        } else {
+            Self::draw_post_pass(
+                encoder,
+                graphics,
+                &graphics.tonemap_pipeline,
+                &graphics.hdr_target,
+                output,
+            );
+        }
+    }
 
-impl<'gfx> Frame<'gfx> {
-    pub fn begin_render_pass<'frame>(&'frame mut self) -> Pass<'gfx, 'frame> {
-        let pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+    fn draw_post_pass(
+        encoder: &mut wgpu::CommandEncoder,
+        graphics: &GraphicsContext,
+        pipeline: &wgpu::RenderPipeline,
+        source: &RenderTarget,
+        target: &wgpu::TextureView,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &self.frame.output.view,
+                view: target,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                     store: true,
                 },
             }],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.graphics.depth_view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: false,
-                }),
-                stencil_ops: None,
-            }),
+            depth_stencil_attachment: None,
         });
 
-        Pass {
-            graphics: self.graphics,
-            pass,
-        }
+        let mesh = graphics.get_quad_mesh();
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &graphics.post_bind_group, &[]);
+        pass.set_bind_group(1, &source.bind_group, &[]);
+        pass.set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+        pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+        pass.draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
     }
+}
 
-    pub fn submit(self) {
-        self.graphics.queue.submit(Some(self.encoder.finish()));
-    }
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    /// Pipeline, bind group and buffer bindings actually recorded.
+    pub state_changes: u32,
+}
+
+/// What is currently bound on the pass, by address, so redundant binds can be
+/// skipped.
+#[derive(Default)]
+struct BoundState {
+    pipeline: Option<*const wgpu::RenderPipeline>,
+    diffuse: Option<*const wgpu::BindGroup>,
+    normal: Option<*const wgpu::BindGroup>,
+    orm: Option<*const wgpu::BindGroup>,
+    vertex: Option<*const wgpu::Buffer>,
+    index: Option<*const wgpu::Buffer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PassKind {
+    Scene,
+    Transparent,
+    Decal,
+    Overlay,
 }
 
 pub struct Pass<'gfx, 'frame> {
     graphics: &'gfx GraphicsContext,
+    textures: &'frame [Option<Rc<TextureResources>>],
     pass: wgpu::RenderPass<'frame>,
+    kind: PassKind,
+    /// Region drawn to as (x, y, width, height) in pixels of the pass's
+    /// targets.
+    viewport: [f32; 4],
+    bound: BoundState,
+    stats: RenderStats,
 }
 
 impl<'gfx: 'frame, 'frame> Pass<'gfx, 'frame> {
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Where `texture`'s handle pointed as the frame began.
+    fn texture_bind_group(&self, texture: &Texture) -> &'frame wgpu::BindGroup {
+        let textures: &'frame [_] = self.textures;
+        match textures.get(texture.handle().0).and_then(Option::as_deref) {
+            Some((bind_group, _, _)) => bind_group,
+            None => panic!("textures should be created before the frame drawing them begins"),
+        }
+    }
+
+    /// Limits drawing to `rect`, given in pixels as (x, y, width, height)
+    /// from the top left corner of the screen, e.g. to draw the scene from
+    /// two cameras side by side. The projection should use the aspect ratio
+    /// of `rect`. Passes start out covering the whole screen. Screen quads
+    /// are placed relative to the whole screen regardless.
+    pub fn set_viewport(&mut self, rect: [f32; 4]) {
+        let (scale_x, scale_y) = match self.kind {
+            PassKind::Overlay => (1.0, 1.0),
+            _ => {
+                let (width, height) = self.graphics.screen_size();
+                let (render_width, render_height) = self.graphics.render_size();
+                (
+                    render_width as f32 / width as f32,
+                    render_height as f32 / height as f32,
+                )
+            }
+        };
+        let [x, y, width, height] = rect;
+        self.viewport = [x * scale_x, y * scale_y, width * scale_x, height * scale_y];
+        let [x, y, width, height] = self.viewport;
+        self.pass.set_viewport(x, y, width, height, 0.0, 1.0);
+    }
+
+    /// Whether meshes with `material` belong in this pass: with
+    /// order-independent transparency, the transparent pass takes the
+    /// `blended` ones and the scene pass the rest.
+    pub fn draws(&self, material: &Material) -> bool {
+        match self.kind {
+            PassKind::Scene => !(material.blended() && self.graphics.draws_transparent_pass()),
+            PassKind::Transparent => material.blended() && self.graphics.draws_transparent_pass(),
+            PassKind::Decal | PassKind::Overlay => false,
+        }
+    }
+
+    fn set_pipeline(&mut self, pipeline: &'frame wgpu::RenderPipeline) {
+        let key: *const wgpu::RenderPipeline = pipeline;
+        if self.bound.pipeline != Some(key) {
+            self.pass.set_pipeline(pipeline);
+            // the pipelines don't share bind group layouts, so every group
+            // has to be bound again
+            self.bound = BoundState {
+                pipeline: Some(key),
+                ..BoundState::default()
+            };
+            self.stats.state_changes += 1;
+        }
+    }
+
+    /// Whether point clouds belong in this pass, which is the scene pass
+    /// whatever their material, as they're always opaque.
+    pub fn draws_points(&self) -> bool {
+        self.kind == PassKind::Scene
+    }
+
+    /// Whether `render_wireframe` should be called for the meshes drawn in
+    /// this pass.
+    pub fn draws_wireframe(&self) -> bool {
+        self.kind == PassKind::Scene
+            && self.graphics.wireframe_overlay.is_some()
+            && self.graphics.wireframe_pipeline.is_some()
+    }
+
+    /// Draws the edges of a mesh already drawn with `render_mesh` this frame,
+    /// reusing the uniforms written there.
+    pub fn render_wireframe(&mut self, mesh: &'frame Mesh, uniform_buffer: &'frame UniformBuffer) {
+        let pipeline = match &self.graphics.wireframe_pipeline {
+            _ if mesh.topology == Topology::Points => return,
+            Some(pipeline) if self.draws_wireframe() => pipeline,
+            _ => return,
+        };
+        self.set_pipeline(pipeline);
+
+        self.pass.set_bind_group(0, &uniform_buffer.bind_group, &[]);
+        self.stats.state_changes += 1;
+
+        let index_key: *const wgpu::Buffer = mesh.index();
+        if self.bound.index != Some(index_key) {
+            self.pass
+                .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+            self.bound.index = Some(index_key);
+            self.stats.state_changes += 1;
+        }
+        let vertex_key: *const wgpu::Buffer = mesh.vertex();
+        if self.bound.vertex != Some(vertex_key) {
+            self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+            self.bound.vertex = Some(vertex_key);
+            self.stats.state_changes += 1;
+        }
+
+        self.pass
+            .draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
+        self.stats.draw_calls += 1;
+    }
+
+    /// Projects `texture`, multiplied by `color`, onto the scene surfaces
+    /// inside the box that `transform` makes of the cube from -1 to 1,
+    /// along the box's z axis. The texture's top is towards its +y. Only
+    /// valid in a pass from `Frame::begin_decal_pass`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_decal(
+        &mut self,
+        decal: &'frame Decal,
+        texture: &'frame Texture,
+        transform: Mat4,
+        color: [f32; 4],
+        perspective: Mat4,
+        view: Mat4,
+    ) {
+        let [x, y, width, height] = self.viewport;
+        let mvp = perspective * view * transform;
+        let uniforms = DecalUniforms {
+            mvp: mvp.to_cols_array(),
+            inverse_mvp: mvp.inverse().to_cols_array(),
+            color,
+            viewport_size: [width, height],
+            viewport_origin: [x, y],
+        };
+        self.graphics
+            .queue
+            .write_buffer(&decal.buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        let mesh = self.graphics.get_cube_mesh();
+
+        self.set_pipeline(&self.graphics.decal_pipeline);
+        self.pass.set_bind_group(0, &decal.bind_group, &[]);
+        let bind_group = self.texture_bind_group(texture);
+        self.pass.set_bind_group(1, bind_group, &[]);
+        self.bound.diffuse = Some(bind_group);
+        self.pass
+            .set_bind_group(2, &self.graphics.depth_bind_group, &[]);
+        self.stats.state_changes += 3;
+
+        let index_key: *const wgpu::Buffer = mesh.index();
+        if self.bound.index != Some(index_key) {
+            self.pass
+                .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+            self.bound.index = Some(index_key);
+            self.stats.state_changes += 1;
+        }
+        let vertex_key: *const wgpu::Buffer = mesh.vertex();
+        if self.bound.vertex != Some(vertex_key) {
+            self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+            self.bound.vertex = Some(vertex_key);
+            self.stats.state_changes += 1;
+        }
+
+        self.pass
+            .draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
+        self.stats.draw_calls += 1;
+    }
+
+    /// Draws a `width` pixels wide outline of `color` around `mesh`, e.g. to
+    /// highlight a selection, over everything so it shows through whatever
+    /// is in front of it. The mesh is marked in the stencil buffer, then
+    /// drawn again pushed out along its normals where it isn't marked, so
+    /// meshes with hard edges get gaps at their corners. Morph targets are
+    /// ignored, and point clouds get no outline. Only valid in a pass from
+    /// `Frame::begin_render_pass`, after the scene.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_outline(
+        &mut self,
+        outline: &'frame Outline,
+        mesh: &'frame Mesh,
+        model: Mat4,
+        perspective: Mat4,
+        view: Mat4,
+        color: [f32; 4],
+        width: f32,
+    ) {
+        if mesh.topology == Topology::Points {
+            return;
+        }
+        let [_, _, viewport_width, viewport_height] = self.viewport;
+        let uniforms = OutlineUniforms {
+            mvp: (perspective * view * model).to_cols_array(),
+            color,
+            viewport_size: [viewport_width, viewport_height],
+            width,
+            _padding: 0.0,
+        };
+        self.graphics
+            .queue
+            .write_buffer(&outline.buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        self.pass.set_stencil_reference(1);
+        self.stats.state_changes += 1;
+        let graphics = self.graphics;
+        for pipeline in &[&graphics.outline_mask_pipeline, &graphics.outline_pipeline] {
+            self.set_pipeline(pipeline);
+            self.pass.set_bind_group(0, &outline.bind_group, &[]);
+            self.stats.state_changes += 1;
+
+            let index_key: *const wgpu::Buffer = mesh.index();
+            if self.bound.index != Some(index_key) {
+                self.pass
+                    .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+                self.bound.index = Some(index_key);
+                self.stats.state_changes += 1;
+            }
+            let vertex_key: *const wgpu::Buffer = mesh.vertex();
+            if self.bound.vertex != Some(vertex_key) {
+                self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+                self.bound.vertex = Some(vertex_key);
+                self.stats.state_changes += 1;
+            }
+
+            self.pass
+                .draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
+            self.stats.draw_calls += 1;
+        }
+    }
+
+    /// Draws `lines` over everything, hidden or not. Only valid in a pass
+    /// from `Frame::begin_render_pass`.
+    pub fn draw_debug_lines(&mut self, lines: &'frame DebugLines, perspective: Mat4, view: Mat4) {
+        if self.kind != PassKind::Scene || lines.is_empty() {
+            return;
+        }
+        let view_projection = (perspective * view).to_cols_array();
+        self.graphics.queue.write_buffer(
+            &lines.buffer,
+            0,
+            bytemuck::cast_slice(&[view_projection]),
+        );
+        self.graphics
+            .queue
+            .write_buffer(&lines.vertices, 0, bytemuck::cast_slice(&lines.lines));
+
+        self.set_pipeline(&self.graphics.debug_lines_pipeline);
+        self.pass.set_bind_group(0, &lines.bind_group, &[]);
+        self.pass.set_vertex_buffer(0, lines.vertices.slice(..));
+        self.bound.vertex = Some(&*lines.vertices);
+        self.stats.state_changes += 2;
+
+        self.pass.draw(0..lines.lines.len() as u32, 0..1);
+        self.stats.draw_calls += 1;
+    }
+
+    /// Draws the bounding boxes queued with `OcclusionQueries::test`, only
+    /// against the depth buffer, so it should come after the occluders. Only
+    /// valid in a pass from `Frame::begin_render_pass`, and followed by
+    /// `Frame::resolve_occlusion_queries` once it ends.
+    pub fn draw_occlusion_queries(&mut self, queries: &'frame OcclusionQueries) {
+        let pipeline = match &self.graphics.occlusion_pipeline {
+            Some(pipeline) if !queries.tested.is_empty() => pipeline,
+            _ => return,
+        };
+
+        let boxes = queries
+            .tested
+            .iter()
+            .map(|(_, mvp)| mvp.to_cols_array())
+            .collect::<Vec<_>>();
+        self.graphics
+            .queue
+            .write_buffer(&queries.boxes, 0, bytemuck::cast_slice(&boxes));
+
+        let mesh = self.graphics.get_cube_mesh();
+
+        self.set_pipeline(pipeline);
+        self.pass
+            .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+        self.bound.index = Some(mesh.index());
+        self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+        self.bound.vertex = Some(mesh.vertex());
+        // the instance slot isn't tracked, like with particles
+        self.pass.set_vertex_buffer(1, queries.boxes.slice(..));
+        self.stats.state_changes += 4;
+
+        for i in 0..boxes.len() as u32 {
+            self.pass
+                .begin_pipeline_statistics_query(&queries.query_set, i);
+            self.pass
+                .draw_indexed(mesh.index_range(), mesh.base_vertex(), i..i + 1);
+            self.pass.end_pipeline_statistics_query();
+            self.stats.draw_calls += 1;
+        }
+    }
+
+    /// Draws `instances` (at most the buffer's capacity) in one instanced
+    /// call, multiplying `texture` (white without one) by each one's color.
+    /// They're alpha blended without writing depth, so they should be drawn
+    /// after opaque meshes and sorted back to front.
+    pub fn render_particles(
+        &mut self,
+        buffer: &'frame ParticleBuffer,
+        instances: &[ParticleInstance],
+        texture: Option<&'frame Texture>,
+        perspective: Mat4,
+        view: Mat4,
+    ) {
+        let count = instances.len().min(buffer.capacity);
+        if count == 0 {
+            return;
+        }
+
+        // the rows of the view rotation are the camera axes in world space
+        let uniforms = ParticleUniforms {
+            view_projection: (perspective * view).to_cols_array(),
+            camera_right: view.row(0).truncate().extend(0.0).into(),
+            camera_up: view.row(1).truncate().extend(0.0).into(),
+        };
+        let queue = &self.graphics.queue;
+        queue.write_buffer(&buffer.uniforms, 0, bytemuck::cast_slice(&[uniforms]));
+        queue.write_buffer(
+            &buffer.instances,
+            0,
+            bytemuck::cast_slice(&instances[..count]),
+        );
+
+        let texture = texture.unwrap_or_else(|| self.graphics.empty_texture());
+        let mesh = self.graphics.get_quad_mesh();
+
+        self.set_pipeline(&self.graphics.particle_pipeline);
+        self.pass.set_bind_group(0, &buffer.bind_group, &[]);
+        let bind_group = self.texture_bind_group(texture);
+        self.pass.set_bind_group(1, bind_group, &[]);
+        self.bound.diffuse = Some(bind_group);
+        self.pass
+            .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+        self.bound.index = Some(mesh.index());
+        self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+        self.bound.vertex = Some(mesh.vertex());
+        // the instance slot isn't tracked, nothing else uses it
+        self.pass.set_vertex_buffer(1, buffer.instances.slice(..));
+        self.stats.state_changes += 5;
+
+        self.pass
+            .draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..count as u32);
+        self.stats.draw_calls += 1;
+    }
+
+    /// Draws `texture` over the scene at `rect`, given in pixels as (x, y,
+    /// width, height) from the top left corner of the screen. `uv_rect` is
+    /// the part of the texture to show as (left, top, right, bottom) and
+    /// `color` multiplies it, alpha included. Only valid in a pass from
+    /// `Frame::begin_overlay_pass`.
+    pub fn draw_screen_quad(
+        &mut self,
+        quad: &'frame ScreenQuad,
+        texture: &'frame Texture,
+        rect: [f32; 4],
+        uv_rect: [f32; 4],
+        color: [f32; 4],
+    ) {
+        let (width, height) = self.graphics.screen_size();
+        let (width, height) = (width as f32, height as f32);
+        let [x, y, w, h] = rect;
+        let uniforms = ScreenQuadUniforms {
+            rect: [
+                x / width * 2.0 - 1.0,
+                1.0 - y / height * 2.0,
+                (x + w) / width * 2.0 - 1.0,
+                1.0 - (y + h) / height * 2.0,
+            ],
+            uv_rect,
+            color,
+        };
+        self.graphics
+            .queue
+            .write_buffer(&quad.buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        let mesh = self.graphics.get_quad_mesh();
+
+        self.set_pipeline(&self.graphics.screen_pipeline);
+        self.pass.set_bind_group(0, &quad.bind_group, &[]);
+        let bind_group = self.texture_bind_group(texture);
+        self.pass.set_bind_group(1, bind_group, &[]);
+        self.bound.diffuse = Some(bind_group);
+        self.stats.state_changes += 2;
+
+        let index_key: *const wgpu::Buffer = mesh.index();
+        if self.bound.index != Some(index_key) {
+            self.pass
+                .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+            self.bound.index = Some(index_key);
+            self.stats.state_changes += 1;
+        }
+        let vertex_key: *const wgpu::Buffer = mesh.vertex();
+        if self.bound.vertex != Some(vertex_key) {
+            self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+            self.bound.vertex = Some(vertex_key);
+            self.stats.state_changes += 1;
+        }
+
+        self.pass
+            .draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
+        self.stats.draw_calls += 1;
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn render_mesh(
         &mut self,
         mesh: &'frame Mesh,
@@ -554,18 +5124,130 @@ impl<'gfx: 'frame, 'frame> Pass<'gfx, 'frame> {
         perspective: Mat4,
         view: Mat4,
         model: Mat4,
+        morph_weights: &[f32],
+        fade: f32,
+        tint: [f32; 4],
     ) {
-        let diffuse = material.diffuse.as_ref();
+        if mesh.topology == Topology::Points {
+            let color = Vec4::from(material.base_diffuse_color) * Vec4::from(tint);
+            self.render_points(mesh, uniform_buffer, color.into(), perspective, view, model);
+            return;
+        }
+
+        let diffuse = match material.diffuse.as_ref() {
+            None if self.graphics.checker_untextured => Some(self.graphics.checker_texture()),
+            diffuse => diffuse,
+        };
         let normal = material.normal.as_ref();
 
+        let fog = self.graphics.fog;
+        let (fog_mode, fog_start, fog_end, fog_density) = match fog.map(|it| it.falloff) {
+            None => (0, 0.0, 0.0, 0.0),
+            Some(FogFalloff::Linear { start, end }) => (1, start, end, 0.0),
+            Some(FogFalloff::Exponential { density }) => (2, 0.0, 0.0, density),
+        };
+
         let uniforms = Uniforms {
             mvp: (perspective * view * model).to_cols_array(),
             transpose_inverse_modelview: (view * model).inverse().transpose().to_cols_array(),
+            inverse_view: view.inverse().to_cols_array(),
             light_direction: [-1.0, 0.4, 0.9f32, 0.0],
             base_diffuse_color: material.base_diffuse_color,
+            tint,
+            wireframe_color: self.graphics.wireframe_overlay.unwrap_or([0.0; 4]),
+            fog_color: Vec3::from(fog.map_or([0.0; 3], |it| it.color))
+                .extend(0.0)
+                .into(),
+            ambient_sky: {
+                let ambient = self.graphics.ambient_light;
+                (Vec3::from(ambient.sky) * ambient.intensity)
+                    .extend(0.0)
+                    .into()
+            },
+            ambient_ground: {
+                let ambient = self.graphics.ambient_light;
+                (Vec3::from(ambient.ground) * ambient.intensity)
+                    .extend(0.0)
+                    .into()
+            },
+            up: view
+                .transform_vector3(self.graphics.world_up)
+                .extend(0.0)
+                .into(),
+            diffuse_uv_transform: diffuse
+                .map_or(UvTransform::default(), |it| it.uv_transform)
+                .rows(),
+            normal_uv_transform: normal
+                .map_or(UvTransform::default(), |it| it.uv_transform)
+                .rows(),
+            orm_uv_transform: material
+                .orm
+                .as_ref()
+                .map_or(UvTransform::default(), |it| it.texture.uv_transform)
+                .rows(),
+            lod_bias: {
+                let bias = |texture: Option<&Texture>| {
+                    texture.map_or(0.0, |it| it.sampler_options().lod_bias)
+                };
+                [
+                    bias(diffuse),
+                    bias(normal),
+                    bias(material.orm.as_ref().map(|it| &it.texture)),
+                    0.0,
+                ]
+            },
+            specular_color: {
+                let [r, g, b] = material.specular_color_factor;
+                let factor = material.specular_factor;
+                [r * factor, g * factor, b * factor, 0.0]
+            },
             has_diffuse_texture: if diffuse.is_some() { 1 } else { 0 },
             has_normal_texture: if normal.is_some() { 1 } else { 0 },
             shaded: if material.shaded { 1 } else { 0 },
+            has_occlusion_texture: match &material.orm {
+                Some(orm) if orm.has_occlusion => 1,
+                _ => 0,
+            },
+            has_metallic_roughness_texture: match &material.orm {
+                Some(orm) if orm.has_metallic_roughness => 1,
+                _ => 0,
+            },
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            occlusion_strength: material.occlusion_strength,
+            morph_target_count: mesh.morph_targets.len().min(morph_weights.len()) as u32,
+            vertex_count: mesh.vertex_count as u32,
+            fade: fade.clamp(0.0, 1.0),
+            transmission_factor: material.transmission_factor.clamp(0.0, 1.0),
+            ior: material.ior,
+            clearcoat_factor: material.clearcoat_factor.clamp(0.0, 1.0),
+            clearcoat_roughness_factor: material.clearcoat_roughness_factor.clamp(0.0, 1.0),
+            debug_view: match self.graphics.debug_view {
+                DebugView::Shaded | DebugView::Overdraw => 0,
+                DebugView::Normals => 1,
+                DebugView::TexCoords => 2,
+            },
+            fog_mode,
+            fog_start,
+            fog_end,
+            fog_density,
+            reflection_probe: if self.graphics.reflection_probe_position.is_some() {
+                1
+            } else {
+                0
+            },
+            _padding: [0; 3],
+            morph_weights: {
+                let mut weights = [[0.0; 4]; 2];
+                for (i, weight) in morph_weights
+                    .iter()
+                    .take(MorphTarget::MAX_COUNT)
+                    .enumerate()
+                {
+                    weights[i / 4][i % 4] = *weight;
+                }
+                weights
+            },
         };
         self.graphics.queue.write_buffer(
             &uniform_buffer.buffer,
@@ -573,19 +5255,132 @@ impl<'gfx: 'frame, 'frame> Pass<'gfx, 'frame> {
             bytemuck::cast_slice(&[uniforms]),
         );
 
-        let diffuse = diffuse.unwrap_or_else(|| self.graphics.get_default_texture());
-        let normal = normal.unwrap_or_else(|| self.graphics.get_default_texture());
+        let diffuse = diffuse.unwrap_or_else(|| self.graphics.empty_texture());
+        let normal = normal.unwrap_or_else(|| self.graphics.empty_normal_texture());
+        let orm = match &material.orm {
+            Some(orm) => &orm.texture,
+            None => self.graphics.empty_texture(),
+        };
+
+        let pipeline = match (self.kind, &material.depth_bias) {
+            _ if self.graphics.debug_view == DebugView::Overdraw => {
+                &self.graphics.overdraw_pipeline
+            }
+            (PassKind::Transparent, Some(bias)) => &bias.inner.1,
+            (PassKind::Transparent, None) => &self.graphics.oit_pipeline,
+            (_, Some(bias)) => &bias.inner.0,
+            (_, None) => &self.graphics.pipeline,
+        };
+        self.set_pipeline(pipeline);
 
-        self.pass.set_pipeline(&self.graphics.pipeline);
+        // every mesh has its own uniforms, so this one always changes
         self.pass.set_bind_group(0, &uniform_buffer.bind_group, &[]);
-        self.pass.set_bind_group(1, diffuse.bind_group(), &[]);
-        self.pass.set_bind_group(2, normal.bind_group(), &[]);
+        self.stats.state_changes += 1;
+
+        let diffuse = self.texture_bind_group(diffuse);
+        let diffuse_key: *const wgpu::BindGroup = diffuse;
+        if self.bound.diffuse != Some(diffuse_key) {
+            self.pass.set_bind_group(1, diffuse, &[]);
+            self.bound.diffuse = Some(diffuse_key);
+            self.stats.state_changes += 1;
+        }
+        let normal = self.texture_bind_group(normal);
+        let normal_key: *const wgpu::BindGroup = normal;
+        if self.bound.normal != Some(normal_key) {
+            self.pass.set_bind_group(2, normal, &[]);
+            self.bound.normal = Some(normal_key);
+            self.stats.state_changes += 1;
+        }
+        let orm = self.texture_bind_group(orm);
+        let orm_key: *const wgpu::BindGroup = orm;
+        if self.bound.orm != Some(orm_key) {
+            self.pass.set_bind_group(3, orm, &[]);
+            self.bound.orm = Some(orm_key);
+            self.stats.state_changes += 1;
+        }
+        let index_key: *const wgpu::Buffer = mesh.index();
+        if self.bound.index != Some(index_key) {
+            self.pass
+                .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
+            self.bound.index = Some(index_key);
+            self.stats.state_changes += 1;
+        }
+        let vertex_key: *const wgpu::Buffer = mesh.vertex();
+        if self.bound.vertex != Some(vertex_key) {
+            self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
+            self.bound.vertex = Some(vertex_key);
+            self.stats.state_changes += 1;
+        }
+
         self.pass
-            .set_index_buffer(mesh.index().slice(..), wgpu::IndexFormat::Uint16);
-        self.pass.set_vertex_buffer(0, mesh.vertex().slice(..));
-        self.pass.draw_indexed(0..mesh.index_count as u32, 0, 0..1);
+            .draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
+        self.stats.draw_calls += 1;
+    }
+
+    /// Draws a point cloud from `GraphicsContext::create_point_mesh` with the
+    /// quad mesh instanced per point, facing the camera.
+    fn render_points(
+        &mut self,
+        mesh: &'frame Mesh,
+        uniform_buffer: &'frame UniformBuffer,
+        color: [f32; 4],
+        perspective: Mat4,
+        view: Mat4,
+        model: Mat4,
+    ) {
+        if !self.draws_points() || mesh.vertex_count == 0 {
+            return;
+        }
+
+        // the rows of the view rotation are the camera axes in world space
+        let uniforms = PointUniforms {
+            model: model.to_cols_array(),
+            view_projection: (perspective * view).to_cols_array(),
+            camera_right: view.row(0).truncate().extend(0.0).into(),
+            camera_up: view.row(1).truncate().extend(0.0).into(),
+            color,
+            point_size: self.graphics.point_size,
+            _padding: [0.0; 3],
+        };
+        self.graphics.queue.write_buffer(
+            &uniform_buffer.buffer,
+            0,
+            bytemuck::cast_slice(&[uniforms]),
+        );
+
+        let quad = self.graphics.get_quad_mesh();
+        self.set_pipeline(&self.graphics.points_pipeline);
+        self.pass.set_bind_group(0, &uniform_buffer.bind_group, &[]);
+        self.stats.state_changes += 1;
+        let index_key: *const wgpu::Buffer = quad.index();
+        if self.bound.index != Some(index_key) {
+            self.pass
+                .set_index_buffer(quad.index().slice(..), wgpu::IndexFormat::Uint16);
+            self.bound.index = Some(index_key);
+            self.stats.state_changes += 1;
+        }
+        let vertex_key: *const wgpu::Buffer = quad.vertex();
+        if self.bound.vertex != Some(vertex_key) {
+            self.pass.set_vertex_buffer(0, quad.vertex().slice(..));
+            self.bound.vertex = Some(vertex_key);
+            self.stats.state_changes += 1;
+        }
+        // the instance slot isn't tracked, as for particles
+        self.pass.set_vertex_buffer(1, mesh.vertex().slice(..));
+        self.stats.state_changes += 1;
+
+        self.pass.draw_indexed(
+            quad.index_range(),
+            quad.base_vertex(),
+            0..mesh.vertex_count as u32,
+        );
+        self.stats.draw_calls += 1;
     }
 
+    /// Draws `material` on a quad at `position` facing the camera, sized by
+    /// the aspect ratio of its diffuse texture. Text and sprite materials
+    /// should set `shaded: false` so they aren't lit.
+    #[allow(clippy::too_many_arguments)]
     pub fn render_billboard(
         &mut self,
         uniform_buffer: &'frame UniformBuffer,
@@ -594,23 +5389,201 @@ impl<'gfx: 'frame, 'frame> Pass<'gfx, 'frame> {
         view: Mat4,
         position: Vec3,
         camera_position: Vec3,
+        mode: BillboardMode,
     ) {
         let mesh = self.graphics.get_quad_mesh();
-        let texture = material.diffuse.as_ref().unwrap();
+        // without a texture the quad is square and shows the base color
+        let texture = match material.diffuse.as_ref() {
+            Some(texture) => texture,
+            None => self.graphics.empty_texture(),
+        };
 
         let w = texture.width as f32;
         let h = texture.height as f32;
         let s = 1.2;
         let scale = Vec3::new(w / w.max(h) * s, h / w.max(h) * s, 1.0);
-        let rotation = {
-            let fwd = camera_position - position;
-            let fwd = -fwd.normalize().cross(GLOBAL_UP.into()).normalize();
-            let yaw = f32::atan2(fwd.z, fwd.x);
-            let pitch = f32::asin(fwd.y);
-            Mat4::from_euler(glam::EulerRot::YXZ, -yaw, pitch, 0.0)
+        let rotation = match mode {
+            BillboardMode::Spherical => {
+                // the camera's right, up and backward axes, so the quad is
+                // parallel to the screen
+                let camera = view.inverse();
+                Mat4::from_cols(camera.x_axis, camera.y_axis, camera.z_axis, Vec4::W)
+            }
+            BillboardMode::Cylindrical => {
+                let up = self.graphics.world_up;
+                let to_camera = camera_position - position;
+                let backward = (to_camera - up * to_camera.dot(up)).normalize_or_zero();
+                let right = up.cross(backward);
+                Mat4::from_cols(
+                    right.extend(0.0),
+                    up.extend(0.0),
+                    backward.extend(0.0),
+                    Vec4::W,
+                )
+            }
         };
         let model = Mat4::from_translation(position) * rotation * Mat4::from_scale(scale);
 
-        self.render_mesh(&mesh, uniform_buffer, material, perspective, view, model);
+        self.render_mesh(
+            &mesh,
+            uniform_buffer,
+            material,
+            perspective,
+            view,
+            model,
+            &[],
+            1.0,
+            [1.0; 4],
+        );
+    }
+}
+
+/// GPU resources for tests, made on a device of their own since a
+/// `GraphicsContext` needs a window.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+
+    /// `None` where there's no adapter, in which case tests needing one are
+    /// skipped.
+    pub(crate) fn device() -> Option<wgpu::Device> {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()))?;
+        let (device, _) =
+            pollster::block_on(adapter.request_device(&Default::default(), None)).ok()?;
+        Some(device)
+    }
+
+    /// A mesh of one triangle, and a uniform buffer to draw it with.
+    pub(crate) fn triangle(device: &wgpu::Device) -> (Mesh, UniformBuffer) {
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::Y];
+        let vertices = positions
+            .iter()
+            .map(|it| Vertex {
+                position: it.extend(1.0).into(),
+                normal: [0.0, 0.0, 1.0],
+                tex_coord: [0.0; 2],
+                tangent: [0.0; 4],
+            })
+            .collect::<Vec<_>>();
+        let indices = vec![0u16, 1, 2];
+        let buffer = |contents: &[u8], usage| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents,
+                usage,
+            })
+        };
+        let mesh = Mesh {
+            inner: Rc::new((
+                buffer(bytemuck::cast_slice(&vertices), wgpu::BufferUsage::VERTEX),
+                buffer(bytemuck::cast_slice(&indices), wgpu::BufferUsage::INDEX),
+            )),
+            topology: Topology::Triangles,
+            first_index: 0,
+            base_vertex: 0,
+            index_count: indices.len(),
+            vertex_count: vertices.len(),
+            vertex_bytes: std::mem::size_of_val(vertices.as_slice()) as u64,
+            index_bytes: std::mem::size_of_val(indices.as_slice()) as u64,
+            bounding_sphere: bounding_sphere(&positions, &[]),
+            bounding_box: bounding_box(&positions),
+            morph_targets: Rc::new(vec![]),
+            morph_buffer: None,
+            positions: Rc::new(positions),
+            indices: Rc::new(indices),
+            content_hash: None,
+        };
+
+        let size = std::mem::size_of::<Uniforms>() as u64;
+        let buffer = PooledBuffer {
+            buffer: Some(buffer(
+                &[0; std::mem::size_of::<Uniforms>()],
+                wgpu::BufferUsage::UNIFORM,
+            )),
+            key: (size, wgpu::BufferUsage::UNIFORM),
+            size,
+            pool: Weak::new(),
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.binding(),
+            }],
+        });
+        (mesh, UniformBuffer { buffer, bind_group })
+    }
+
+    /// An untextured material of `color`, otherwise glTF's default one.
+    pub(crate) fn material(color: [f32; 4]) -> Material {
+        Material {
+            normal: None,
+            diffuse: None,
+            base_diffuse_color: color,
+            orm: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            occlusion_strength: 1.0,
+            shaded: true,
+            alpha_blend: false,
+            transmission_factor: 0.0,
+            transmission_texture: None,
+            ior: 1.5,
+            clearcoat_factor: 0.0,
+            clearcoat_roughness_factor: 0.0,
+            clearcoat_texture: None,
+            clearcoat_roughness_texture: None,
+            specular_factor: 1.0,
+            specular_color_factor: [1.0; 3],
+            specular_texture: None,
+            specular_color_texture: None,
+            sampler: None,
+            depth_bias: None,
+            name: None,
+            extras: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pooled_buffers_are_reused_from_the_next_frame() {
+        let pool = BufferPool::<&str>::default();
+        let usage = wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST;
+
+        let (key, reused) = pool.reuse(100, usage);
+        assert_eq!((key, reused), ((128, usage), None));
+        pool.release(key, "first");
+        // not before the frame it was released in is over
+        assert_eq!(pool.reuse(100, usage).1, None);
+        pool.recycle();
+
+        // a similar size shares the bucket, a larger one doesn't
+        assert_eq!(pool.reuse(256, usage).1, None);
+        assert_eq!(pool.reuse(120, usage), ((128, usage), Some("first")));
+        assert_eq!(pool.reuse(120, usage).1, None);
+        // nor does another usage
+        pool.release(key, "second");
+        pool.recycle();
+        assert_eq!(pool.reuse(100, wgpu::BufferUsage::VERTEX).1, None);
+        assert_eq!(pool.reuse(100, usage).1, Some("second"));
     }
 }
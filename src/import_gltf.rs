@@ -1,47 +1,835 @@
-use std::{borrow::Cow, convert::TryInto, iter::repeat};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    io::Cursor,
+    iter::repeat,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
 
-use glam::Mat4;
+use glam::{Mat4, Quat, Vec3, Vec4};
+use gltf::animation::util::ReadOutputs;
 use image::{DynamicImage, EncodableLayout, ImageError, ImageFormat};
 use smallvec::SmallVec;
 
 use crate::{
+    animation::{Animation, AnimationRange, Channel, ChannelValues, Interpolation},
     graphics::{
-        GraphicsContext, Material, Mesh, Texture, TextureDescription, UniformBuffer, Vertex,
+        GraphicsContext, Material, Mesh, MorphTarget, OrmTexture, PointVertex, Texture,
+        TextureDescription, Topology, UniformBuffer, UvTransform, Vertex,
     },
-    transform::Transform,
-    Node, Scene, Skin,
+    simplify,
+    transform::{Transform, Trs},
+    AssetInfo, Catalog, Node, NodeId, Scene, SceneDiff, Skin,
 };
 
+/// Optional checks and fixes applied to imported data. The defaults trust the
+/// asset and skip them.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub non_finite_vertices: NonFiniteVertices,
+    pub unnormalized_normals: UnnormalizedNormals,
+    pub degenerate_triangles: DegenerateTriangles,
+    /// Rotates the scene a quarter turn around X, so glTF's +Y up becomes +Z
+    /// up, for worlds built around Z. Off by default, since the engine is Y
+    /// up like glTF (see `transform::GLOBAL_UP`).
+    pub z_up: bool,
+    /// Uniform scale baked into the scene's root transform, e.g. 0.001 for
+    /// assets modeled in millimeters. 1 by default.
+    pub scale: f32,
+    /// Reverses the winding of every triangle, for assets exported clockwise
+    /// instead of glTF's counter-clockwise, which face the wrong way. Meshes
+    /// are drawn without back-face culling, so it doesn't change how they
+    /// look, but it fixes what relies on the winding, like the normals
+    /// `foliage::scatter` aligns instances with. Off by default.
+    pub reverse_winding: bool,
+    /// Inverts the green channel of normal textures, for assets authored
+    /// with DirectX's green down convention instead of glTF's green up one.
+    /// Off by default.
+    pub flip_normal_green: bool,
+    /// Reports primitives with an index at least this large, in
+    /// `ImportReport::index_near_limit`, as meshes are drawn with 16 bit indices and anything past `u16::MAX`
+    /// wraps around. `INDEX_WARNING_THRESHOLD` is a reasonable value. `None`
+    /// by default, which doesn't check.
+    pub index_warning_threshold: Option<u32>,
+    /// Base color textures start out as a texel of their average color, grey
+    /// where it can't be found cheaply (see `average_color`), and their
+    /// images are decoded on a background thread, so the import returns
+    /// sooner. They show up after `GraphicsContext::update_streamed_textures`
+    /// uploads them, which also returns the images that failed to decode.
+    /// Off by default.
+    pub stream_textures: bool,
+    /// Makes two imports of the same file give the same scene, drawn the same
+    /// way from the first frame, for snapshot tests. That's the case already
+    /// except for `stream_textures`, which this turns off, since when the
+    /// decoded textures show up depends on thread timing. The rest doesn't
+    /// depend on the mode: nodes are kept in file order (see `Catalog`),
+    /// extras are parsed into sorted maps and extensions are checked in the
+    /// order of `extensionsUsed`. The adapter, picked by `GraphicsContext`,
+    /// is always the low power one compatible with the window. Off by
+    /// default.
+    pub deterministic: bool,
+    /// Decimates the meshes of the scene, each by the same proportion, until
+    /// it has at most this many triangles in total, counting meshes once per
+    /// node using them, for weak hardware. See `simplify::simplify` for how.
+    /// The counts before and after are in `ImportReport::decimated`. `None` by
+    /// default, which keeps every triangle.
+    pub max_triangles: Option<usize>,
+    /// Bakes the meshes of nodes that can't move into world space and merges
+    /// the ones with equal materials into one mesh each, on a new root node
+    /// named "merged", for static scenery made of many small nodes. Nodes
+    /// that are animated or below one that is, skinned meshes and meshes with
+    /// morph targets are left alone. Nodes the merge leaves without meshes or
+    /// children are removed, along with ancestors that leaves empty, unless
+    /// they have extras or are a skin's joints. The draws saved are in
+    /// `ImportReport::merged`. Off by default.
+    pub merge_by_material: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            non_finite_vertices: NonFiniteVertices::default(),
+            unnormalized_normals: UnnormalizedNormals::default(),
+            degenerate_triangles: DegenerateTriangles::default(),
+            z_up: false,
+            scale: 1.0,
+            reverse_winding: false,
+            flip_normal_green: false,
+            index_warning_threshold: None,
+            stream_textures: false,
+            deterministic: false,
+            max_triangles: None,
+            merge_by_material: false,
+        }
+    }
+}
+
+/// What the import options that check or change the scene found and did,
+/// from `import_default_scene_with_report`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportReport {
+    /// Triangles before and after `ImportOptions::max_triangles` decimated
+    /// the scene, `None` if it was under the limit already.
+    pub decimated: Option<(usize, usize)>,
+    /// Meshes `ImportOptions::merge_by_material` merged and the meshes they
+    /// became, `None` if there was nothing to merge. Each of the difference
+    /// is a draw saved.
+    pub merged: Option<(usize, usize)>,
+    /// How many degenerate triangles `ImportOptions::degenerate_triangles`
+    /// found in a mesh and primitive, for each primitive with any. They're
+    /// gone with `DegenerateTriangles::Remove`.
+    pub degenerate_triangles: Vec<(usize, usize, usize)>,
+    /// The largest index of a mesh and primitive, for each primitive with
+    /// one past `ImportOptions::index_warning_threshold`.
+    pub index_near_limit: Vec<(u32, usize, usize)>,
+}
+
+impl ImportReport {
+    /// What was found, as `validate` would report it.
+    pub fn issues(&self) -> Vec<ValidationIssue> {
+        self.degenerate_triangles
+            .iter()
+            .map(|&(count, mesh, primitive)| {
+                ValidationIssue::DegenerateTriangles(count, mesh, primitive)
+            })
+            .chain(
+                self.index_near_limit
+                    .iter()
+                    .map(|&(index, mesh, primitive)| {
+                        ValidationIssue::IndexNearLimit(index, mesh, primitive)
+                    }),
+            )
+            .collect()
+    }
+}
+
+/// What to do with vertices that have NaN or infinite positions or normals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFiniteVertices {
+    /// Don't scan for them.
+    #[default]
+    Ignore,
+    /// Fail the import with `ImportGltfError::NonFiniteVertex`.
+    Error,
+    /// Drop every triangle that uses one of them.
+    DropTriangles,
+}
+
+/// What to do with normals whose length differs from 1 by more than
+/// `NORMAL_LENGTH_TOLERANCE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnnormalizedNormals {
+    /// Keep them as they are, in case they are intentional.
+    #[default]
+    Ignore,
+    /// Print a warning with how many were found.
+    Warn,
+    /// Rescale them to unit length.
+    Renormalize,
+}
+
+/// What to do with triangles that use the same vertex twice or have no area,
+/// which have no well defined normal and can cause shading artifacts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DegenerateTriangles {
+    /// Don't scan for them.
+    #[default]
+    Ignore,
+    /// Count them in `ImportReport::degenerate_triangles`.
+    Warn,
+    /// Drop them, counting them like `Warn` does.
+    Remove,
+}
+
+pub const NORMAL_LENGTH_TOLERANCE: f32 = 0.01;
+
+/// Indices from here on are close to overflowing 16 bits, see
+/// `ImportOptions::index_warning_threshold`. Also what `validate` checks for.
+pub const INDEX_WARNING_THRESHOLD: u32 = 60_000;
+
 pub fn import_default_scene(
     file_name: &str,
     graphics: &GraphicsContext,
 ) -> Result<Scene, ImportGltfError> {
-    let gltf = gltf::Gltf::open(file_name)?;
-    let base_path = file_name[0..file_name.rfind("/").unwrap()].to_string();
+    import_default_scene_with_options(file_name, graphics, &ImportOptions::default())
+}
+
+pub fn import_default_scene_with_options(
+    file_name: &str,
+    graphics: &GraphicsContext,
+    options: &ImportOptions,
+) -> Result<Scene, ImportGltfError> {
+    import_default_scene_with_report(file_name, graphics, options).map(|(scene, _)| scene)
+}
+
+/// Like `import_default_scene_with_options`, also returning what the options
+/// did to the scene.
+pub fn import_default_scene_with_report(
+    file_name: &str,
+    graphics: &GraphicsContext,
+    options: &ImportOptions,
+) -> Result<(Scene, ImportReport), ImportGltfError> {
+    let bytes = std::fs::read(file_name)?;
+    let base_path = base_dir(file_name);
+    import_bytes(
+        &bytes,
+        ExternalFiles::Dir(base_path),
+        graphics,
+        options,
+        None,
+    )
+}
+
+/// Imports `file_name` again as a new version of `old`, e.g. after it changed
+/// on disk. Meshes and textures created from the same data as one of `old`'s
+/// are shared with it instead of uploaded again, so only what changed goes to
+/// the GPU. Also returns what changed, see `Scene::diff`.
+pub fn reimport(
+    file_name: &str,
+    old: &Scene,
+    graphics: &GraphicsContext,
+    options: &ImportOptions,
+) -> Result<(Scene, SceneDiff), ImportGltfError> {
+    let bytes = std::fs::read(file_name)?;
+    let base_path = base_dir(file_name);
+    let (scene, _) = import_bytes(
+        &bytes,
+        ExternalFiles::Dir(base_path),
+        graphics,
+        options,
+        Some(old),
+    )?;
+    let diff = old.diff(&scene);
+    Ok((scene, diff))
+}
+
+/// Imports the default scene of the .gltf or .glb file at `url`, fetching the
+/// buffers and images it refers to by relative URI from next to it. Data URIs
+/// are decoded without touching the network. Everything is downloaded before
+/// anything is uploaded to the GPU.
+///
+/// Uses reqwest's async client, so it has to be awaited within a Tokio
+/// runtime.
+#[cfg(feature = "reqwest")]
+pub async fn import_from_url(
+    url: &str,
+    graphics: &GraphicsContext,
+) -> Result<Scene, ImportGltfError> {
+    let base = reqwest::Url::parse(url)
+        .map_err(|e| ImportGltfError::InvalidUrl(url.to_string(), e.to_string()))?;
+    let bytes = fetch(&base).await?;
+    let gltf = gltf::Gltf::from_slice(&bytes)?;
+
+    let buffer_uris = gltf.document.buffers().filter_map(|it| match it.source() {
+        gltf::buffer::Source::Uri(uri) => Some(uri),
+        gltf::buffer::Source::Bin => None,
+    });
+    let image_uris = gltf.document.images().filter_map(|it| match it.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    let mut fetched = HashMap::new();
+    for uri in buffer_uris.chain(image_uris) {
+        if uri.starts_with("data:") || fetched.contains_key(uri) {
+            continue;
+        }
+        let url = base
+            .join(uri)
+            .map_err(|e| ImportGltfError::InvalidUrl(uri.to_string(), e.to_string()))?;
+        fetched.insert(uri.to_string(), fetch(&url).await?);
+    }
+
+    import_bytes(
+        &bytes,
+        ExternalFiles::Fetched(fetched),
+        graphics,
+        &ImportOptions::default(),
+        None,
+    )
+    .map(|(scene, _)| scene)
+}
+
+#[cfg(feature = "reqwest")]
+async fn fetch(url: &reqwest::Url) -> Result<Vec<u8>, ImportGltfError> {
+    let http_error = |e| ImportGltfError::HttpError(url.to_string(), e);
+    let response = reqwest::get(url.clone())
+        .await
+        .and_then(|it| it.error_for_status())
+        .map_err(http_error)?;
+    let bytes = response.bytes().await.map_err(http_error)?;
+    Ok(bytes.to_vec())
+}
+
+fn import_bytes(
+    bytes: &[u8],
+    files: ExternalFiles,
+    graphics: &GraphicsContext,
+    options: &ImportOptions,
+    previous: Option<&Scene>,
+) -> Result<(Scene, ImportReport), ImportGltfError> {
+    let gltf = gltf::Gltf::from_slice(bytes)?;
+    let mut previous_meshes = HashMap::new();
+    let mut previous_textures = HashMap::new();
+    for (mesh, _, material) in previous
+        .iter()
+        .flat_map(|it| it.nodes.iter())
+        .flat_map(|it| &it.meshes)
+    {
+        if let Some(hash) = mesh.content_hash {
+            previous_meshes.insert(hash, mesh.clone());
+        }
+        for texture in material.textures() {
+            if let Some(hash) = texture.content_hash {
+                previous_textures.insert(hash, texture.clone());
+            }
+        }
+    }
     let mut importer = Importer {
         blob: gltf.blob,
+        raw_materials: raw_materials(bytes),
         buffers: vec![],
         images: vec![],
         textures: vec![None; gltf.document.textures().count()],
         materials: vec![None; gltf.document.materials().count()],
+        default_material: None,
         meshes: vec![None; gltf.document.meshes().count()],
-        base_path,
+        keep_triangles: None,
+        static_geometry: HashMap::new(),
+        previous_meshes,
+        previous_textures,
+        report: ImportReport::default(),
+        files,
+        options: options.clone(),
         graphics,
     };
 
-    importer.import_default_scene(gltf.document)
+    let scene = importer.import_default_scene(gltf.document)?;
+    Ok((scene, importer.report))
+}
+
+/// Imports the default scene of `file_name` but keeps only the node named
+/// `node_name` (see `Scene::find_node`) and its descendants, with that node as
+/// the only root, e.g. to take a single prop out of a file with many. The
+/// transforms of its ancestors are dropped, as are skins and animation
+/// channels referring to nodes outside of it.
+pub fn import_node_subtree(
+    file_name: &str,
+    node_name: &str,
+    graphics: &GraphicsContext,
+) -> Result<Scene, ImportGltfError> {
+    let mut full = import_default_scene(file_name, graphics)?;
+    let root = full
+        .find_node(node_name)
+        .ok_or_else(|| ImportGltfError::NodeNameNotFound(node_name.to_string()))?;
+
+    full.reparent(root, None)
+        .expect("the node was found in the scene");
+    for other in full.root_nodes.clone() {
+        if other != root {
+            full.remove_node(other)
+                .expect("root nodes should be in the scene");
+        }
+    }
+
+    // merging into an empty scene hands out new ids to the nodes left
+    let mut scene = Scene {
+        nodes: Catalog::new(),
+        root_nodes: SmallVec::new(),
+        transform: full.transform.clone(),
+        animations: vec![],
+        extras: None,
+        asset: full.asset.clone(),
+    };
+    scene
+        .merge(full, None)
+        .expect("merging into an empty scene can't fail");
+    scene.animations.retain(|it| !it.channels.is_empty());
+    Ok(scene)
+}
+
+/// Extensions the importer understands, anything else in `extensionsUsed` is
+/// ignored.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "KHR_materials_unlit",
+    "KHR_materials_transmission",
+    "KHR_materials_clearcoat",
+    "KHR_materials_specular",
+    "KHR_materials_ior",
+    "KHR_texture_transform",
+];
+
+/// Largest texture width or height accepted by every adapter, wgpu's default
+/// limit.
+pub const MAX_TEXTURE_SIZE: u32 = 8192;
+
+/// Something wrong or unusual about a glTF file, found by `validate`.
+#[derive(thiserror::Error, Debug)]
+pub enum ValidationIssue {
+    /// Something that makes the import fail.
+    #[error("{0}")]
+    Error(#[from] ImportGltfError),
+    #[error("{0} degenerate triangles in mesh with index {1} and primitive with index {2}")]
+    DegenerateTriangles(usize, usize, usize),
+    /// Edges shared by triangles facing opposite ways, some of them are likely
+    /// flipped.
+    #[error(
+        "{0} edges with inconsistent winding in mesh with index {1} and primitive with index {2}"
+    )]
+    InconsistentWinding(usize, usize, usize),
+    #[error("extension '{0}' is not supported")]
+    UnsupportedExtension(String),
+    #[error("image {0} is {1}x{2}, larger than {max} in some adapters", max = MAX_TEXTURE_SIZE)]
+    OversizedTexture(usize, u32, u32),
+    /// An index of at least `INDEX_WARNING_THRESHOLD`, which may already be
+    /// past the 16 bits meshes are drawn with.
+    #[error(
+        "index {0} in mesh with index {1} and primitive with index {2} is near or past the 16 bit limit of {max}",
+        max = u16::MAX
+    )]
+    IndexNearLimit(u32, usize, usize),
+}
+
+/// Checks the default scene of `file_name` for problems, reporting all of
+/// them instead of stopping at the first like the import does. Buffers are
+/// read, but images are only decoded far enough to know their size and nothing
+/// is uploaded to the GPU.
+pub fn validate(file_name: &str) -> Vec<ValidationIssue> {
+    let gltf = match gltf::Gltf::open(file_name) {
+        Ok(it) => it,
+        Err(error) => return vec![ImportGltfError::from(error).into()],
+    };
+    let files = ExternalFiles::Dir(base_dir(file_name));
+    let mut issues = vec![];
+
+    for extension in gltf.document.extensions_used() {
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            issues.push(ValidationIssue::UnsupportedExtension(extension.to_string()));
+        }
+    }
+
+    let mut blob = gltf.blob;
+    let buffers = gltf
+        .document
+        .buffers()
+        .map(|buffer| {
+            read_gltf_buffer(buffer, &mut blob, &files).unwrap_or_else(|error| {
+                issues.push(error.into());
+                vec![]
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for image in gltf.document.images() {
+        let size = read_gltf_image(&image, &buffers, &files).and_then(|(data, format)| {
+            image::io::Reader::with_format(std::io::Cursor::new(data), format)
+                .into_dimensions()
+                .map_err(|e| ImportGltfError::ImageLoadingFailed(image.index().to_string(), e))
+        });
+        match size {
+            Ok((width, height)) if width.max(height) > MAX_TEXTURE_SIZE => {
+                issues.push(ValidationIssue::OversizedTexture(
+                    image.index(),
+                    width,
+                    height,
+                ));
+            }
+            Ok(_) => {}
+            Err(error) => issues.push(error.into()),
+        }
+    }
+
+    for mesh in gltf.document.meshes() {
+        for primitive in mesh.primitives() {
+            let missing = |property| {
+                ImportGltfError::RequiredMeshPropertyMissing(
+                    property,
+                    mesh.index(),
+                    primitive.index(),
+                )
+                .into()
+            };
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+
+            // point clouds only need positions
+            if primitive.mode() == gltf::mesh::Mode::Points {
+                if reader.read_positions().is_none() {
+                    issues.push(missing("positions"));
+                }
+                continue;
+            }
+            if reader.read_normals().is_none() {
+                issues.push(missing("normals"));
+            }
+            if reader.read_tex_coords(0).is_none() {
+                issues.push(missing("uvs"));
+            }
+            let positions = match reader.read_positions() {
+                Some(it) => it.collect::<Vec<_>>(),
+                None => {
+                    issues.push(missing("positions"));
+                    continue;
+                }
+            };
+            let indices = match reader.read_indices() {
+                Some(it) => it.into_u32().collect::<Vec<_>>(),
+                None => {
+                    issues.push(missing("indices"));
+                    continue;
+                }
+            };
+
+            if let Some(&index) = indices.iter().max() {
+                if index as usize >= positions.len() {
+                    issues.push(
+                        ImportGltfError::IndexOutOfBounds(
+                            index,
+                            positions.len(),
+                            mesh.index(),
+                            primitive.index(),
+                        )
+                        .into(),
+                    );
+                    continue;
+                }
+                if index >= INDEX_WARNING_THRESHOLD {
+                    issues.push(ValidationIssue::IndexNearLimit(
+                        index,
+                        mesh.index(),
+                        primitive.index(),
+                    ));
+                }
+            }
+
+            if primitive.mode() == gltf::mesh::Mode::Triangles {
+                let degenerate = indices
+                    .chunks_exact(3)
+                    .filter(|tri| is_degenerate_triangle(tri, |i| positions[i as usize].into()))
+                    .count();
+                if degenerate > 0 {
+                    issues.push(ValidationIssue::DegenerateTriangles(
+                        degenerate,
+                        mesh.index(),
+                        primitive.index(),
+                    ));
+                }
+                let inconsistent = count_inconsistent_edges(&indices);
+                if inconsistent > 0 {
+                    issues.push(ValidationIssue::InconsistentWinding(
+                        inconsistent,
+                        mesh.index(),
+                        primitive.index(),
+                    ));
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+/// Returns the glTF file itself along with every external buffer and image it
+/// references, so callers can tell when any part of the asset changes.
+pub fn referenced_files(file_name: &str) -> Result<Vec<PathBuf>, ImportGltfError> {
+    let gltf = gltf::Gltf::open(file_name)?;
+    let base_path = base_dir(file_name);
+
+    let buffer_uris = gltf.document.buffers().filter_map(|it| match it.source() {
+        gltf::buffer::Source::Uri(uri) => Some(uri),
+        gltf::buffer::Source::Bin => None,
+    });
+    let image_uris = gltf.document.images().filter_map(|it| match it.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+
+    let mut files = vec![PathBuf::from(file_name)];
+    for uri in buffer_uris.chain(image_uris) {
+        if !uri.starts_with("data:") {
+            files.push(base_path.join(uri));
+        }
+    }
+    Ok(files)
+}
+/// Structure of a glTF file, found by `describe`. Its `Display` is a
+/// readable dump, one line per mesh primitive and material.
+#[derive(Debug, Clone)]
+pub struct SceneDescription {
+    pub node_count: usize,
+    pub scene_count: usize,
+    pub animation_count: usize,
+    pub skin_count: usize,
+    pub image_count: usize,
+    pub extensions_used: Vec<String>,
+    pub meshes: Vec<MeshDescription>,
+    pub materials: Vec<MaterialDescription>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MeshDescription {
+    pub name: Option<String>,
+    pub primitives: Vec<PrimitiveDescription>,
 }
+
+#[derive(Debug, Clone)]
+pub struct PrimitiveDescription {
+    pub mode: gltf::mesh::Mode,
+    /// Attribute names as in the file, such as `TEXCOORD_0`, sorted.
+    pub attributes: Vec<String>,
+    pub vertex_count: usize,
+    /// `None` for primitives without indices.
+    pub index_count: Option<usize>,
+    pub morph_target_count: usize,
+    /// Index of the material, `None` for the default one.
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MaterialDescription {
+    pub name: Option<String>,
+    pub alpha_mode: gltf::material::AlphaMode,
+    pub double_sided: bool,
+    pub unlit: bool,
+    pub base_color_factor: [f32; 4],
+    /// Slots with a texture, such as `baseColor` or `normal`.
+    pub textures: Vec<&'static str>,
+}
+
+/// Describes the structure of `file_name` without importing it: only the
+/// JSON is read, so neither buffers nor images are loaded and nothing is
+/// uploaded to the GPU. Counts come from the accessors.
+pub fn describe(file_name: &str) -> Result<SceneDescription, ImportGltfError> {
+    let gltf = gltf::Gltf::open(file_name)?;
+    let document = &gltf.document;
+
+    let meshes = document
+        .meshes()
+        .map(|mesh| MeshDescription {
+            name: mesh.name().map(str::to_string),
+            primitives: mesh
+                .primitives()
+                .map(|primitive| {
+                    let mut attributes = primitive
+                        .attributes()
+                        .map(|(semantic, _)| semantic.to_string())
+                        .collect::<Vec<_>>();
+                    attributes.sort();
+                    PrimitiveDescription {
+                        mode: primitive.mode(),
+                        attributes,
+                        vertex_count: primitive
+                            .get(&gltf::Semantic::Positions)
+                            .map_or(0, |it| it.count()),
+                        index_count: primitive.indices().map(|it| it.count()),
+                        morph_target_count: primitive.morph_targets().count(),
+                        material: primitive.material().index(),
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    let materials = document
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            let textures = [
+                ("baseColor", pbr.base_color_texture().is_some()),
+                (
+                    "metallicRoughness",
+                    pbr.metallic_roughness_texture().is_some(),
+                ),
+                ("normal", material.normal_texture().is_some()),
+                ("occlusion", material.occlusion_texture().is_some()),
+                ("emissive", material.emissive_texture().is_some()),
+            ];
+            MaterialDescription {
+                name: material.name().map(str::to_string),
+                alpha_mode: material.alpha_mode(),
+                double_sided: material.double_sided(),
+                unlit: material.unlit(),
+                base_color_factor: pbr.base_color_factor(),
+                textures: textures
+                    .iter()
+                    .filter(|(_, present)| *present)
+                    .map(|(slot, _)| *slot)
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(SceneDescription {
+        node_count: document.nodes().count(),
+        scene_count: document.scenes().count(),
+        animation_count: document.animations().count(),
+        skin_count: document.skins().count(),
+        image_count: document.images().count(),
+        extensions_used: document.extensions_used().map(str::to_string).collect(),
+        meshes,
+        materials,
+    })
+}
+
+impl std::fmt::Display for SceneDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} nodes, {} scenes, {} meshes, {} materials, {} animations, {} skins, {} images",
+            self.node_count,
+            self.scene_count,
+            self.meshes.len(),
+            self.materials.len(),
+            self.animation_count,
+            self.skin_count,
+            self.image_count
+        )?;
+        if !self.extensions_used.is_empty() {
+            writeln!(f, "extensions: {}", self.extensions_used.join(", "))?;
+        }
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            writeln!(f, "mesh {} {:?}", i, mesh.name.as_deref().unwrap_or(""))?;
+            for (j, primitive) in mesh.primitives.iter().enumerate() {
+                write!(
+                    f,
+                    "  primitive {}: {:?}, {} vertices",
+                    j, primitive.mode, primitive.vertex_count
+                )?;
+                if let Some(count) = primitive.index_count {
+                    write!(f, ", {} indices", count)?;
+                }
+                if primitive.morph_target_count > 0 {
+                    write!(f, ", {} morph targets", primitive.morph_target_count)?;
+                }
+                match primitive.material {
+                    Some(material) => write!(f, ", material {}", material)?,
+                    None => write!(f, ", default material")?,
+                }
+                writeln!(f, " [{}]", primitive.attributes.join(" "))?;
+            }
+        }
+        for (i, material) in self.materials.iter().enumerate() {
+            write!(
+                f,
+                "material {} {:?}: {:?}, base color {:?}",
+                i,
+                material.name.as_deref().unwrap_or(""),
+                material.alpha_mode,
+                material.base_color_factor
+            )?;
+            if material.double_sided {
+                write!(f, ", double sided")?;
+            }
+            if material.unlit {
+                write!(f, ", unlit")?;
+            }
+            writeln!(f, " [{}]", material.textures.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// rgba bytes, width, height, format
+type DecodedImage = (Vec<u8>, u32, u32, wgpu::TextureFormat);
+
+/// Where the buffers and images a glTF file refers to by relative URI are
+/// read from.
+enum ExternalFiles {
+    /// The directory the file is in, see `base_dir`.
+    Dir(PathBuf),
+    /// Downloaded beforehand, by URI.
+    #[cfg_attr(not(feature = "reqwest"), allow(dead_code))]
+    Fetched(HashMap<String, Vec<u8>>),
+}
+
+/// The directory `file_name` is in, empty for a bare file name so that what
+/// it refers to is read from the working directory.
+fn base_dir(file_name: &str) -> PathBuf {
+    Path::new(file_name)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+impl ExternalFiles {
+    fn read(&self, uri: &str) -> Result<Vec<u8>, ImportGltfError> {
+        match self {
+            ExternalFiles::Dir(base_path) => Ok(std::fs::read(base_path.join(uri))?),
+            ExternalFiles::Fetched(files) => files
+                .get(uri)
+                .cloned()
+                .ok_or_else(|| ImportGltfError::MissingFile(uri.to_string())),
+        }
+    }
+}
+
 struct Importer<'gfx> {
-    base_path: String,
+    files: ExternalFiles,
     blob: Option<Vec<u8>>,
+    /// The `materials` of the glTF JSON, for extensions gltf doesn't parse.
+    raw_materials: Vec<serde_json::Value>,
 
     buffers: Vec<Vec<u8>>,
-    images: Vec<(Vec<u8>, u32, u32, wgpu::TextureFormat)>,
+    /// Decoded on first use when streaming textures, see `decode_image`.
+    images: Vec<Option<DecodedImage>>,
 
     textures: Vec<Option<Texture>>,
     materials: Vec<Option<Material>>,
+    default_material: Option<Material>,
     meshes: Vec<Option<Vec<(Mesh, Material)>>>,
+    /// Fraction of the triangles of each primitive to keep, to fit
+    /// `ImportOptions::max_triangles`.
+    keep_triangles: Option<f32>,
+    /// The vertices and indices of every mesh created, by `static_mesh_key`,
+    /// to merge them with `ImportOptions::merge_by_material`.
+    static_geometry: HashMap<StaticMeshKey, (Vec<Vertex>, Vec<u16>)>,
+    /// The meshes and textures of the scene being reimported, by their
+    /// content hash, see `reimport`.
+    previous_meshes: HashMap<u64, Mesh>,
+    previous_textures: HashMap<u64, Texture>,
+    report: ImportReport,
 
+    options: ImportOptions,
     graphics: &'gfx GraphicsContext,
 }
 
@@ -58,18 +846,30 @@ impl<'gfx> Importer<'gfx> {
             self.buffers.push(b);
         }
 
-        for image in document.images() {
-            self.images.push(self.import_gltf_image(image)?);
+        self.images = vec![None; document.images().count()];
+        if !self.streams_textures() {
+            for image in document.images() {
+                self.decode_image(image)?;
+            }
         }
 
-        let mut nodes = vec![];
+        let triangle_count = scene_triangle_count(&scene);
+        if let Some(max_triangles) = self.options.max_triangles {
+            if triangle_count > max_triangles {
+                self.keep_triangles = Some(max_triangles as f32 / triangle_count as f32);
+            }
+        }
 
-        let root_nodes = scene
+        // every node gets an id up front, so they can refer to each other
+        let mut nodes = Catalog::new();
+        let node_ids = document
             .nodes()
-            .map(|it| map_node_to_u16_index(&it))
-            .collect::<Result<SmallVec<[u16; 4]>, ImportGltfError>>()?;
+            .map(|_| nodes.reserve())
+            .collect::<Vec<NodeId>>();
+
+        let root_nodes = scene.nodes().map(|it| node_ids[it.index()]).collect();
 
-        let mut node_stack: Vec<(gltf::Node, Option<u16>)> =
+        let mut node_stack: Vec<(gltf::Node, Option<NodeId>)> =
             scene.nodes().zip(repeat(None)).collect();
 
         loop {
@@ -78,19 +878,24 @@ impl<'gfx> Importer<'gfx> {
                 None => break,
             };
 
-            let node_index = map_node_to_u16_index(&node)?;
+            let node_id = node_ids[node.index()];
 
-            node_stack.extend(node.children().zip(repeat(Some(node_index))));
+            node_stack.extend(node.children().zip(repeat(Some(node_id))));
 
-            let children = node
-                .children()
-                .map(|it| map_node_to_u16_index(&it))
-                .collect::<Result<SmallVec<[u16; 4]>, ImportGltfError>>()?;
+            let children = node.children().map(|it| node_ids[it.index()]).collect();
 
             let transform = Transform::from(Mat4::from_cols_array_2d(&node.transform().matrix()));
+            let trs = {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Trs {
+                    translation: translation.into(),
+                    rotation: Quat::from_xyzw(rotation[0], rotation[1], rotation[2], rotation[3]),
+                    scale: scale.into(),
+                }
+            };
 
             let meshes = match node.mesh() {
-                Some(mesh) => self.import_gltf_mesh(mesh)?,
+                Some(mesh) => self.import_gltf_mesh(mesh, &document)?,
                 None => vec![],
             };
 
@@ -98,14 +903,10 @@ impl<'gfx> Importer<'gfx> {
                 Some(skin) => {
                     let joints = skin
                         .joints()
-                        .map(|it| map_node_to_u16_index(&it))
-                        .collect::<Result<SmallVec<[u16; 4]>, ImportGltfError>>()?;
+                        .map(|it| node_ids[it.index()])
+                        .collect::<SmallVec<[NodeId; 4]>>();
 
-                    let skeleton = match skin.skeleton().map(|it| map_node_to_u16_index(&it)) {
-                        Some(Ok(it)) => Ok(Some(it)),
-                        Some(Err(e)) => Err(e),
-                        None => Ok(None),
-                    }?;
+                    let skeleton = skin.skeleton().map(|it| node_ids[it.index()]);
 
                     let inverse_bind_matrices = skin
                         .reader(|buffer| self.buffers.get(buffer.index()).map(Vec::as_slice))
@@ -125,102 +926,309 @@ impl<'gfx> Importer<'gfx> {
                 None => None,
             };
 
-            nodes.push((
-                node_index,
+            let morph_weights = node
+                .weights()
+                .or_else(|| node.mesh().and_then(|it| it.weights()))
+                .map(|it| it.iter().copied().take(MorphTarget::MAX_COUNT).collect())
+                .unwrap_or_default();
+
+            nodes.insert(
+                node_id,
                 Node {
                     parent,
                     children,
                     transform,
+                    trs,
                     meshes,
                     skin,
+                    morph_weights,
+                    fade_distance: None,
+                    tint: [1.0; 4],
                     name: node.name().map(str::to_string),
+                    extras: parse_extras(node.extras()),
+                    user_data: None,
                 },
-            ));
+            );
         }
 
-        nodes.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let rotation = if self.options.z_up {
+            Mat4::from_rotation_x(std::f32::consts::FRAC_PI_2)
+        } else {
+            Mat4::IDENTITY
+        };
+        let transform =
+            Transform::from(Mat4::from_scale(Vec3::splat(self.options.scale)) * rotation);
+
+        let animations = document
+            .animations()
+            .map(|it| self.import_gltf_animation(it, &node_ids))
+            .collect::<Result<Vec<_>, ImportGltfError>>()?;
 
-        let nodes = nodes.into_iter().map(|it| it.1).collect();
+        let extras = parse_extras(scene.extras());
+
+        if self.keep_triangles.is_some() {
+            let decimated_count = nodes
+                .iter()
+                .flat_map(|it| &it.meshes)
+                .map(|(mesh, _, _)| mesh.index_count / 3)
+                .sum::<usize>();
+            self.report.decimated = Some((triangle_count, decimated_count));
+        }
 
-        let transform = Transform::from(Mat4::IDENTITY);
+        let asset = document.into_json().asset;
+        let asset = AssetInfo {
+            generator: asset.generator,
+            version: asset.version,
+            min_version: asset.min_version,
+            copyright: asset.copyright,
+        };
 
-        Ok(Scene {
+        let mut scene = Scene {
             transform,
             nodes,
             root_nodes,
-        })
+            animations,
+            extras,
+            asset,
+        };
+        if self.options.merge_by_material {
+            self.merge_by_material(&mut scene);
+        }
+        Ok(scene)
     }
 
-    fn import_gltf_buffer(&mut self, buffer: gltf::Buffer) -> Result<Vec<u8>, ImportGltfError> {
-        match buffer.source() {
-            gltf::buffer::Source::Bin => {
-                self.blob.take().ok_or(ImportGltfError::BinSectionNotFound)
+    /// See `ImportOptions::merge_by_material`.
+    fn merge_by_material(&mut self, scene: &mut Scene) {
+        let animated = scene
+            .animations
+            .iter()
+            .flat_map(|it| &it.channels)
+            .map(|it| it.node)
+            .collect::<HashSet<_>>();
+        let joints = scene
+            .nodes
+            .iter()
+            .filter_map(|it| it.skin.as_ref())
+            .flat_map(|it| it.joints.iter().copied().chain(it.skeleton))
+            .collect::<HashSet<_>>();
+
+        // transform of each node that can't move, relative to the scene
+        let mut world = HashMap::new();
+        for (id, node) in scene.nodes.iter_with_id() {
+            let mut current = Some(*id);
+            while let Some(it) = current.filter(|it| !animated.contains(it)) {
+                current = scene.nodes.get(it).and_then(|it| it.parent);
             }
-            gltf::buffer::Source::Uri(uri) => {
-                if uri.starts_with("data:") {
-                    Ok(data_uri_to_bytes_and_type(uri)?.0)
-                } else {
-                    Ok(std::fs::read(format!("{}/{}", self.base_path, uri))?)
+            if current.is_none() {
+                world.insert(*id, scene.node_matrix(node));
+            }
+        }
+
+        let mut groups: Vec<(Material, Vec<(NodeId, usize)>)> = vec![];
+        for (id, node) in scene.nodes.iter_with_id() {
+            if node.skin.is_some() || !world.contains_key(id) {
+                continue;
+            }
+            for (i, (mesh, _, material)) in node.meshes.iter().enumerate() {
+                if mesh.morph_targets.is_empty()
+                    && self.static_geometry.contains_key(&static_mesh_key(mesh))
+                {
+                    match groups.iter_mut().find(|(it, _)| it == material) {
+                        Some((_, members)) => members.push((*id, i)),
+                        None => groups.push((material.clone(), vec![(*id, i)])),
+                    }
                 }
             }
         }
-    }
+        groups.retain(|(_, members)| members.len() > 1);
+        if groups.is_empty() {
+            return;
+        }
 
-    // result is (rgba bytes, width, height, format)
-    fn import_gltf_image(
-        &self,
-        image: gltf::Image,
-    ) -> Result<(Vec<u8>, u32, u32, wgpu::TextureFormat), ImportGltfError> {
-        let (data, mime_type) = match image.source() {
-            gltf::image::Source::Uri { uri, mime_type } => {
-                let (data, parsed_mt) = if uri.starts_with("data:") {
-                    data_uri_to_bytes_and_type(uri)?
-                } else {
-                    let bytes = std::fs::read(&format!("{}/{}", self.base_path, uri))?;
-                    let format = if uri.ends_with(".png") {
-                        "image/png"
-                    } else if uri.ends_with(".jpg") || uri.ends_with(".jpeg") {
-                        "image/jpeg"
+        // indices are 16 bits, so a group too large for that takes several
+        let mut merged: Vec<(Vec<Vertex>, Vec<u16>, Material)> = vec![];
+        let mut merged_count = 0;
+        for (material, members) in &groups {
+            let mut current: (Vec<Vertex>, Vec<u16>) = (vec![], vec![]);
+            for &(id, i) in members {
+                let matrix = world[&id];
+                let mesh = &scene.nodes.get(id).expect("grouped nodes exist").meshes[i].0;
+                let (vertices, indices) = &self.static_geometry[&static_mesh_key(mesh)];
+                if current.0.len() + vertices.len() > u16::MAX as usize + 1 {
+                    merged.push((current.0, current.1, material.clone()));
+                    current = (vec![], vec![]);
+                }
+                let (current_vertices, current_indices) = &mut current;
+                let offset = current_vertices.len() as u16;
+                current_vertices.extend(vertices.iter().map(|it| bake_vertex(it, matrix)));
+                let mirrored = matrix.determinant() < 0.0;
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|it| it + offset);
+                    if mirrored {
+                        current_indices.extend_from_slice(&[a, c, b]);
                     } else {
-                        "application/octet-stream"
-                    };
-                    (bytes, format)
-                };
+                        current_indices.extend_from_slice(&[a, b, c]);
+                    }
+                }
+                merged_count += 1;
+            }
+            merged.push((current.0, current.1, material.clone()));
+        }
 
-                let mime_type = match mime_type {
-                    Some(mt) => mt,
-                    None => parsed_mt,
-                };
+        // taken out last first, so the indices of the rest stay valid
+        let mut taken = groups
+            .into_iter()
+            .flat_map(|(_, members)| members)
+            .collect::<Vec<_>>();
+        taken.sort_by(|a, b| b.cmp(a));
+        let mut emptied = taken.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        emptied.dedup();
+        for (id, i) in taken {
+            if let Some(node) = scene.nodes.get_mut(id) {
+                node.meshes.remove(i);
+            }
+        }
 
-                (Cow::from(data), mime_type)
-            }
-            gltf::image::Source::View { view, mime_type } => {
-                let buffer_index = view.buffer().index();
-                let buffer = &self
-                    .buffers
-                    .get(buffer_index)
-                    .ok_or(ImportGltfError::UnknownBufferIndex(buffer_index))?;
-                let from = view.offset();
-                let to = view.offset() + view.length();
-                let data = buffer
-                    .get(from..to)
-                    .ok_or(ImportGltfError::BufferRangeOutOfBounds(
-                        buffer_index,
-                        from,
-                        to,
-                    ))?;
-                (Cow::from(data), mime_type)
+        let meshes = self
+            .graphics
+            .create_meshes(
+                &merged
+                    .iter()
+                    .map(|(vertices, indices, _)| (vertices.as_slice(), indices.as_slice()))
+                    .collect::<Vec<_>>(),
+            )
+            .into_iter()
+            .zip(merged)
+            .map(|(mesh, (_, _, material))| {
+                let uniform_buffer = self.graphics.create_mesh_uniform_buffer(&mesh);
+                (mesh, uniform_buffer, material)
+            })
+            .collect::<Vec<_>>();
+        self.report.merged = Some((merged_count, meshes.len()));
+        let merged_id = scene.nodes.add(Node {
+            parent: None,
+            children: SmallVec::new(),
+            transform: Transform::from(Mat4::IDENTITY),
+            trs: Trs {
+                translation: Vec3::ZERO,
+                rotation: Quat::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            meshes,
+            skin: None,
+            morph_weights: vec![],
+            fade_distance: None,
+            tint: [1.0; 4],
+            name: Some("merged".to_string()),
+            extras: None,
+            user_data: None,
+        });
+        scene.root_nodes.push(merged_id);
+
+        // nodes the merge emptied go, which can leave their parents empty
+        // leaves too, while the ones that were empty in the file stay
+        for id in emptied {
+            let mut current = Some(id);
+            while let Some(id) = current {
+                let node = match scene.nodes.get(id) {
+                    Some(it) => it,
+                    None => break,
+                };
+                if !node.meshes.is_empty()
+                    || !node.children.is_empty()
+                    || node.extras.is_some()
+                    || joints.contains(&id)
+                {
+                    break;
+                }
+                current = node.parent;
+                scene.remove_node(id).expect("the node was found");
             }
-        };
+        }
+    }
+
+    fn import_gltf_animation(
+        &self,
+        animation: gltf::Animation,
+        node_ids: &[NodeId],
+    ) -> Result<Animation, ImportGltfError> {
+        let mut channels = vec![];
+        for (channel_index, channel) in animation.channels().enumerate() {
+            let reader =
+                channel.reader(|buffer| self.buffers.get(buffer.index()).map(Vec::as_slice));
+
+            let times = reader
+                .read_inputs()
+                .ok_or(ImportGltfError::RequiredAnimationPropertyMissing(
+                    "inputs",
+                    animation.index(),
+                    channel_index,
+                ))?
+                .collect();
+
+            let values = match reader.read_outputs().ok_or(
+                ImportGltfError::RequiredAnimationPropertyMissing(
+                    "outputs",
+                    animation.index(),
+                    channel_index,
+                ),
+            )? {
+                ReadOutputs::Translations(it) => {
+                    ChannelValues::Translation(it.map(Vec3::from).collect())
+                }
+                ReadOutputs::Rotations(it) => ChannelValues::Rotation(
+                    it.into_f32()
+                        .map(|[x, y, z, w]| Quat::from_xyzw(x, y, z, w))
+                        .collect(),
+                ),
+                ReadOutputs::Scales(it) => ChannelValues::Scale(it.map(Vec3::from).collect()),
+                ReadOutputs::MorphTargetWeights(it) => {
+                    ChannelValues::MorphWeights(it.into_f32().collect())
+                }
+            };
+
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+
+            channels.push(Channel {
+                node: node_ids[channel.target().node().index()],
+                interpolation,
+                times,
+                values,
+            });
+        }
+
+        let duration = channels
+            .iter()
+            .filter_map(|it| it.times.last())
+            .fold(0.0, |a: f32, &b| a.max(b));
+
+        let extras = parse_extras(animation.extras());
+        let ranges = extras
+            .as_ref()
+            .map_or_else(Vec::new, parse_animation_ranges);
 
-        let format = match mime_type {
-            "image/jpeg" => Ok(ImageFormat::Jpeg),
-            "image/png" => Ok(ImageFormat::Png),
-            fmt => Err(ImportGltfError::UnknownImageFormat(
-                fmt.to_string(),
-                image.index(),
+        Ok(Animation {
+            name: Some(animation.name().map_or_else(
+                || format!("animation_{}", animation.index()),
+                str::to_string,
             )),
-        }?;
+            channels,
+            duration,
+            ranges,
+            extras,
+        })
+    }
+
+    fn import_gltf_buffer(&mut self, buffer: gltf::Buffer) -> Result<Vec<u8>, ImportGltfError> {
+        read_gltf_buffer(buffer, &mut self.blob, &self.files)
+    }
+
+    fn import_gltf_image(&self, image: gltf::Image) -> Result<DecodedImage, ImportGltfError> {
+        let (data, format) = read_gltf_image(&image, &self.buffers, &self.files)?;
 
         let image = image::load_from_memory_with_format(&data, format)
             .map_err(|e| ImportGltfError::ImageLoadingFailed(image.index().to_string(), e))?;
@@ -243,6 +1251,38 @@ impl<'gfx> Importer<'gfx> {
         }
     }
 
+    /// Creates the texture of `description`, unless the scene being
+    /// reimported has one made from the same.
+    fn build_texture(&self, description: &TextureDescription) -> Texture {
+        let hash = description.content_hash();
+        let mut texture = match self.previous_textures.get(&hash) {
+            Some(previous) => previous.with_uv_transform(UvTransform::default()),
+            None => description.build(self.graphics),
+        };
+        texture.content_hash = Some(hash);
+        texture
+    }
+
+    fn streams_textures(&self) -> bool {
+        self.options.stream_textures && !self.options.deterministic
+    }
+
+    /// Decodes `image` into `images` unless it already was.
+    fn decode_image(&mut self, image: gltf::Image) -> Result<(), ImportGltfError> {
+        let index = image.index();
+        if let Some(None) = self.images.get(index) {
+            self.images[index] = Some(self.import_gltf_image(image)?);
+        }
+        Ok(())
+    }
+
+    fn decoded_image(&self, index: usize) -> Result<&DecodedImage, ImportGltfError> {
+        self.images
+            .get(index)
+            .and_then(Option::as_ref)
+            .ok_or(ImportGltfError::UnknownImageIndex(index))
+    }
+
     fn import_gltf_texture(&mut self, texture: gltf::Texture) -> Result<Texture, ImportGltfError> {
         let texture_index = texture.index();
         if let Some(tex) = self
@@ -253,83 +1293,298 @@ impl<'gfx> Importer<'gfx> {
             return Ok(tex.clone());
         }
 
-        let image_index = texture.source().index();
-        let (data, width, height, format) = &self
-            .images
-            .get(image_index)
-            .ok_or(ImportGltfError::UnknownImageIndex(image_index))?;
+        self.decode_image(texture.source())?;
+        let (data, width, height, format) = self.decoded_image(texture.source().index())?;
 
-        let sampler = texture.sampler();
-
-        let mut desc = TextureDescription::new(data, *width, *height, *format)
-            .wrap_s(match sampler.wrap_s() {
-                gltf::texture::WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
-                gltf::texture::WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
-                gltf::texture::WrappingMode::Repeat => wgpu::AddressMode::Repeat,
-            })
-            .wrap_t(match sampler.wrap_s() {
-                gltf::texture::WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
-                gltf::texture::WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
-                gltf::texture::WrappingMode::Repeat => wgpu::AddressMode::Repeat,
-            });
+        let builder = Texture::builder(data, *width, *height, *format);
+        Ok(self.build_texture(&apply_gltf_sampler(builder, texture.sampler())))
+    }
 
-        if let Some(min_filter) = sampler.min_filter() {
-            desc = desc.min_filter(match min_filter {
-                gltf::texture::MinFilter::Nearest => wgpu::FilterMode::Nearest,
-                gltf::texture::MinFilter::Linear => wgpu::FilterMode::Linear,
-                _ => unimplemented!(),
-            });
+    /// Like `import_gltf_texture`, but when streaming textures the image is
+    /// decoded on a background thread while a placeholder is shown.
+    fn import_gltf_streamed_texture(
+        &mut self,
+        texture: gltf::Texture,
+    ) -> Result<Texture, ImportGltfError> {
+        let texture_index = texture.index();
+        let image = texture.source();
+        let decoded = !matches!(self.images.get(image.index()), Some(None));
+        if !self.streams_textures() || decoded {
+            return self.import_gltf_texture(texture);
         }
-
-        if let Some(mag_filter) = sampler.mag_filter() {
-            desc = desc.mag_filter(match mag_filter {
-                gltf::texture::MagFilter::Nearest => wgpu::FilterMode::Nearest,
-                gltf::texture::MagFilter::Linear => wgpu::FilterMode::Linear,
-            });
+        if let Some(tex) = self
+            .textures
+            .get(texture_index)
+            .ok_or(ImportGltfError::UnknownTextureIndex(texture_index))?
+        {
+            return Ok(tex.clone());
         }
 
-        let texture = self.graphics.create_texture(&desc);
-        Ok(texture)
+        let (data, format) = read_gltf_image(&image, &self.buffers, &self.files)?;
+        let data = data.into_owned();
+        let (width, height) = image::io::Reader::with_format(Cursor::new(&data), format)
+            .into_dimensions()
+            .map_err(|e| ImportGltfError::ImageLoadingFailed(image.index().to_string(), e))?;
+
+        let texel = average_color(&data, format).unwrap_or([128, 128, 128, 255]);
+        let builder = Texture::builder(&texel, 1, 1, wgpu::TextureFormat::Rgba8Unorm);
+        let placeholder = apply_gltf_sampler(builder, texture.sampler());
+
+        // the texels aren't known yet either, so a texture of the scene being
+        // reimported is found by its encoded image instead
+        let mut hasher = DefaultHasher::new();
+        (placeholder.content_hash(), &data).hash(&mut hasher);
+        let hash = hasher.finish();
+        let mut texture = match self.previous_textures.get(&hash) {
+            Some(previous) => previous.with_uv_transform(UvTransform::default()),
+            None => {
+                let (sender, receiver) = mpsc::channel();
+                let image_index = image.index();
+                std::thread::spawn(move || {
+                    let texels = image::load_from_memory_with_format(&data, format)
+                        .map(|decoded| decoded.into_rgba8().into_raw())
+                        .map_err(|e| format!("couldn't decode image {}: {}", image_index, e));
+                    // the receiver is gone if the texture was dropped already
+                    let _ = sender.send(texels);
+                });
+                self.graphics
+                    .create_streamed_texture(&placeholder, width, height, receiver)
+            }
+        };
+        texture.content_hash = Some(hash);
+        self.textures[texture_index] = Some(texture.clone());
+        Ok(texture)
+    }
+
+    /// Like `import_gltf_texture`, but with green flipped first if the import
+    /// asks for it.
+    fn import_gltf_normal_texture(
+        &mut self,
+        texture: gltf::Texture,
+    ) -> Result<Texture, ImportGltfError> {
+        if !self.options.flip_normal_green {
+            return self.import_gltf_texture(texture);
+        }
+
+        self.decode_image(texture.source())?;
+        let (data, width, height, format) = self.decoded_image(texture.source().index())?;
+        let mut flipped = data.clone();
+        for texel in flipped.chunks_mut(4) {
+            texel[1] = 255 - texel[1];
+        }
+
+        let builder = Texture::builder(&flipped, *width, *height, *format);
+        Ok(self.build_texture(&apply_gltf_sampler(builder, texture.sampler())))
+    }
+
+    fn import_gltf_orm_texture(
+        &mut self,
+        material: &gltf::Material,
+    ) -> Result<Option<OrmTexture>, ImportGltfError> {
+        let occlusion = material.occlusion_texture().map(|it| it.texture());
+        let metallic_roughness = material
+            .pbr_metallic_roughness()
+            .metallic_roughness_texture()
+            .map(|it| it.texture());
+        let occlusion_uv = self.raw_uv_transform(material, "/occlusionTexture");
+        let metallic_roughness_uv =
+            self.raw_uv_transform(material, "/pbrMetallicRoughness/metallicRoughnessTexture");
+
+        let (occlusion, metallic_roughness) = match (occlusion, metallic_roughness) {
+            (None, None) => return Ok(None),
+            (Some(occlusion), None) => {
+                return Ok(Some(OrmTexture {
+                    texture: self
+                        .import_gltf_texture(occlusion)?
+                        .with_uv_transform(occlusion_uv),
+                    has_occlusion: true,
+                    has_metallic_roughness: false,
+                }))
+            }
+            (None, Some(metallic_roughness)) => {
+                return Ok(Some(OrmTexture {
+                    texture: self
+                        .import_gltf_texture(metallic_roughness)?
+                        .with_uv_transform(metallic_roughness_uv),
+                    has_occlusion: false,
+                    has_metallic_roughness: true,
+                }))
+            }
+            (Some(occlusion), Some(metallic_roughness)) => (occlusion, metallic_roughness),
+        };
+
+        // the common case: both slots share one already packed image. It's
+        // sampled once for both, here and when packed below, so occlusion
+        // uses the metallic-roughness UV transform
+        if occlusion.source().index() == metallic_roughness.source().index() {
+            return Ok(Some(OrmTexture {
+                texture: self
+                    .import_gltf_texture(metallic_roughness)?
+                    .with_uv_transform(metallic_roughness_uv),
+                has_occlusion: true,
+                has_metallic_roughness: true,
+            }));
+        }
+
+        self.decode_image(occlusion.source())?;
+        self.decode_image(metallic_roughness.source())?;
+        let (occlusion_data, occlusion_width, occlusion_height, _) =
+            self.decoded_image(occlusion.source().index())?;
+        let (mr_data, width, height, format) =
+            self.decoded_image(metallic_roughness.source().index())?;
+
+        // packing needs matching sizes, otherwise occlusion is dropped
+        if (occlusion_width, occlusion_height) != (width, height) {
+            return Ok(Some(OrmTexture {
+                texture: self
+                    .import_gltf_texture(metallic_roughness)?
+                    .with_uv_transform(metallic_roughness_uv),
+                has_occlusion: false,
+                has_metallic_roughness: true,
+            }));
+        }
+
+        let mut packed = mr_data.clone();
+        for (texel, occlusion_texel) in packed.chunks_mut(4).zip(occlusion_data.chunks(4)) {
+            texel[0] = occlusion_texel[0];
+        }
+
+        let builder = Texture::builder(&packed, *width, *height, *format);
+        let texture =
+            self.build_texture(&apply_gltf_sampler(builder, metallic_roughness.sampler()));
+        Ok(Some(OrmTexture {
+            texture: texture.with_uv_transform(metallic_roughness_uv),
+            has_occlusion: true,
+            has_metallic_roughness: true,
+        }))
+    }
+
+    /// `KHR_texture_transform` of the `textureInfo` at `pointer` in the raw
+    /// JSON of `material`. gltf doesn't read it for normal and occlusion
+    /// textures, so it's read from there for all of them.
+    fn raw_uv_transform(&self, material: &gltf::Material, pointer: &str) -> UvTransform {
+        parse_uv_transform(
+            material
+                .index()
+                .and_then(|it| self.raw_materials.get(it))
+                .and_then(|it| it.pointer(pointer)),
+        )
+    }
+
+    /// Imports the texture of a `textureInfo` from the raw JSON, if there's
+    /// one.
+    fn import_raw_texture_info(
+        &mut self,
+        info: Option<&serde_json::Value>,
+        document: &gltf::Document,
+    ) -> Result<Option<Texture>, ImportGltfError> {
+        let index = match info
+            .and_then(|it| it.get("index"))
+            .and_then(serde_json::Value::as_u64)
+        {
+            Some(index) => index as usize,
+            None => return Ok(None),
+        };
+        let texture = document
+            .textures()
+            .nth(index)
+            .ok_or(ImportGltfError::UnknownTextureIndex(index))?;
+        Ok(Some(
+            self.import_gltf_texture(texture)?
+                .with_uv_transform(parse_uv_transform(info)),
+        ))
     }
 
     fn import_gltf_material(
         &mut self,
         material: gltf::Material,
+        document: &gltf::Document,
     ) -> Result<Material, ImportGltfError> {
-        if let Some(index) = material.index() {
-            if let Some(mat) = self
+        let cached = match material.index() {
+            Some(index) => self
                 .materials
                 .get(index)
-                .ok_or(ImportGltfError::UnknownMaterialIndex(index))?
-            {
-                return Ok(mat.clone());
-            }
+                .ok_or(ImportGltfError::UnknownMaterialIndex(index))?,
+            None => &self.default_material,
+        };
+        if let Some(mat) = cached {
+            return Ok(mat.clone());
         }
 
-        let normal = match material.normal_texture().as_ref() {
-            Some(info) => Some(self.import_gltf_texture(info.texture())?),
+        let raw = material
+            .index()
+            .and_then(|it| self.raw_materials.get(it))
+            .cloned();
+        let mut imported = untextured_material(&material, raw.as_ref());
+
+        imported.normal = match material.normal_texture().as_ref() {
+            Some(info) => Some(
+                self.import_gltf_normal_texture(info.texture())?
+                    .with_uv_transform(self.raw_uv_transform(&material, "/normalTexture")),
+            ),
             None => None,
         };
-        let diffuse = match material
+        imported.diffuse = match material
             .pbr_metallic_roughness()
             .base_color_texture()
             .as_ref()
         {
-            Some(info) => Some(self.import_gltf_texture(info.texture())?),
+            Some(info) => Some(
+                self.import_gltf_streamed_texture(info.texture())?
+                    .with_uv_transform(
+                        self.raw_uv_transform(&material, "/pbrMetallicRoughness/baseColorTexture"),
+                    ),
+            ),
             None => None,
         };
-        let base_diffuse_color = material.pbr_metallic_roughness().base_color_factor();
-        Ok(Material {
-            normal,
-            diffuse,
-            base_diffuse_color,
-            shaded: true,
-        })
+        imported.orm = self.import_gltf_orm_texture(&material)?;
+
+        // gltf doesn't know about clearcoat and specular, their textures are
+        // read from the raw JSON
+        let extension = |name| raw.as_ref().and_then(|it| it.pointer(name));
+        let clearcoat = extension("/extensions/KHR_materials_clearcoat");
+        imported.clearcoat_texture = self.import_raw_texture_info(
+            clearcoat.and_then(|it| it.get("clearcoatTexture")),
+            document,
+        )?;
+        imported.clearcoat_roughness_texture = self.import_raw_texture_info(
+            clearcoat.and_then(|it| it.get("clearcoatRoughnessTexture")),
+            document,
+        )?;
+        let specular = extension("/extensions/KHR_materials_specular");
+        imported.specular_texture = self
+            .import_raw_texture_info(specular.and_then(|it| it.get("specularTexture")), document)?;
+        imported.specular_color_texture = self.import_raw_texture_info(
+            specular.and_then(|it| it.get("specularColorTexture")),
+            document,
+        )?;
+
+        imported.transmission_texture = match material
+            .transmission()
+            .and_then(|it| it.transmission_texture())
+        {
+            Some(info) => Some(self.import_gltf_texture(info.texture())?.with_uv_transform(
+                self.raw_uv_transform(
+                    &material,
+                    "/extensions/KHR_materials_transmission/transmissionTexture",
+                ),
+            )),
+            None => None,
+        };
+
+        // shared by every primitive using it, so they can be drawn together
+        let cached = match material.index() {
+            Some(index) => &mut self.materials[index],
+            None => &mut self.default_material,
+        };
+        *cached = Some(imported.clone());
+        Ok(imported)
     }
 
     fn import_gltf_mesh(
         &mut self,
         mesh: gltf::Mesh,
+        document: &gltf::Document,
     ) -> Result<Vec<(Mesh, UniformBuffer, Material)>, ImportGltfError> {
         let mesh_index = mesh.index();
         if let Some(m) = self
@@ -342,7 +1597,7 @@ impl<'gfx> Importer<'gfx> {
                 .map(|(mesh, mat)| {
                     (
                         mesh.clone(),
-                        self.graphics.create_uniform_buffer(),
+                        self.graphics.create_mesh_uniform_buffer(mesh),
                         mat.clone(),
                     )
                 })
@@ -350,74 +1605,673 @@ impl<'gfx> Importer<'gfx> {
         }
 
         let mut primitives = vec![];
+        let mut point_clouds = vec![];
         for primitive in mesh.primitives() {
             let reader =
                 primitive.reader(|buffer| self.buffers.get(buffer.index()).map(Vec::as_slice));
 
-            let mut positions =
-                reader
-                    .read_positions()
-                    .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
-                        "positions",
-                        mesh.index(),
-                        primitive.index(),
-                    ))?;
+            // only positions and colors, the points aren't lit or textured
+            if primitive.mode() == gltf::mesh::Mode::Points {
+                let positions =
+                    reader
+                        .read_positions()
+                        .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
+                            "positions",
+                            mesh.index(),
+                            primitive.index(),
+                        ))?;
+                let mut colors = reader.read_colors(0).map(|it| it.into_rgba_f32());
+                let points = positions
+                    .map(|position| PointVertex {
+                        position,
+                        color: colors.as_mut().and_then(|it| it.next()).unwrap_or([1.0; 4]),
+                    })
+                    .collect::<Vec<_>>();
+                let material = self.import_gltf_material(primitive.material(), document)?;
+                point_clouds.push((points, material));
+                continue;
+            }
 
-            let mut normals =
-                reader
-                    .read_normals()
-                    .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
-                        "normals",
-                        mesh.index(),
-                        primitive.index(),
-                    ))?;
+            let (vertices, indices, morph_targets) = read_primitive(
+                &primitive,
+                mesh.index(),
+                &self.buffers,
+                &self.options,
+                self.keep_triangles,
+                &mut self.report,
+            )?;
 
-            let mut tex_coords = reader
-                .read_tex_coords(0)
-                .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
-                    "uvs",
-                    mesh.index(),
-                    primitive.index(),
-                ))?
-                .into_f32();
-
-            let mut vertices: Vec<Vertex> = Vec::with_capacity(positions.len());
-            for _ in 0..positions.len() {
-                let p = positions.next().unwrap();
-                let position = [p[0], p[1], p[2], 1.0];
-                let normal = normals.next().unwrap();
-                let tex_coord = {
-                    let val = tex_coords.next().unwrap();
-                    [val[0], val[1]]
-                };
-                let vertex = Vertex {
-                    position,
-                    normal,
-                    tex_coord,
+            let material = self.import_gltf_material(primitive.material(), document)?;
+
+            primitives.push((vertices, indices, morph_targets, material));
+        }
+
+        // meshes unchanged since the scene being reimported are reused, the
+        // rest share buffers, except the morphed ones, whose morph targets are
+        // looked up by vertex index
+        let hashes = primitives
+            .iter()
+            .map(|(vertices, indices, morph_targets, _)| {
+                geometry_hash(vertices, indices, morph_targets)
+            })
+            .collect::<Vec<_>>();
+        let mut shared = self
+            .graphics
+            .create_meshes(
+                &primitives
+                    .iter()
+                    .zip(&hashes)
+                    .filter(|((_, _, morph_targets, _), hash)| {
+                        morph_targets.is_empty() && !self.previous_meshes.contains_key(hash)
+                    })
+                    .map(|((vertices, indices, _, _), _)| (vertices.as_slice(), indices.as_slice()))
+                    .collect::<Vec<_>>(),
+            )
+            .into_iter();
+
+        let mut meshes = primitives
+            .into_iter()
+            .zip(hashes)
+            .map(|((vertices, indices, morph_targets, material), hash)| {
+                let mut mesh = match self.previous_meshes.get(&hash) {
+                    Some(previous) => previous.clone(),
+                    None if morph_targets.is_empty() => shared.next().unwrap(),
+                    None => self
+                        .graphics
+                        .create_morph_mesh(&vertices, &indices, morph_targets),
                 };
-                vertices.push(vertex);
+                mesh.content_hash = Some(hash);
+                if self.options.merge_by_material && mesh.morph_targets.is_empty() {
+                    self.static_geometry
+                        .insert(static_mesh_key(&mesh), (vertices, indices));
+                }
+                let ub = self.graphics.create_mesh_uniform_buffer(&mesh);
+                (mesh, ub, material)
+            })
+            .collect::<Vec<_>>();
+        for (points, material) in point_clouds {
+            let mut hasher = DefaultHasher::new();
+            (Topology::Points, bytemuck::cast_slice::<_, u8>(&points)).hash(&mut hasher);
+            let hash = hasher.finish();
+            let mut mesh = match self.previous_meshes.get(&hash) {
+                Some(previous) => previous.clone(),
+                None => self.graphics.create_point_mesh(&points),
+            };
+            mesh.content_hash = Some(hash);
+            let ub = self.graphics.create_mesh_uniform_buffer(&mesh);
+            meshes.push((mesh, ub, material));
+        }
+        Ok(meshes)
+    }
+}
+
+/// The factors and flags of `material`, with no textures, reading the
+/// extensions gltf doesn't know about from its `raw` JSON. Primitives without
+/// a material get gltf's default one, which is the spec's: white, fully
+/// metallic and rough, opaque and untextured.
+fn untextured_material(material: &gltf::Material, raw: Option<&serde_json::Value>) -> Material {
+    let factor = |pointer, default| {
+        raw.and_then(|it| it.pointer(pointer))
+            .and_then(serde_json::Value::as_f64)
+            .map_or(default, |it| it as f32)
+    };
+    let specular_color_factor = match raw
+        .and_then(|it| it.pointer("/extensions/KHR_materials_specular/specularColorFactor"))
+        .and_then(serde_json::Value::as_array)
+        .map(|it| {
+            it.iter()
+                .filter_map(serde_json::Value::as_f64)
+                .collect::<Vec<_>>()
+        })
+        .as_deref()
+    {
+        Some(&[r, g, b]) => [r as f32, g as f32, b as f32],
+        _ => [1.0; 3],
+    };
+    Material {
+        normal: None,
+        diffuse: None,
+        base_diffuse_color: material.pbr_metallic_roughness().base_color_factor(),
+        orm: None,
+        metallic_factor: material.pbr_metallic_roughness().metallic_factor(),
+        roughness_factor: material.pbr_metallic_roughness().roughness_factor(),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map(|it| it.strength())
+            .unwrap_or(1.0),
+        shaded: !material.unlit(),
+        alpha_blend: material.alpha_mode() == gltf::material::AlphaMode::Blend,
+        transmission_factor: material
+            .transmission()
+            .as_ref()
+            .map_or(0.0, |it| it.transmission_factor()),
+        transmission_texture: None,
+        ior: material.ior().unwrap_or(1.5),
+        clearcoat_factor: factor("/extensions/KHR_materials_clearcoat/clearcoatFactor", 0.0),
+        clearcoat_roughness_factor: factor(
+            "/extensions/KHR_materials_clearcoat/clearcoatRoughnessFactor",
+            0.0,
+        ),
+        clearcoat_texture: None,
+        clearcoat_roughness_texture: None,
+        specular_factor: factor("/extensions/KHR_materials_specular/specularFactor", 1.0),
+        specular_color_factor,
+        specular_texture: None,
+        specular_color_texture: None,
+        sampler: None,
+        depth_bias: None,
+        name: material.name().map(str::to_string),
+        extras: parse_extras(material.extras()),
+    }
+}
+
+/// Vertices, indices and morph targets of a primitive.
+type PrimitiveGeometry = (Vec<Vertex>, Vec<u16>, Vec<MorphTarget>);
+
+/// Reads the vertices, indices and morph targets of a triangle primitive of
+/// mesh `mesh_index`, checked and fixed as `options` ask, without touching the
+/// GPU. `keep_triangles` is the fraction of its triangles to decimate to.
+/// What the checks find goes in `report`.
+fn read_primitive(
+    primitive: &gltf::Primitive,
+    mesh_index: usize,
+    buffers: &[Vec<u8>],
+    options: &ImportOptions,
+    keep_triangles: Option<f32>,
+    report: &mut ImportReport,
+) -> Result<PrimitiveGeometry, ImportGltfError> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+
+    let mut positions =
+        reader
+            .read_positions()
+            .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
+                "positions",
+                mesh_index,
+                primitive.index(),
+            ))?;
+
+    let mut normals = reader
+        .read_normals()
+        .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
+            "normals",
+            mesh_index,
+            primitive.index(),
+        ))?;
+
+    let mut tex_coords = reader
+        .read_tex_coords(0)
+        .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
+            "uvs",
+            mesh_index,
+            primitive.index(),
+        ))?
+        .into_f32();
+
+    // optional, the shader falls back to deriving them per pixel
+    let mut tangents = reader.read_tangents();
+
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(positions.len());
+    for _ in 0..positions.len() {
+        let p = positions.next().unwrap();
+        let position = [p[0], p[1], p[2], 1.0];
+        let normal = normals.next().unwrap();
+        let tex_coord = {
+            let val = tex_coords.next().unwrap();
+            [val[0], val[1]]
+        };
+        // w is kept as is, it flips the bitangent for mirrored UVs
+        let tangent = tangents
+            .as_mut()
+            .and_then(|it| it.next())
+            .unwrap_or([0.0; 4]);
+        let vertex = Vertex {
+            position,
+            normal,
+            tex_coord,
+            tangent,
+        };
+        vertices.push(vertex);
+    }
+
+    let is_unnormalized = |v: &Vertex| {
+        let length = glam::Vec3::from(v.normal).length();
+        (length - 1.0).abs() > NORMAL_LENGTH_TOLERANCE
+    };
+    match options.unnormalized_normals {
+        UnnormalizedNormals::Ignore => {}
+        UnnormalizedNormals::Warn => {
+            let count = vertices.iter().filter(|it| is_unnormalized(it)).count();
+            if count > 0 {
+                eprintln!(
+                    "warning: {} unnormalized normals in mesh {} primitive {}",
+                    count,
+                    mesh_index,
+                    primitive.index()
+                );
             }
+        }
+        UnnormalizedNormals::Renormalize => {
+            for vertex in vertices.iter_mut().filter(|it| is_unnormalized(it)) {
+                let normal = glam::Vec3::from(vertex.normal).normalize_or_zero();
+                vertex.normal = normal.into();
+            }
+        }
+    }
 
-            let indices = reader
-                .read_indices()
-                .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
-                    "indices",
-                    mesh.index(),
+    // read by hand rather than with `reader.read_morph_targets`, see
+    // `read_vec3_accessor`
+    let mut morph_targets = vec![];
+    for target in primitive.morph_targets() {
+        if morph_targets.len() == MorphTarget::MAX_COUNT {
+            eprintln!(
+                "warning: mesh {} primitive {} has more than {} morph targets",
+                mesh_index,
+                primitive.index(),
+                MorphTarget::MAX_COUNT
+            );
+            break;
+        }
+        let vertex_count = vertices.len();
+        let read_deltas = |accessor: Option<gltf::Accessor>| match accessor {
+            Some(accessor) => read_vec3_accessor(&accessor, buffers)
+                .filter(|it| it.len() == vertex_count)
+                .ok_or_else(|| ImportGltfError::InvalidAccessor(accessor.index())),
+            None => Ok(vec![[0.0; 3]; vertex_count]),
+        };
+        morph_targets.push(MorphTarget {
+            positions: read_deltas(target.positions())?,
+            normals: read_deltas(target.normals())?,
+        });
+    }
+
+    let mut indices = reader
+        .read_indices()
+        .ok_or(ImportGltfError::RequiredMeshPropertyMissing(
+            "indices",
+            mesh_index,
+            primitive.index(),
+        ))?
+        .into_u32()
+        .collect::<Vec<_>>();
+    if options.reverse_winding {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    if let Some(&index) = indices.iter().max() {
+        if index as usize >= vertices.len() {
+            return Err(ImportGltfError::IndexOutOfBounds(
+                index,
+                vertices.len(),
+                mesh_index,
+                primitive.index(),
+            ));
+        }
+    }
+
+    let is_finite = |v: &Vertex| {
+        v.position
+            .iter()
+            .chain(v.normal.iter())
+            .all(|it| it.is_finite())
+    };
+    match options.non_finite_vertices {
+        NonFiniteVertices::Ignore => {}
+        NonFiniteVertices::Error => {
+            if let Some(index) = vertices.iter().position(|it| !is_finite(it)) {
+                return Err(ImportGltfError::NonFiniteVertex(
+                    index,
+                    mesh_index,
                     primitive.index(),
-                ))?
-                .into_u32()
-                .map(|it| it as u16) // TODO! this sucks
-                .collect::<Vec<_>>();
+                ));
+            }
+        }
+        NonFiniteVertices::DropTriangles => {
+            indices = indices
+                .chunks_exact(3)
+                .filter(|tri| tri.iter().all(|&it| is_finite(&vertices[it as usize])))
+                .flatten()
+                .copied()
+                .collect();
+        }
+    }
+
+    let is_degenerate = |tri: &[u32]| {
+        is_degenerate_triangle(tri, |i| {
+            Vec4::from(vertices[i as usize].position).truncate()
+        })
+    };
+    match options.degenerate_triangles {
+        DegenerateTriangles::Ignore => {}
+        DegenerateTriangles::Warn => {
+            let count = indices
+                .chunks_exact(3)
+                .filter(|it| is_degenerate(it))
+                .count();
+            if count > 0 {
+                report
+                    .degenerate_triangles
+                    .push((count, mesh_index, primitive.index()));
+            }
+        }
+        DegenerateTriangles::Remove => {
+            let triangle_count = indices.len() / 3;
+            indices = indices
+                .chunks_exact(3)
+                .filter(|it| !is_degenerate(it))
+                .flatten()
+                .copied()
+                .collect();
+            let count = triangle_count - indices.len() / 3;
+            if count > 0 {
+                report
+                    .degenerate_triangles
+                    .push((count, mesh_index, primitive.index()));
+            }
+        }
+    }
+
+    if let (Some(threshold), Some(&index)) = (options.index_warning_threshold, indices.iter().max())
+    {
+        if index >= threshold {
+            report
+                .index_near_limit
+                .push((index, mesh_index, primitive.index()));
+        }
+    }
+
+    if let Some(keep) = keep_triangles {
+        let positions = vertices
+            .iter()
+            .map(|it| Vec4::from(it.position).truncate())
+            .collect::<Vec<_>>();
+        let target = (indices.len() / 3) as f32 * keep;
+        indices = simplify::simplify(&positions, &indices, target as usize);
+    }
+
+    let indices = indices
+        .into_iter()
+        .map(|it| it as u16) // TODO! this sucks
+        .collect::<Vec<_>>();
+
+    Ok((vertices, indices, morph_targets))
+}
+
+fn read_gltf_buffer(
+    buffer: gltf::Buffer,
+    blob: &mut Option<Vec<u8>>,
+    files: &ExternalFiles,
+) -> Result<Vec<u8>, ImportGltfError> {
+    match buffer.source() {
+        gltf::buffer::Source::Bin => blob.take().ok_or(ImportGltfError::BinSectionNotFound),
+        gltf::buffer::Source::Uri(uri) => {
+            if uri.starts_with("data:") {
+                Ok(data_uri_to_bytes_and_type(uri)?.0)
+            } else {
+                files.read(uri)
+            }
+        }
+    }
+}
+
+/// The values of `accessor` if it holds `f32` triples, or `None` if it
+/// doesn't or reaches out of its buffers. Sparse accessors are decoded here
+/// because the gltf crate's reader never ends on those without a base buffer
+/// view, the usual layout of morph targets touching few vertices: the base
+/// values, zeros when there's no view, are replaced at the sparse indices
+/// only.
+fn read_vec3_accessor(accessor: &gltf::Accessor, buffers: &[Vec<u8>]) -> Option<Vec<[f32; 3]>> {
+    const SIZE: usize = std::mem::size_of::<[f32; 3]>();
+    if accessor.data_type() != gltf::accessor::DataType::F32
+        || accessor.dimensions() != gltf::accessor::Dimensions::Vec3
+    {
+        return None;
+    }
+    let view_bytes = |view: gltf::buffer::View| {
+        let buffer = buffers.get(view.buffer().index())?;
+        buffer.get(view.offset()..view.offset() + view.length())
+    };
+    let read = |bytes: &[u8], offset: usize| {
+        let bytes = bytes.get(offset..offset + SIZE)?;
+        let component =
+            |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some([component(0), component(4), component(8)])
+    };
+
+    let mut values = match accessor.view() {
+        Some(view) => {
+            let stride = view.stride().unwrap_or(SIZE);
+            let bytes = view_bytes(view)?;
+            (0..accessor.count())
+                .map(|i| read(bytes, accessor.offset() + i * stride))
+                .collect::<Option<Vec<_>>>()?
+        }
+        None => vec![[0.0; 3]; accessor.count()],
+    };
+
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_bytes = view_bytes(indices.view())?.get(indices.offset() as usize..)?;
+        let value_bytes =
+            view_bytes(sparse.values().view())?.get(sparse.values().offset() as usize..)?;
+        let index_size = indices.index_type().size();
+        for i in 0..sparse.count() as usize {
+            let bytes = index_bytes.get(i * index_size..(i + 1) * index_size)?;
+            let index = match indices.index_type() {
+                gltf::accessor::sparse::IndexType::U8 => bytes[0] as usize,
+                gltf::accessor::sparse::IndexType::U16 => {
+                    u16::from_le_bytes([bytes[0], bytes[1]]) as usize
+                }
+                gltf::accessor::sparse::IndexType::U32 => {
+                    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+                }
+            };
+            *values.get_mut(index)? = read(value_bytes, i * SIZE)?;
+        }
+    }
+    Some(values)
+}
+
+/// The still encoded bytes of `image` and their format.
+/// The average color of an encoded image, for the placeholder of a streamed
+/// texture, if it can be found without decoding the whole image, which would
+/// defeat streaming. JPEGs can be decoded at an eighth of their size for it;
+/// other formats have no such shortcut and give `None`.
+fn average_color(data: &[u8], format: ImageFormat) -> Option<[u8; 4]> {
+    if format != ImageFormat::Jpeg {
+        return None;
+    }
+    let mut decoder = image::codecs::jpeg::JpegDecoder::new(Cursor::new(data)).ok()?;
+    decoder.scale(1, 1).ok()?;
+    let image = DynamicImage::from_decoder(decoder).ok()?.into_rgba8();
+
+    let mut sum = [0u64; 4];
+    for texel in image.pixels() {
+        for (sum, &channel) in sum.iter_mut().zip(texel.0.iter()) {
+            *sum += channel as u64;
+        }
+    }
+    let count = (image.width() as u64 * image.height() as u64).max(1);
+    let mut average = [0; 4];
+    for (average, sum) in average.iter_mut().zip(sum.iter()) {
+        *average = (sum / count) as u8;
+    }
+    Some(average)
+}
+
+fn read_gltf_image<'a>(
+    image: &gltf::Image,
+    buffers: &'a [Vec<u8>],
+    files: &ExternalFiles,
+) -> Result<(Cow<'a, [u8]>, ImageFormat), ImportGltfError> {
+    let (data, mime_type) = match image.source() {
+        gltf::image::Source::Uri { uri, mime_type } => {
+            let (data, parsed_mt) = if uri.starts_with("data:") {
+                data_uri_to_bytes_and_type(uri)?
+            } else {
+                let bytes = files.read(uri)?;
+                let format = if uri.ends_with(".png") {
+                    "image/png"
+                } else if uri.ends_with(".jpg") || uri.ends_with(".jpeg") {
+                    "image/jpeg"
+                } else {
+                    "application/octet-stream"
+                };
+                (bytes, format)
+            };
+
+            let mime_type = match mime_type {
+                Some(mt) => mt,
+                None => parsed_mt,
+            };
+
+            (Cow::from(data), mime_type)
+        }
+        gltf::image::Source::View { view, mime_type } => {
+            let buffer_index = view.buffer().index();
+            let buffer = buffers
+                .get(buffer_index)
+                .ok_or(ImportGltfError::UnknownBufferIndex(buffer_index))?;
+            let from = view.offset();
+            let to = view.offset() + view.length();
+            let data = buffer
+                .get(from..to)
+                .ok_or(ImportGltfError::BufferRangeOutOfBounds(
+                    buffer_index,
+                    from,
+                    to,
+                ))?;
+            (Cow::from(data), mime_type)
+        }
+    };
 
-            let material = self.import_gltf_material(primitive.material())?;
+    let format = match mime_type {
+        "image/jpeg" => Ok(ImageFormat::Jpeg),
+        "image/png" => Ok(ImageFormat::Png),
+        fmt => Err(ImportGltfError::UnknownImageFormat(
+            fmt.to_string(),
+            image.index(),
+        )),
+    }?;
+    Ok((data, format))
+}
 
-            let mesh = self.graphics.create_mesh(&vertices, &indices);
-            let ub = self.graphics.create_uniform_buffer();
+/// Hash of what a triangle mesh is created from, see `Mesh::content_hash`.
+fn geometry_hash(vertices: &[Vertex], indices: &[u16], morph_targets: &[MorphTarget]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    Topology::Triangles.hash(&mut hasher);
+    bytemuck::cast_slice::<_, u8>(vertices).hash(&mut hasher);
+    indices.hash(&mut hasher);
+    for target in morph_targets {
+        bytemuck::cast_slice::<_, u8>(&target.positions).hash(&mut hasher);
+        bytemuck::cast_slice::<_, u8>(&target.normals).hash(&mut hasher);
+    }
+    hasher.finish()
+}
 
-            primitives.push((mesh, ub, material.clone()));
+/// Sort key, first index and base vertex of a mesh.
+type StaticMeshKey = (usize, u32, i32);
+
+/// Tells apart meshes created by the importer, even when they share buffers.
+fn static_mesh_key(mesh: &Mesh) -> StaticMeshKey {
+    (
+        mesh.sort_key(),
+        mesh.index_range().start,
+        mesh.base_vertex(),
+    )
+}
+
+/// `vertex` moved by `matrix`, with its normal and tangent turned along.
+fn bake_vertex(vertex: &Vertex, matrix: Mat4) -> Vertex {
+    let normal_matrix = matrix.inverse().transpose();
+    let tangent = Vec4::from(vertex.tangent);
+    // a mirroring matrix flips the bitangent the shader derives
+    let handedness = if matrix.determinant() < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let tangent = matrix
+        .transform_vector3(tangent.truncate())
+        .normalize_or_zero()
+        .extend(tangent.w * handedness);
+    Vertex {
+        position: (matrix * Vec4::from(vertex.position)).into(),
+        normal: normal_matrix
+            .transform_vector3(vertex.normal.into())
+            .normalize_or_zero()
+            .into(),
+        tex_coord: vertex.tex_coord,
+        tangent: tangent.into(),
+    }
+}
+
+/// Triangles in the meshes of `scene`, counting each once per node using it,
+/// from the counts of the index accessors.
+fn scene_triangle_count(scene: &gltf::Scene) -> usize {
+    let mut count = 0;
+    let mut pending: Vec<gltf::Node> = scene.nodes().collect();
+    while let Some(node) = pending.pop() {
+        if let Some(mesh) = node.mesh() {
+            count += mesh
+                .primitives()
+                .filter(|it| it.mode() == gltf::mesh::Mode::Triangles)
+                .filter_map(|it| it.indices())
+                .map(|it| it.count() / 3)
+                .sum::<usize>();
         }
+        pending.extend(node.children());
+    }
+    count
+}
+
+/// Whether the triangle uses the same vertex twice or has no area, within
+/// floating point precision.
+fn is_degenerate_triangle(triangle: &[u32], position: impl Fn(u32) -> Vec3) -> bool {
+    let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+    if a == b || b == c || a == c {
+        return true;
+    }
+    let ab = position(b) - position(a);
+    let ac = position(c) - position(a);
+    ab.cross(ac).length() <= f32::EPSILON * ab.length() * ac.length()
+}
 
-        Ok(primitives)
+/// How many edges are walked in the same direction by more than one triangle.
+/// Triangles sharing an edge walk it in opposite directions when their
+/// winding agrees, so each of these is next to a flipped triangle.
+fn count_inconsistent_edges(indices: &[u32]) -> usize {
+    let mut edges = HashSet::new();
+    let mut count = 0;
+    for tri in indices.chunks_exact(3) {
+        for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+            if a != b && !edges.insert((a, b)) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// The `materials` array of a .gltf or .glb file's JSON, empty if it can't be
+/// read.
+fn raw_materials(bytes: &[u8]) -> Vec<serde_json::Value> {
+    let json = if bytes.starts_with(b"glTF") {
+        match gltf::Glb::from_slice(bytes) {
+            Ok(glb) => glb.json,
+            Err(_) => return vec![],
+        }
+    } else {
+        Cow::from(bytes)
+    };
+    match serde_json::from_slice::<serde_json::Value>(&json) {
+        Ok(serde_json::Value::Object(mut root)) => match root.remove("materials") {
+            Some(serde_json::Value::Array(materials)) => materials,
+            _ => vec![],
+        },
+        _ => vec![],
     }
 }
 
@@ -427,16 +2281,126 @@ fn data_uri_to_bytes_and_type(uri: &str) -> Result<(Vec<u8>, &str), base64::Deco
     Ok((bytes, mt))
 }
 
-fn map_node_to_u16_index(node: &gltf::Node) -> Result<u16, ImportGltfError> {
-    node.index()
-        .try_into()
-        .map_err(|_| ImportGltfError::NodeIndexOutOfRange(node.index()))
+/// The `KHR_texture_transform` of a raw `textureInfo`, with the spec's
+/// defaults for whatever is missing. Its `texCoord` override is ignored, only
+/// the first set of texture coordinates is imported.
+fn parse_uv_transform(info: Option<&serde_json::Value>) -> UvTransform {
+    let transform = info.and_then(|it| it.pointer("/extensions/KHR_texture_transform"));
+    let pair = |name, default: [f32; 2]| match transform
+        .and_then(|it| it.get(name))
+        .and_then(serde_json::Value::as_array)
+        .map(|it| {
+            it.iter()
+                .filter_map(serde_json::Value::as_f64)
+                .collect::<Vec<_>>()
+        })
+        .as_deref()
+    {
+        Some(&[x, y]) => [x as f32, y as f32],
+        _ => default,
+    };
+    UvTransform {
+        offset: pair("offset", [0.0, 0.0]),
+        rotation: transform
+            .and_then(|it| it.get("rotation"))
+            .and_then(serde_json::Value::as_f64)
+            .map_or(0.0, |it| it as f32),
+        scale: pair("scale", [1.0, 1.0]),
+    }
+}
+
+/// `extras` are kept as raw JSON by gltf, parsed here so they can be inspected.
+fn parse_extras(extras: &gltf::json::Extras) -> Option<serde_json::Value> {
+    extras
+        .as_ref()
+        .and_then(|it| serde_json::from_str(it.get()).ok())
+}
+
+/// Named ranges of an animation from its extras, as a `"ranges"` list of
+/// `{"name": "attack", "start": 1.0, "end": 2.5}` in seconds. Entries
+/// missing any of them are skipped.
+fn parse_animation_ranges(extras: &serde_json::Value) -> Vec<AnimationRange> {
+    let ranges = match extras.get("ranges").and_then(serde_json::Value::as_array) {
+        Some(ranges) => ranges,
+        None => return vec![],
+    };
+    ranges
+        .iter()
+        .filter_map(|it| {
+            let time = |key| it.get(key)?.as_f64().map(|it| it as f32);
+            Some(AnimationRange {
+                name: it.get("name")?.as_str()?.to_string(),
+                start: time("start")?,
+                end: time("end")?,
+            })
+        })
+        .collect()
+}
+
+fn apply_gltf_sampler<'a>(
+    mut builder: TextureDescription<'a>,
+    sampler: gltf::texture::Sampler,
+) -> TextureDescription<'a> {
+    builder = builder
+        .wrap_s(map_wrapping_mode(sampler.wrap_s()))
+        .wrap_t(map_wrapping_mode(sampler.wrap_t()));
+
+    if let Some(min_filter) = sampler.min_filter() {
+        let (min_filter, mipmap_filter) = match min_filter {
+            gltf::texture::MinFilter::Nearest => {
+                (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+            }
+            gltf::texture::MinFilter::Linear => {
+                (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest)
+            }
+            gltf::texture::MinFilter::NearestMipmapNearest => {
+                (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+            }
+            gltf::texture::MinFilter::LinearMipmapNearest => {
+                (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest)
+            }
+            gltf::texture::MinFilter::NearestMipmapLinear => {
+                (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear)
+            }
+            gltf::texture::MinFilter::LinearMipmapLinear => {
+                (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear)
+            }
+        };
+        builder = builder.min_filter(min_filter).mipmap_filter(mipmap_filter);
+    }
+
+    if let Some(mag_filter) = sampler.mag_filter() {
+        builder = builder.mag_filter(match mag_filter {
+            gltf::texture::MagFilter::Nearest => wgpu::FilterMode::Nearest,
+            gltf::texture::MagFilter::Linear => wgpu::FilterMode::Linear,
+        });
+    }
+
+    builder
+}
+
+fn map_wrapping_mode(mode: gltf::texture::WrappingMode) -> wgpu::AddressMode {
+    match mode {
+        gltf::texture::WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        gltf::texture::WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        gltf::texture::WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum ImportGltfError {
     #[error("io error: {0}")]
     IOError(#[from] std::io::Error),
+    /// A request of `import_from_url` failed or got an error status back.
+    #[cfg(feature = "reqwest")]
+    #[error("http request for '{0}' failed: {1}")]
+    HttpError(String, reqwest::Error),
+    #[cfg(feature = "reqwest")]
+    #[error("invalid url '{0}': {1}")]
+    InvalidUrl(String, String),
+    /// A buffer or image that wasn't fetched along with the file.
+    #[error("file '{0}' referenced by the gltf was not loaded")]
+    MissingFile(String),
     #[error("base 64 decode error: {0}")]
     Base64Error(#[from] base64::DecodeError),
     #[error("error while loading source gltf: {0}")]
@@ -451,6 +2415,22 @@ pub enum ImportGltfError {
         "required property '{0}' is missing for mesh with index {1} and primitive with index {2}"
     )]
     RequiredMeshPropertyMissing(&'static str, usize, usize),
+    #[error(
+        "index {0} is out of bounds for {1} vertices in mesh with index {2} and primitive with index {3}"
+    )]
+    IndexOutOfBounds(u32, usize, usize, usize),
+    #[error(
+        "vertex {0} has a non-finite position or normal in mesh with index {1} and primitive with index {2}"
+    )]
+    NonFiniteVertex(usize, usize, usize),
+    #[error(
+        "required property '{0}' is missing for animation with index {1} and channel with index {2}"
+    )]
+    RequiredAnimationPropertyMissing(&'static str, usize, usize),
+    /// An accessor with a type that doesn't fit its use, or that reaches out
+    /// of its buffers.
+    #[error("accessor {0} is out of bounds or has an unexpected type or count")]
+    InvalidAccessor(usize),
     #[error("unknown buffer index {0}")]
     UnknownBufferIndex(usize),
     #[error("buffer {0} has a view with range ({1}..{2}) that is out of bounds")]
@@ -461,14 +2441,331 @@ pub enum ImportGltfError {
     UnknownMaterialIndex(usize),
     #[error("unknown node index {0}")]
     UnknownNodeIndex(usize),
+    #[error("no node named '{0}' in the default scene")]
+    NodeNameNotFound(String),
     #[error("unknown mesh index {0}")]
     UnknownMeshIndex(usize),
     #[error("unknown texture index {0}")]
     UnknownTextureIndex(usize),
     #[error("unkown skin index {0}")]
     UnknownSkinIndex(usize),
-    #[error("node index {0} out of range")]
-    NodeIndexOutOfRange(usize),
     #[error("unreachable")]
     Unreachable,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vec3_bytes(values: &[[f32; 3]]) -> Vec<u8> {
+        values
+            .iter()
+            .flatten()
+            .flat_map(|it| it.to_le_bytes())
+            .collect()
+    }
+
+    /// A glTF with one mesh of one triangle using `indices`, with `tangents`
+    /// if given, and its buffer.
+    fn triangle(indices: [u16; 3], tangents: Option<[[f32; 4]; 3]>) -> (gltf::Gltf, Vec<Vec<u8>>) {
+        let mut buffer = vec3_bytes(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        buffer.extend(vec3_bytes(&[[0.0, 0.0, 1.0]; 3]));
+        buffer.extend(
+            [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0]
+                .iter()
+                .flat_map(|it| it.to_le_bytes()),
+        );
+        buffer.extend(indices.iter().flat_map(|it| it.to_le_bytes()));
+        buffer.extend([0; 2].iter());
+        let tangent_bytes = tangents.unwrap_or([[0.0; 4]; 3]);
+        buffer.extend(
+            tangent_bytes
+                .iter()
+                .flatten()
+                .flat_map(|it| it.to_le_bytes()),
+        );
+
+        let tangent_attribute = if tangents.is_some() {
+            r#", "TANGENT": 4"#
+        } else {
+            ""
+        };
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{ "byteLength": {} }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 72, "byteLength": 24 }},
+                    {{ "buffer": 0, "byteOffset": 96, "byteLength": 6 }},
+                    {{ "buffer": 0, "byteOffset": 104, "byteLength": 48 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0] }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                    {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                    {{ "bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR" }},
+                    {{ "bufferView": 4, "componentType": 5126, "count": 3, "type": "VEC4" }}
+                ],
+                "meshes": [{{ "primitives": [{{
+                    "attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2{} }},
+                    "indices": 3
+                }}] }}]
+            }}"#,
+            buffer.len(),
+            tangent_attribute,
+        );
+        (
+            gltf::Gltf::from_slice(json.as_bytes()).unwrap(),
+            vec![buffer],
+        )
+    }
+
+    fn read_triangle(
+        gltf: &gltf::Gltf,
+        buffers: &[Vec<u8>],
+    ) -> Result<PrimitiveGeometry, ImportGltfError> {
+        let mesh = gltf.document.meshes().next().unwrap();
+        let primitive = mesh.primitives().next().unwrap();
+        read_primitive(
+            &primitive,
+            0,
+            buffers,
+            &ImportOptions::default(),
+            None,
+            &mut ImportReport::default(),
+        )
+    }
+
+    #[test]
+    fn out_of_range_index_is_an_error() {
+        let (gltf, buffers) = triangle([0, 1, 3], None);
+        assert!(matches!(
+            read_triangle(&gltf, &buffers),
+            Err(ImportGltfError::IndexOutOfBounds(3, 3, 0, 0))
+        ));
+    }
+
+    #[test]
+    fn dropped_non_finite_vertices_leave_finite_bounds() {
+        let (gltf, mut buffers) = triangle([0, 1, 2], None);
+        // x of the third position
+        buffers[0][24..28].copy_from_slice(&f32::NAN.to_le_bytes());
+        let mesh = gltf.document.meshes().next().unwrap();
+        let primitive = mesh.primitives().next().unwrap();
+        let options = ImportOptions {
+            non_finite_vertices: NonFiniteVertices::DropTriangles,
+            ..Default::default()
+        };
+        let mut report = ImportReport::default();
+        let (vertices, indices, morph_targets) =
+            read_primitive(&primitive, 0, &buffers, &options, None, &mut report).unwrap();
+        assert!(indices.is_empty());
+
+        let positions = crate::graphics::vertex_positions(&vertices);
+        let (center, radius) = crate::graphics::bounding_sphere(&positions, &morph_targets);
+        let (min, max) = crate::graphics::bounding_box(&positions);
+        assert!(center.is_finite() && radius.is_finite());
+        assert_eq!((min, max), (Vec3::ZERO, Vec3::X));
+    }
+
+    #[test]
+    fn removed_degenerate_triangles_are_reported() {
+        let (gltf, buffers) = triangle([0, 1, 1], None);
+        let mesh = gltf.document.meshes().next().unwrap();
+        let primitive = mesh.primitives().next().unwrap();
+        let options = ImportOptions {
+            degenerate_triangles: DegenerateTriangles::Remove,
+            ..Default::default()
+        };
+        let mut report = ImportReport::default();
+        let (_, indices, _) =
+            read_primitive(&primitive, 0, &buffers, &options, None, &mut report).unwrap();
+        assert!(indices.is_empty());
+        assert_eq!(report.degenerate_triangles, [(1, 0, 0)]);
+        assert!(matches!(
+            report.issues()[..],
+            [ValidationIssue::DegenerateTriangles(1, 0, 0)]
+        ));
+    }
+
+    #[test]
+    fn index_past_the_threshold_is_reported() {
+        let (gltf, buffers) = triangle([0, 1, 2], None);
+        let mesh = gltf.document.meshes().next().unwrap();
+        let primitive = mesh.primitives().next().unwrap();
+        let options = ImportOptions {
+            index_warning_threshold: Some(2),
+            ..Default::default()
+        };
+        let mut report = ImportReport::default();
+        read_primitive(&primitive, 0, &buffers, &options, None, &mut report).unwrap();
+        assert_eq!(report.index_near_limit, [(2, 0, 0)]);
+    }
+
+    #[test]
+    fn jpeg_placeholder_is_its_average_color() {
+        let image = image::RgbImage::from_fn(64, 64, |x, _| {
+            if x < 32 {
+                image::Rgb([200, 0, 0])
+            } else {
+                image::Rgb([0, 0, 200])
+            }
+        });
+        let mut data = vec![];
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, 100)
+            .encode_image(&DynamicImage::ImageRgb8(image))
+            .unwrap();
+
+        let [r, g, b, a] = average_color(&data, ImageFormat::Jpeg).unwrap();
+        for (channel, expected) in [r, g, b, a].iter().zip([100, 0, 100, 255].iter()) {
+            assert!(
+                (*channel as i32 - expected).abs() <= 8,
+                "{:?}",
+                [r, g, b, a]
+            );
+        }
+        assert_eq!(average_color(&data, ImageFormat::Png), None);
+    }
+
+    #[test]
+    fn bare_file_names_are_in_the_working_directory() {
+        assert_eq!(base_dir("scene.gltf"), PathBuf::new());
+        assert_eq!(base_dir("samples/scene.gltf"), PathBuf::from("samples"));
+        assert_eq!(
+            base_dir("scene.gltf").join("scene.bin"),
+            PathBuf::from("scene.bin")
+        );
+    }
+
+    #[test]
+    fn tangent_handedness_is_kept() {
+        let tangents = [
+            [1.0, 0.0, 0.0, -1.0],
+            [1.0, 0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, -1.0],
+        ];
+        let (gltf, buffers) = triangle([0, 1, 2], Some(tangents));
+        let (vertices, _, _) = read_triangle(&gltf, &buffers).unwrap();
+        let read = vertices.iter().map(|it| it.tangent).collect::<Vec<_>>();
+        assert_eq!(read, tangents);
+    }
+
+    #[test]
+    fn primitive_without_material_is_white_and_untextured() {
+        let (gltf, _) = triangle([0, 1, 2], None);
+        let mesh = gltf.document.meshes().next().unwrap();
+        let primitive = mesh.primitives().next().unwrap();
+        let material = untextured_material(&primitive.material(), None);
+        assert_eq!(material.base_diffuse_color, [1.0; 4]);
+        assert!(material.diffuse.is_none());
+        assert!(material.normal.is_none());
+        assert!(material.orm.is_none());
+    }
+
+    #[test]
+    fn sparse_morph_target_imports_deltas_for_every_vertex() {
+        // the triangle's buffer, then a sparse index of 1, padding and the
+        // delta there
+        let (_, mut buffers) = triangle([0, 1, 2], None);
+        let delta = [0.0, 0.5, 0.0];
+        let offset = buffers[0].len();
+        buffers[0].extend([1u16, 0].iter().flat_map(|it| it.to_le_bytes()));
+        buffers[0].extend(vec3_bytes(&[delta]));
+
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{ "byteLength": {length} }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 72, "byteLength": 24 }},
+                    {{ "buffer": 0, "byteOffset": 96, "byteLength": 6 }},
+                    {{ "buffer": 0, "byteOffset": {offset}, "byteLength": 2 }},
+                    {{ "buffer": 0, "byteOffset": {values}, "byteLength": 12 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0] }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                    {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                    {{ "bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR" }},
+                    {{ "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [0, 0.5, 0],
+                       "sparse": {{
+                           "count": 1,
+                           "indices": {{ "bufferView": 4, "componentType": 5123 }},
+                           "values": {{ "bufferView": 5 }}
+                       }} }}
+                ],
+                "meshes": [{{ "primitives": [{{
+                    "attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }},
+                    "indices": 3,
+                    "targets": [{{ "POSITION": 4 }}]
+                }}] }}]
+            }}"#,
+            length = buffers[0].len(),
+            offset = offset,
+            values = offset + 4,
+        );
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let (vertices, _, morph_targets) = read_triangle(&gltf, &buffers).unwrap();
+
+        assert_eq!(vertices.len(), 3);
+        assert_eq!(morph_targets.len(), 1);
+        assert_eq!(morph_targets[0].positions, vec![[0.0; 3], delta, [0.0; 3]]);
+        assert_eq!(morph_targets[0].normals, vec![[0.0; 3]; 3]);
+    }
+
+    #[test]
+    fn sparse_morph_target_only_moves_its_vertices() {
+        // six base positions, then indices 1 and 4 and the values there
+        let base = (0..6).map(|i| [i as f32, 0.0, 0.0]).collect::<Vec<_>>();
+        let moved = [[0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let mut buffer = vec3_bytes(&base);
+        buffer.extend([1u16, 4].iter().flat_map(|it| it.to_le_bytes()));
+        buffer.extend(vec3_bytes(&moved));
+
+        let sparse = r#"{
+            "count": 2,
+            "indices": { "bufferView": 1, "componentType": 5123 },
+            "values": { "bufferView": 2 }
+        }"#;
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{ "byteLength": {} }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 72 }},
+                    {{ "buffer": 0, "byteOffset": 72, "byteLength": 4 }},
+                    {{ "buffer": 0, "byteOffset": 76, "byteLength": 24 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 6, "type": "VEC3",
+                       "sparse": {sparse} }},
+                    {{ "componentType": 5126, "count": 6, "type": "VEC3", "sparse": {sparse} }}
+                ]
+            }}"#,
+            buffer.len(),
+            sparse = sparse,
+        );
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let buffers = vec![buffer];
+        let mut accessors = gltf.document.accessors();
+
+        let with_base = read_vec3_accessor(&accessors.next().unwrap(), &buffers).unwrap();
+        let mut expected = base.clone();
+        expected[1] = moved[0];
+        expected[4] = moved[1];
+        assert_eq!(with_base, expected);
+
+        let without_base = read_vec3_accessor(&accessors.next().unwrap(), &buffers).unwrap();
+        let mut expected = vec![[0.0; 3]; 6];
+        expected[1] = moved[0];
+        expected[4] = moved[1];
+        assert_eq!(without_base, expected);
+    }
+}
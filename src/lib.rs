@@ -5,21 +5,50 @@ pub mod graphics;
 
 pub mod catalog;
 pub use catalog::Catalog;
-use glam::Mat4;
+use glam::{Mat4, Vec3, Vec4};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet, VecDeque},
+};
 
-use graphics::GraphicsContext;
+use graphics::{GraphicsContext, Topology};
 use smallvec::SmallVec;
-use transform::Transform;
+use transform::{Transform, Trs};
 
+pub mod animation;
 pub mod camera;
+pub mod foliage;
 pub mod import_gltf;
+pub mod particles;
+pub mod render_graph;
+pub mod simplify;
 pub mod transform;
 
+/// Refers to a node of a scene, staying valid as other nodes are added to or
+/// removed from it.
+pub type NodeId = catalog::Id<Node>;
+
 #[derive(Debug)]
 pub struct Scene {
-    pub nodes: Vec<Node>,
-    pub root_nodes: SmallVec<[u16; 4]>,
+    pub nodes: Catalog<Node>,
+    pub root_nodes: SmallVec<[NodeId; 4]>,
     pub transform: Transform,
+    pub animations: Vec<animation::Animation>,
+    /// Application specific data from the glTF `extras` of the scene.
+    pub extras: Option<serde_json::Value>,
+    pub asset: AssetInfo,
+}
+
+/// Metadata about the file a scene was imported from, from the glTF `asset`.
+#[derive(Debug, Clone, Default)]
+pub struct AssetInfo {
+    /// Tool that exported the file.
+    pub generator: Option<String>,
+    /// glTF version the file follows.
+    pub version: String,
+    /// Oldest glTF version able to load the file, if it isn't `version`.
+    pub min_version: Option<String>,
+    pub copyright: Option<String>,
 }
 
 impl Scene {
@@ -29,77 +58,983 @@ impl Scene {
         perspective: Mat4,
         view: Mat4,
     ) {
-        let base_transform = &self.transform;
-        for node in &self.nodes {
+        let frustum = frustum_planes(perspective * view);
+        let camera_position = view.inverse().w_axis.truncate();
+        let mut opaque = vec![];
+        let mut blended = vec![];
+        for node in self.nodes.iter() {
             if node.meshes.is_empty() {
                 continue;
             }
 
-            let transform = {
-                let mut current = node;
-                let mut transform = node.transform.mat4().clone();
-                'transform: loop {
-                    current = match current.parent {
-                        Some(index) => &self.nodes[usize::from(index)],
-                        None => break 'transform,
-                    };
+            let model = self.model_matrix(node);
+
+            for (mesh, ub, material) in &node.meshes {
+                let drawn = match mesh.topology() {
+                    Topology::Points => pass.draws_points(),
+                    Topology::Triangles => pass.draws(material),
+                };
+                if !drawn {
+                    continue;
+                }
+                // the sphere is the cheap test, the box the tighter one, but
+                // the box leaves out morph targets
+                let sphere = transform_sphere(model, mesh.bounding_sphere());
+                if !sphere_in_frustum(&frustum, sphere) {
+                    continue;
+                }
+                if mesh.morph_targets.is_empty()
+                    && !box_in_frustum(&frustum, transform_box(model, mesh.bounding_box()))
+                {
+                    continue;
+                }
+
+                let fade = match node.fade_distance {
+                    Some(fade_distance) => {
+                        fade_at(fade_distance, sphere.0.distance(camera_position))
+                    }
+                    None => 1.0,
+                };
+                if fade <= 0.0 {
+                    continue;
+                }
+
+                let distance = sphere.0.distance_squared(camera_position);
+                let draw = (
+                    mesh,
+                    ub,
+                    material,
+                    model,
+                    &node.morph_weights,
+                    fade,
+                    node.tint,
+                );
+                if material.blended() {
+                    blended.push((distance, draw));
+                } else {
+                    let state = (material.sort_key(), mesh.sort_key());
+                    opaque.push(((state, distance), draw));
+                }
+            }
+        }
+
+        // materials sort by pipeline and then by texture, so sorting by
+        // material and then by mesh keeps redundant binds to a minimum, and
+        // front to back among the rest lets the depth test skip hidden ones
+        opaque.sort_by(|(a, _), (b, _)| {
+            a.0.cmp(&b.0)
+                .then(a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        });
+        // blending needs what's behind drawn first, whatever the binds
+        blended.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        let draws: Vec<_> = opaque
+            .into_iter()
+            .map(|(_, draw)| draw)
+            .chain(blended.into_iter().map(|(_, draw)| draw))
+            .collect();
+
+        for &(mesh, ub, material, model, morph_weights, fade, tint) in &draws {
+            pass.render_mesh(
+                mesh,
+                ub,
+                material,
+                perspective,
+                view,
+                model,
+                morph_weights,
+                fade,
+                tint,
+            );
+        }
+
+        // after every face, so the state changes once
+        if pass.draws_wireframe() {
+            for &(mesh, ub, _, _, _, _, _) in &draws {
+                pass.render_wireframe(mesh, ub);
+            }
+        }
+    }
+
+    /// Sphere around every mesh of the scene in world space, or `None` if it
+    /// has no meshes.
+    pub fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        self.nodes
+            .iter()
+            .flat_map(|node| {
+                let model = self.model_matrix(node);
+                node.meshes
+                    .iter()
+                    .map(move |(mesh, _, _)| transform_sphere(model, mesh.bounding_sphere()))
+            })
+            .reduce(merge_spheres)
+    }
+
+    /// Triangles drawn for the whole scene, counting meshes shared between
+    /// nodes once per node.
+    pub fn triangle_count(&self) -> usize {
+        self.meshes().map(|mesh| mesh.index_count / 3).sum()
+    }
+
+    /// Vertices of every mesh in the scene, counting meshes shared between
+    /// nodes once per node.
+    pub fn vertex_count(&self) -> usize {
+        self.meshes().map(|mesh| mesh.vertex_count).sum()
+    }
 
-                    transform = transform * current.transform.mat4();
+    /// GPU memory taken by the buffers and textures of the scene's meshes.
+    /// Meshes and textures shared between nodes or materials are counted
+    /// once, but buffers of meshes imported together are counted by the part
+    /// each mesh uses, without any padding the driver adds.
+    pub fn gpu_memory_estimate(&self) -> GpuMemoryReport {
+        let mut report = GpuMemoryReport::default();
+        let mut meshes = HashSet::new();
+        let mut textures = HashSet::new();
+        for (mesh, uniform_buffer, material) in self.nodes.iter().flat_map(|it| &it.meshes) {
+            report.uniform_bytes += uniform_buffer.byte_size();
+            if meshes.insert((
+                mesh.sort_key(),
+                mesh.index_range().start,
+                mesh.base_vertex(),
+            )) {
+                report.vertex_bytes += mesh.vertex_bytes;
+                report.index_bytes += mesh.index_bytes;
+                report.morph_bytes += mesh.morph_byte_size();
+            }
+            for texture in material.textures() {
+                if textures.insert(texture.texels_handle()) {
+                    report.texture_bytes += texture.byte_size();
+                    report.texture_count += 1;
                 }
-                Transform::from(transform)
+            }
+        }
+        report
+    }
+
+    /// The closest mesh hit by the ray from `origin` along `direction`, as
+    /// its node and the distance to where the ray enters its bounding box,
+    /// in units of `direction`'s length. Boxes are tested rather than
+    /// triangles, so it's coarse; see `raycast_boxes`.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<(NodeId, f32)> {
+        self.raycast_boxes(origin, direction)
+            .into_iter()
+            .filter_map(|it| Some((it.node, it.distance?)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+    }
+
+    /// Every box `raycast` tests, one per mesh: the world space box around
+    /// the mesh's model space bounding box, and where the ray enters it if it
+    /// does. For seeing why a raycast hit or missed something.
+    pub fn raycast_boxes(&self, origin: Vec3, direction: Vec3) -> Vec<RayBoxTest> {
+        self.nodes
+            .iter_with_id()
+            .flat_map(|(id, node)| {
+                let model = self.model_matrix(node);
+                node.meshes.iter().map(move |(mesh, _, _)| {
+                    let (min, max) = transform_box(model, mesh.bounding_box());
+                    RayBoxTest {
+                        node: *id,
+                        min,
+                        max,
+                        distance: ray_box_distance(origin, direction, min, max),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn meshes(&self) -> impl Iterator<Item = &graphics::Mesh> {
+        self.nodes
+            .iter()
+            .flat_map(|node| node.meshes.iter().map(|(mesh, _, _)| mesh))
+    }
+
+    /// The material of every mesh in the scene. Meshes imported with the same
+    /// glTF material have equal copies of it, which show up once per mesh.
+    pub fn materials(&self) -> impl Iterator<Item = &graphics::Material> {
+        self.nodes
+            .iter()
+            .flat_map(|node| node.meshes.iter().map(|(_, _, material)| material))
+    }
+
+    /// Like `materials`, for replacing them, e.g. every one with a given name.
+    pub fn materials_mut(&mut self) -> impl Iterator<Item = &mut graphics::Material> {
+        self.nodes
+            .iter_mut()
+            .flat_map(|node| node.meshes.iter_mut().map(|(_, _, material)| material))
+    }
+
+    /// Transform from the model space of `node`'s meshes to world space.
+    fn model_matrix(&self, node: &Node) -> Mat4 {
+        self.node_matrix(node) * self.transform.mat4()
+    }
+
+    /// Like `model_matrix`, leaving out the scene's own `transform`.
+    pub(crate) fn node_matrix(&self, node: &Node) -> Mat4 {
+        let mut current = node;
+        let mut transform = node.transform.mat4().clone();
+        'transform: loop {
+            current = match self.nodes.get_opt(current.parent) {
+                Some(parent) => parent,
+                None => break 'transform,
             };
 
-            for (mesh, ub, material) in &node.meshes {
-                let base_transform = base_transform.mat4();
-                let mesh_transform = transform.mat4();
-                let model = mesh_transform * base_transform;
+            transform = transform * current.transform.mat4();
+        }
+        transform
+    }
+
+    /// The node named `name` closest to the roots, the first one in order if
+    /// there are several at the same depth.
+    pub fn find_node(&self, name: &str) -> Option<NodeId> {
+        let mut pending: VecDeque<NodeId> = self.root_nodes.iter().copied().collect();
+        while let Some(id) = pending.pop_front() {
+            let node = self.nodes.get(id)?;
+            if node.name.as_deref() == Some(name) {
+                return Some(id);
+            }
+            pending.extend(node.children.iter().copied());
+        }
+        None
+    }
+
+    /// Moves the nodes and animations of `other` into this scene, giving them
+    /// new ids. Its root nodes become children of `attach_to` when given, or
+    /// roots of this scene otherwise. The `transform` of `other` is dropped,
+    /// so its nodes end up under this scene's.
+    pub fn merge(&mut self, other: Scene, attach_to: Option<NodeId>) -> Result<(), AyudeError> {
+        if let Some(parent) = attach_to {
+            self.check_node(parent)?;
+        }
+
+        let new_ids = other
+            .nodes
+            .iter_ids()
+            .map(|&id| (id, self.nodes.reserve()))
+            .collect::<HashMap<NodeId, NodeId>>();
+        // references to nodes missing from `other` are dropped
+        let remap = |id: NodeId| new_ids.get(&id).copied();
+
+        for (id, mut node) in other.nodes {
+            node.parent = node.parent.and_then(remap);
+            node.children = node.children.iter().filter_map(|&it| remap(it)).collect();
+            if let Some(skin) = node.skin.take() {
+                let joints: Option<_> = skin.joints.iter().map(|&it| remap(it)).collect();
+                node.skin = joints.map(|joints| Skin {
+                    joints,
+                    skeleton: skin.skeleton.and_then(remap),
+                    ..skin
+                });
+            }
+            self.nodes.insert(new_ids[&id], node);
+        }
+
+        for root in other.root_nodes.iter().filter_map(|&it| remap(it)) {
+            match attach_to {
+                Some(parent) => {
+                    self.nodes.get_mut(root).unwrap().parent = Some(parent);
+                    self.nodes.get_mut(parent).unwrap().children.push(root);
+                }
+                None => self.root_nodes.push(root),
+            }
+        }
+
+        for mut animation in other.animations {
+            animation
+                .channels
+                .retain(|it| new_ids.contains_key(&it.node));
+            for channel in &mut animation.channels {
+                channel.node = new_ids[&channel.node];
+            }
+            self.animations.push(animation);
+        }
+        Ok(())
+    }
+
+    /// Moves `child` under `new_parent`, or to the roots of the scene with
+    /// `None`. Its local transform is kept, so it moves along with its new
+    /// parent.
+    pub fn reparent(
+        &mut self,
+        child: NodeId,
+        new_parent: Option<NodeId>,
+    ) -> Result<(), AyudeError> {
+        self.check_node(child)?;
+        if let Some(parent) = new_parent {
+            self.check_node(parent)?;
+            if self.is_descendant(parent, child) {
+                return Err(AyudeError::CyclicParent(child, parent));
+            }
+        }
 
-                pass.render_mesh(mesh, ub, material, perspective, view, model);
+        self.detach(child);
+        match new_parent {
+            Some(parent) => self.nodes.get_mut(parent).unwrap().children.push(child),
+            None => self.root_nodes.push(child),
+        }
+        self.nodes.get_mut(child).unwrap().parent = new_parent;
+        Ok(())
+    }
+
+    /// Removes `id` and all its descendants. Ids of the remaining nodes are
+    /// unaffected. Skins using a removed node as a joint are removed too, as
+    /// are animation channels targeting removed nodes.
+    pub fn remove_node(&mut self, id: NodeId) -> Result<(), AyudeError> {
+        self.check_node(id)?;
+        self.detach(id);
+
+        let mut removed = HashSet::new();
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.nodes.remove(id) {
+                removed.insert(id);
+                pending.extend_from_slice(&node.children);
+            }
+        }
+
+        for node in self.nodes.iter_mut() {
+            let skin = match &mut node.skin {
+                Some(skin) => skin,
+                None => continue,
+            };
+            if skin.joints.iter().any(|it| removed.contains(it)) {
+                node.skin = None;
+            } else {
+                skin.skeleton = skin.skeleton.filter(|it| !removed.contains(it));
             }
         }
+        for animation in &mut self.animations {
+            animation.channels.retain(|it| !removed.contains(&it.node));
+        }
+        Ok(())
+    }
+
+    /// Takes `id` out of its parent's children, or the root nodes.
+    fn detach(&mut self, id: NodeId) {
+        match self.nodes.get(id).and_then(|it| it.parent) {
+            Some(parent) => {
+                if let Some(parent) = self.nodes.get_mut(parent) {
+                    parent.children.retain(|it| *it != id);
+                }
+            }
+            None => self.root_nodes.retain(|it| *it != id),
+        }
+    }
+
+    fn check_node(&self, id: NodeId) -> Result<(), AyudeError> {
+        if self.nodes.contains(id) {
+            Ok(())
+        } else {
+            Err(AyudeError::NodeNotFound(id))
+        }
+    }
+
+    /// Whether `node` is `ancestor` or somewhere below it.
+    fn is_descendant(&self, node: NodeId, ancestor: NodeId) -> bool {
+        let mut current = Some(node);
+        while let Some(id) = current {
+            if id == ancestor {
+                return true;
+            }
+            current = self.nodes.get(id).and_then(|it| it.parent);
+        }
+        false
+    }
+
+    /// What changed from this scene to `new`, e.g. a reimport of the same
+    /// file. Nodes are matched by name, several with the same name (or
+    /// without one) in the order of their ids.
+    pub fn diff(&self, new: &Scene) -> SceneDiff {
+        let mut diff = SceneDiff::default();
+        let mut by_name: HashMap<Option<&str>, VecDeque<NodeId>> = HashMap::new();
+        for (id, node) in new.nodes.iter_with_id() {
+            by_name
+                .entry(node.name.as_deref())
+                .or_default()
+                .push_back(*id);
+        }
+
+        for (old_id, old_node) in self.nodes.iter_with_id() {
+            let new_id = match by_name
+                .get_mut(&old_node.name.as_deref())
+                .and_then(|it| it.pop_front())
+            {
+                Some(id) => id,
+                None => {
+                    diff.removed.push(*old_id);
+                    continue;
+                }
+            };
+            let new_node = match new.nodes.get(new_id) {
+                Some(node) => node,
+                None => continue,
+            };
+            let pair = (*old_id, new_id);
+            if old_node.transform.mat4() != new_node.transform.mat4() {
+                diff.moved.push(pair);
+            }
+            let same_meshes = old_node.meshes.len() == new_node.meshes.len()
+                && old_node
+                    .meshes
+                    .iter()
+                    .zip(&new_node.meshes)
+                    .all(|((old, _, _), (new, _, _))| same_geometry(old, new));
+            if !same_meshes {
+                diff.geometry_changed.push(pair);
+            }
+            let same_materials = old_node.meshes.len() == new_node.meshes.len()
+                && old_node
+                    .meshes
+                    .iter()
+                    .zip(&new_node.meshes)
+                    .all(|((_, _, old), (_, _, new))| same_material(old, new));
+            if !same_materials {
+                diff.materials_changed.push(pair);
+            }
+            diff.matched.push(pair);
+        }
+
+        let mut added: Vec<NodeId> = by_name.into_values().flatten().collect();
+        added.sort();
+        diff.added = added;
+        diff
     }
 
     pub fn duplicate(&self, graphics: &GraphicsContext) -> Self {
         Self {
-            nodes: self.nodes.iter().map(|it| it.duplicate(graphics)).collect(),
+            nodes: self.nodes.map(|it| it.duplicate(graphics)),
             root_nodes: self.root_nodes.clone(),
             transform: self.transform.clone(),
+            animations: self.animations.clone(),
+            extras: self.extras.clone(),
+            asset: self.asset.clone(),
         }
     }
 }
 
-#[derive(Debug)]
 pub struct Node {
-    pub parent: Option<u16>,
-    pub children: SmallVec<[u16; 4]>,
+    pub parent: Option<NodeId>,
+    pub children: SmallVec<[NodeId; 4]>,
     pub transform: Transform,
+    /// Same as `transform`, decomposed for animation.
+    pub trs: Trs,
     pub meshes: Vec<(graphics::Mesh, graphics::UniformBuffer, graphics::Material)>,
     pub skin: Option<Skin>,
+    /// Weights of the morph targets of this node's meshes.
+    pub morph_weights: Vec<f32>,
+    /// Distances from the camera at which the meshes of this node start
+    /// dithering away and are gone completely, if they should. With the
+    /// second no further than the first, they're cut off at the first.
+    pub fade_distance: Option<(f32, f32)>,
+    /// Multiplies the color of this node's meshes, on top of their material,
+    /// e.g. to highlight a selection. White leaves them as they are.
+    pub tint: [f32; 4],
     pub name: Option<String>,
+    /// Application specific data from the glTF `extras` of the node, such as
+    /// gameplay properties set in the authoring tool.
+    pub extras: Option<serde_json::Value>,
+    /// Whatever the application wants to keep with the node, e.g. a physics
+    /// handle, see `user_data`. Moves with the node through reparenting and
+    /// merges, but isn't copied by `duplicate`. `None` after import.
+    pub user_data: Option<Box<dyn Any>>,
+}
+
+impl std::fmt::Debug for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Node")
+            .field("parent", &self.parent)
+            .field("children", &self.children)
+            .field("transform", &self.transform)
+            .field("trs", &self.trs)
+            .field("meshes", &self.meshes)
+            .field("skin", &self.skin)
+            .field("morph_weights", &self.morph_weights)
+            .field("fade_distance", &self.fade_distance)
+            .field("tint", &self.tint)
+            .field("name", &self.name)
+            .field("extras", &self.extras)
+            .field("user_data", &self.user_data.as_ref().map(|_| ".."))
+            .finish()
+    }
 }
 
 impl Node {
+    /// `user_data` if it's a `T`.
+    pub fn user_data<T: Any>(&self) -> Option<&T> {
+        self.user_data.as_ref()?.downcast_ref()
+    }
+
+    pub fn user_data_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.user_data.as_mut()?.downcast_mut()
+    }
+
+    /// Leaves `user_data` out, since it can't be cloned.
     pub fn duplicate(&self, graphics: &GraphicsContext) -> Self {
         Self {
             parent: self.parent.clone(),
             children: self.children.clone(),
             transform: self.transform.clone(),
+            trs: self.trs,
             meshes: self
                 .meshes
                 .iter()
                 .map(|(mesh, _, mat)| {
-                    (mesh.clone(), graphics.create_uniform_buffer(), mat.clone())
+                    (
+                        mesh.clone(),
+                        graphics.create_mesh_uniform_buffer(mesh),
+                        mat.clone(),
+                    )
                 })
                 .collect(),
             skin: self.skin.clone(),
+            morph_weights: self.morph_weights.clone(),
+            fade_distance: self.fade_distance,
+            tint: self.tint,
             name: self.name.clone(),
+            extras: self.extras.clone(),
+            user_data: None,
+        }
+    }
+
+    /// Replaces the material of the mesh at `mesh_index` in `meshes`,
+    /// returning the previous one. The geometry and uniform buffer are kept,
+    /// and the new material's textures are bound from the next render on.
+    ///
+    /// Panics if the node doesn't have that many meshes.
+    pub fn set_material(
+        &mut self,
+        mesh_index: usize,
+        material: graphics::Material,
+    ) -> graphics::Material {
+        std::mem::replace(&mut self.meshes[mesh_index].2, material)
+    }
+}
+
+/// A box tested by `Scene::raycast_boxes`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayBoxTest {
+    pub node: NodeId,
+    /// Corners of the box in world space.
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Distance along the ray to where it enters the box, 0 if it starts
+    /// inside, or `None` if it misses it.
+    pub distance: Option<f32>,
+}
+
+/// Bytes of GPU memory used by a scene, from `Scene::gpu_memory_estimate`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuMemoryReport {
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    /// Morph target deltas.
+    pub morph_bytes: u64,
+    /// One buffer per mesh of every node.
+    pub uniform_bytes: u64,
+    pub texture_bytes: u64,
+    /// Distinct textures counted in `texture_bytes`.
+    pub texture_count: usize,
+}
+
+impl GpuMemoryReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.vertex_bytes
+            + self.index_bytes
+            + self.morph_bytes
+            + self.uniform_bytes
+            + self.texture_bytes
+    }
+}
+
+/// Differences between two versions of a scene, from `Scene::diff`. Nodes are
+/// listed by their ids in the old scene, the new one, or both as
+/// (old, new) pairs.
+#[derive(Debug, Clone, Default)]
+pub struct SceneDiff {
+    /// Nodes present in both scenes, whether they changed or not.
+    pub matched: Vec<(NodeId, NodeId)>,
+    /// Only in the new scene.
+    pub added: Vec<NodeId>,
+    /// Only in the old scene.
+    pub removed: Vec<NodeId>,
+    /// Matched nodes with a different local transform.
+    pub moved: Vec<(NodeId, NodeId)>,
+    /// Matched nodes with different vertex positions, triangles or morph
+    /// targets in any of their meshes, or a different number of meshes.
+    pub geometry_changed: Vec<(NodeId, NodeId)>,
+    /// Matched nodes with a different material on any of their meshes.
+    /// Textures are compared by size and texture transform only, since their
+    /// texels aren't kept on the CPU, so repainting one without resizing it
+    /// isn't noticed.
+    pub materials_changed: Vec<(NodeId, NodeId)>,
+}
+
+impl SceneDiff {
+    /// Whether the nodes are all there with the same transforms, geometry
+    /// and materials.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.geometry_changed.is_empty()
+            && self.materials_changed.is_empty()
+    }
+}
+
+fn same_geometry(old: &graphics::Mesh, new: &graphics::Mesh) -> bool {
+    old.topology() == new.topology()
+        && old.positions() == new.positions()
+        && old.indices() == new.indices()
+        && old.morph_targets.len() == new.morph_targets.len()
+        && old
+            .morph_targets
+            .iter()
+            .zip(new.morph_targets.iter())
+            .all(|(old, new)| old.positions == new.positions && old.normals == new.normals)
+}
+
+/// Like `Material`'s `PartialEq`, but comparing textures by what can be known
+/// of their contents instead of by identity.
+fn same_material(old: &graphics::Material, new: &graphics::Material) -> bool {
+    fn same_texture(old: Option<&graphics::Texture>, new: Option<&graphics::Texture>) -> bool {
+        match (old, new) {
+            (None, None) => true,
+            (Some(old), Some(new)) => {
+                old.width == new.width
+                    && old.height == new.height
+                    && old.uv_transform == new.uv_transform
+            }
+            _ => false,
+        }
+    }
+    let same_orm = match (&old.orm, &new.orm) {
+        (None, None) => true,
+        (Some(old), Some(new)) => {
+            old.has_occlusion == new.has_occlusion
+                && old.has_metallic_roughness == new.has_metallic_roughness
+                && same_texture(Some(&old.texture), Some(&new.texture))
         }
+        _ => false,
+    };
+    if !(same_orm
+        && same_texture(old.normal.as_ref(), new.normal.as_ref())
+        && same_texture(old.diffuse.as_ref(), new.diffuse.as_ref())
+        && same_texture(
+            old.transmission_texture.as_ref(),
+            new.transmission_texture.as_ref(),
+        )
+        && same_texture(
+            old.clearcoat_texture.as_ref(),
+            new.clearcoat_texture.as_ref(),
+        )
+        && same_texture(
+            old.clearcoat_roughness_texture.as_ref(),
+            new.clearcoat_roughness_texture.as_ref(),
+        )
+        && same_texture(old.specular_texture.as_ref(), new.specular_texture.as_ref())
+        && same_texture(
+            old.specular_color_texture.as_ref(),
+            new.specular_color_texture.as_ref(),
+        ))
+    {
+        return false;
     }
+
+    // with the textures swapped for the old ones, the rest compares as usual
+    let mut new = new.clone();
+    new.normal = old.normal.clone();
+    new.diffuse = old.diffuse.clone();
+    new.orm = old.orm.clone();
+    new.transmission_texture = old.transmission_texture.clone();
+    new.clearcoat_texture = old.clearcoat_texture.clone();
+    new.clearcoat_roughness_texture = old.clearcoat_roughness_texture.clone();
+    new.specular_texture = old.specular_texture.clone();
+    new.specular_color_texture = old.specular_color_texture.clone();
+    *old == new && old.name == new.name
+}
+
+/// `sphere` moved by `model`, with its radius scaled by the largest scale
+/// along any axis so it still covers everything it did.
+fn transform_sphere(model: Mat4, (center, radius): (Vec3, f32)) -> (Vec3, f32) {
+    let scale = Vec3::new(
+        model.x_axis.truncate().length(),
+        model.y_axis.truncate().length(),
+        model.z_axis.truncate().length(),
+    );
+    (model.transform_point3(center), radius * scale.max_element())
+}
+
+/// Axis aligned box around the box from `min` to `max` moved by `model`.
+fn transform_box(model: Mat4, (min, max): (Vec3, Vec3)) -> (Vec3, Vec3) {
+    let corners = (0..8).map(|i| {
+        model.transform_point3(Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        ))
+    });
+    corners.fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), it| (min.min(it), max.max(it)),
+    )
+}
+
+/// Where the ray enters the box, by intersecting the slabs between each pair
+/// of opposite faces. Axes the ray is parallel to divide by zero into
+/// infinities, which the comparisons handle.
+fn ray_box_distance(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<f32> {
+    let inverse = direction.recip();
+    let a = (min - origin) * inverse;
+    let b = (max - origin) * inverse;
+    let near = a.min(b).max_element();
+    let far = a.max(b).min_element();
+    if near <= far && far >= 0.0 {
+        Some(near.max(0.0))
+    } else {
+        None
+    }
+}
+
+/// Smallest sphere covering both `a` and `b`.
+fn merge_spheres(a: (Vec3, f32), b: (Vec3, f32)) -> (Vec3, f32) {
+    let ((a_center, a_radius), (b_center, b_radius)) = (a, b);
+    let distance = a_center.distance(b_center);
+    if distance + b_radius <= a_radius {
+        return a;
+    }
+    if distance + a_radius <= b_radius {
+        return b;
+    }
+    let radius = (a_radius + distance + b_radius) * 0.5;
+    let center = a_center + (b_center - a_center) * ((radius - a_radius) / distance);
+    (center, radius)
+}
+
+/// Planes of the frustum of `view_projection` as (normal, distance) with
+/// normals pointing inside, extracted from the matrix rows (Gribb and
+/// Hartmann). The near plane is where wgpu clips, depth 0, which for
+/// projections made for OpenGL's -1 to 1 depth range is past their own near
+/// plane. A plane the matrix doesn't have, like the far plane of an infinite
+/// projection, is left as one that every point is inside of.
+fn frustum_planes(view_projection: Mat4) -> [Vec4; 6] {
+    let row = |i| view_projection.row(i);
+    let planes = [
+        row(3) + row(0),
+        row(3) - row(0),
+        row(3) + row(1),
+        row(3) - row(1),
+        row(2),
+        row(3) - row(2),
+    ];
+    let normalize = |plane: Vec4| {
+        let length = plane.truncate().length();
+        if length <= f32::EPSILON * plane.w.abs().max(1.0) {
+            Vec4::ZERO
+        } else {
+            plane / length
+        }
+    };
+    [
+        normalize(planes[0]),
+        normalize(planes[1]),
+        normalize(planes[2]),
+        normalize(planes[3]),
+        normalize(planes[4]),
+        normalize(planes[5]),
+    ]
+}
+
+/// How opaque a mesh fading away over `(start, end)` is at `distance`.
+fn fade_at((start, end): (f32, f32), distance: f32) -> f32 {
+    if end <= start {
+        return if distance < start { 1.0 } else { 0.0 };
+    }
+    1.0 - ((distance - start) / (end - start)).clamp(0.0, 1.0)
+}
+
+fn sphere_in_frustum(planes: &[Vec4; 6], (center, radius): (Vec3, f32)) -> bool {
+    planes
+        .iter()
+        .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+}
+
+/// Whether the box is at least partly in front of every plane, by testing the
+/// corner farthest along each plane's normal. Boxes near a frustum corner can
+/// pass while outside, like spheres.
+fn box_in_frustum(planes: &[Vec4; 6], (min, max): (Vec3, Vec3)) -> bool {
+    planes.iter().all(|plane| {
+        let normal = plane.truncate();
+        let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+        normal.dot(corner) + plane.w >= 0.0
+    })
 }
 
 #[derive(Debug, Clone)]
 pub struct Skin {
-    pub joints: SmallVec<[u16; 4]>,
+    pub joints: SmallVec<[NodeId; 4]>,
     pub inverse_bind_matrices: Vec<Transform>,
-    pub skeleton: Option<u16>,
+    pub skeleton: Option<NodeId>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether a sphere is in the frustum of `perspective` seen from the
+    /// origin looking down -z.
+    fn visible(perspective: Mat4, center: [f32; 3], radius: f32) -> bool {
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::new(0.0, 0.0, -1.0), Vec3::Y);
+        sphere_in_frustum(&frustum_planes(perspective * view), (center.into(), radius))
+    }
+
+    #[test]
+    fn frustum_culls_with_every_depth_convention() {
+        let fov = std::f32::consts::FRAC_PI_2;
+        let gl = Mat4::perspective_rh_gl(fov, 1.0, 0.1, 100.0);
+        let wgpu = Mat4::perspective_rh(fov, 1.0, 0.1, 100.0);
+        let infinite = Mat4::perspective_infinite_rh(fov, 1.0, 0.1);
+
+        for &perspective in &[gl, wgpu, infinite] {
+            assert!(visible(perspective, [0.0, 0.0, -10.0], 1.0));
+            // partly inside the side planes, at 10 units from the axis
+            assert!(visible(perspective, [10.5, 0.0, -10.0], 1.0));
+            assert!(!visible(perspective, [0.0, 0.0, 10.0], 1.0));
+            assert!(!visible(perspective, [30.0, 0.0, -10.0], 1.0));
+            assert!(!visible(perspective, [0.0, -30.0, -10.0], 1.0));
+        }
+
+        for &perspective in &[gl, wgpu] {
+            assert!(visible(perspective, [0.0, 0.0, -99.5], 1.0));
+            assert!(!visible(perspective, [0.0, 0.0, -200.0], 1.0));
+        }
+        assert!(visible(infinite, [0.0, 0.0, -10_000.0], 1.0));
+    }
+
+    #[test]
+    fn boxes_are_culled_where_their_sphere_is_not() {
+        let perspective = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::new(0.0, 0.0, -1.0), Vec3::Y);
+        let frustum = frustum_planes(perspective * view);
+        let in_box =
+            |min: [f32; 3], max: [f32; 3]| box_in_frustum(&frustum, (min.into(), max.into()));
+
+        assert!(in_box([-1.0, -1.0, -11.0], [1.0, 1.0, -9.0]));
+        // straddling the near plane
+        assert!(in_box([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]));
+        assert!(!in_box([-1.0, -1.0, 1.0], [1.0, 1.0, 3.0]));
+        assert!(!in_box([-1.0, -1.0, -201.0], [1.0, 1.0, -199.0]));
+
+        // a long thin box just left of the frustum, whose sphere reaches in
+        let (min, max) = (Vec3::new(-30.0, -0.1, -10.1), Vec3::new(-10.5, 0.1, -9.9));
+        let sphere = ((min + max) * 0.5, min.distance(max) * 0.5);
+        assert!(sphere_in_frustum(&frustum, sphere));
+        assert!(!box_in_frustum(&frustum, (min, max)));
+    }
+
+    #[test]
+    fn fade_without_a_range_is_a_cutoff() {
+        assert_eq!(fade_at((10.0, 20.0), 15.0), 0.5);
+        assert_eq!(fade_at((10.0, 10.0), 9.0), 1.0);
+        assert_eq!(fade_at((10.0, 10.0), 10.0), 0.0);
+        assert_eq!(fade_at((10.0, 5.0), 7.0), 1.0);
+    }
+
+    /// A scene of a root node without meshes for each name, with the
+    /// transform next to it.
+    fn named_nodes(nodes: &[(&str, Mat4)]) -> Scene {
+        let mut scene = Scene {
+            nodes: Catalog::new(),
+            root_nodes: SmallVec::new(),
+            transform: Transform::from(Mat4::IDENTITY),
+            animations: vec![],
+            extras: None,
+            asset: AssetInfo::default(),
+        };
+        for &(name, transform) in nodes {
+            let id = scene.nodes.add(Node {
+                parent: None,
+                children: SmallVec::new(),
+                transform: Transform::from(transform),
+                trs: Transform::from(transform).decompose(),
+                meshes: vec![],
+                skin: None,
+                morph_weights: vec![],
+                fade_distance: None,
+                tint: [1.0; 4],
+                name: Some(name.to_string()),
+                extras: None,
+                user_data: None,
+            });
+            scene.root_nodes.push(id);
+        }
+        scene
+    }
+
+    /// A scene of a triangle node for each name, in the color next to it.
+    fn triangles(device: &wgpu::Device, nodes: &[(&str, [f32; 4])]) -> Scene {
+        let names = nodes
+            .iter()
+            .map(|&(name, _)| (name, Mat4::IDENTITY))
+            .collect::<Vec<_>>();
+        let mut scene = named_nodes(&names);
+        for (node, &(_, color)) in scene.nodes.iter_mut().zip(nodes) {
+            let (mesh, uniform_buffer) = graphics::test_support::triangle(device);
+            let material = graphics::test_support::material(color);
+            node.meshes.push((mesh, uniform_buffer, material));
+        }
+        scene
+    }
+
+    #[test]
+    fn diff_matches_nodes_by_name_then_order() {
+        let moved = Mat4::from_translation(Vec3::X);
+        let old = named_nodes(&[
+            ("a", Mat4::IDENTITY),
+            ("twin", Mat4::IDENTITY),
+            ("twin", Mat4::IDENTITY),
+            ("b", Mat4::IDENTITY),
+        ]);
+        let new = named_nodes(&[
+            ("twin", Mat4::IDENTITY),
+            ("twin", moved),
+            ("renamed", Mat4::IDENTITY),
+            ("a", Mat4::IDENTITY),
+        ]);
+        let ids = |scene: &Scene| scene.nodes.iter_ids().copied().collect::<Vec<_>>();
+        let (old_ids, new_ids) = (ids(&old), ids(&new));
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.matched,
+            vec![
+                (old_ids[0], new_ids[3]),
+                (old_ids[1], new_ids[0]),
+                (old_ids[2], new_ids[1]),
+            ]
+        );
+        assert_eq!(diff.added, vec![new_ids[2]]);
+        assert_eq!(diff.removed, vec![old_ids[3]]);
+        assert_eq!(diff.moved, vec![(old_ids[2], new_ids[1])]);
+        assert!(diff.geometry_changed.is_empty());
+        assert!(diff.materials_changed.is_empty());
+    }
+
+    // meshes need a device, run with `cargo test -- --ignored` where there's
+    // an adapter
+    #[test]
+    #[ignore]
+    fn diff_finds_renamed_nodes_and_changed_materials() {
+        let device = graphics::test_support::device().expect("no adapter");
+        let white = [1.0; 4];
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let old = triangles(&device, &[("a", white), ("b", white), ("c", white)]);
+        let new = triangles(&device, &[("a", white), ("renamed", white), ("c", red)]);
+        let id = |scene: &Scene, name| scene.find_node(name).unwrap();
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![id(&new, "renamed")]);
+        assert_eq!(diff.removed, vec![id(&old, "b")]);
+        assert_eq!(diff.materials_changed, vec![(id(&old, "c"), id(&new, "c"))]);
+        assert!(diff.moved.is_empty());
+        assert!(diff.geometry_changed.is_empty());
+    }
 }
@@ -0,0 +1,163 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::graphics::{GraphicsContext, ParticleBuffer, ParticleInstance, Pass, Texture};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    /// Seconds left before the particle dies.
+    pub life: f32,
+    /// Seconds the particle lived for in total, to know how old it is.
+    pub lifetime: f32,
+    pub size: f32,
+    pub color: Vec4,
+}
+
+/// Where and how a `ParticleSystem` spawns particles.
+#[derive(Debug, Clone, Copy)]
+pub struct Emitter {
+    pub position: Vec3,
+    /// Particles spawned per second.
+    pub rate: f32,
+    /// Seconds each particle lives for.
+    pub lifetime: f32,
+    /// Initial velocity, before `velocity_spread` is added.
+    pub velocity: Vec3,
+    /// Each axis of the initial velocity is offset by up to this much, in
+    /// either direction.
+    pub velocity_spread: Vec3,
+    pub size: f32,
+    /// Color at spawn, with alpha fading to zero over the particle's life.
+    pub color: Vec4,
+    /// Acceleration applied to every particle.
+    pub gravity: Vec3,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rate: 10.0,
+            lifetime: 1.0,
+            velocity: Vec3::Y,
+            velocity_spread: Vec3::splat(0.5),
+            size: 0.1,
+            color: Vec4::ONE,
+            gravity: Vec3::ZERO,
+        }
+    }
+}
+
+/// A fixed size pool of particles, simulated on the CPU and drawn as
+/// camera-facing billboards in a single instanced draw call.
+#[derive(Debug)]
+pub struct ParticleSystem {
+    pub emitter: Emitter,
+    particles: Vec<Particle>,
+    capacity: usize,
+    /// Fraction of a particle left over from the previous update.
+    pending: f32,
+    seed: u32,
+    buffer: ParticleBuffer,
+}
+
+impl ParticleSystem {
+    /// Creates an empty system that keeps at most `capacity` particles alive,
+    /// skipping spawns while full.
+    pub fn new(graphics: &GraphicsContext, emitter: Emitter, capacity: usize) -> Self {
+        Self {
+            emitter,
+            particles: Vec::with_capacity(capacity),
+            capacity,
+            pending: 0.0,
+            seed: 0x9e37_79b9,
+            buffer: graphics.create_particle_buffer(capacity),
+        }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Moves every particle forward by `delta` seconds, removing the ones that
+    /// died and spawning new ones at the emitter's rate.
+    pub fn update(&mut self, delta: f32) {
+        let gravity = self.emitter.gravity;
+        let mut i = 0;
+        while i < self.particles.len() {
+            let particle = &mut self.particles[i];
+            particle.life -= delta;
+            if particle.life <= 0.0 {
+                self.particles.swap_remove(i);
+                continue;
+            }
+            particle.velocity += gravity * delta;
+            particle.position += particle.velocity * delta;
+            i += 1;
+        }
+
+        self.pending += self.emitter.rate * delta;
+        while self.pending >= 1.0 {
+            self.pending -= 1.0;
+            if self.particles.len() < self.capacity {
+                let particle = self.spawn();
+                self.particles.push(particle);
+            }
+        }
+    }
+
+    /// Draws the particles, back to front from the camera, over whatever
+    /// opaque geometry is already in `pass`.
+    pub fn render<'a: 'pass, 'pass>(
+        &'a self,
+        pass: &'pass mut Pass<'a, 'a>,
+        perspective: Mat4,
+        view: Mat4,
+        texture: Option<&'a Texture>,
+    ) {
+        let camera_position = view.inverse().w_axis.truncate();
+        let distance = |it: &Particle| it.position.distance_squared(camera_position);
+        let mut sorted: Vec<_> = self.particles.iter().collect();
+        sorted.sort_by(|a, b| {
+            distance(b)
+                .partial_cmp(&distance(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let instances: Vec<_> = sorted
+            .into_iter()
+            .map(|it| {
+                let alpha = it.color.w * (it.life / it.lifetime).clamp(0.0, 1.0);
+                ParticleInstance {
+                    center_size: it.position.extend(it.size).into(),
+                    color: it.color.truncate().extend(alpha).into(),
+                }
+            })
+            .collect();
+
+        pass.render_particles(&self.buffer, &instances, texture, perspective, view);
+    }
+
+    fn spawn(&mut self) -> Particle {
+        let spread = self.emitter.velocity_spread;
+        let offset = Vec3::new(self.random(), self.random(), self.random()) * spread;
+        Particle {
+            position: self.emitter.position,
+            velocity: self.emitter.velocity + offset,
+            life: self.emitter.lifetime,
+            lifetime: self.emitter.lifetime,
+            size: self.emitter.size,
+            color: self.emitter.color,
+        }
+    }
+
+    /// Uniformly distributed in [-1, 1], from a xorshift generator; particles
+    /// don't need anything better.
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
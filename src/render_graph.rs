@@ -0,0 +1,133 @@
+use crate::graphics::{Frame, Pass, PassOps, RenderStats};
+
+/// A texture drawn to by some passes and read by others. These are the
+/// targets the `GraphicsContext` keeps across frames, the graph doesn't
+/// allocate any of its own yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Attachment {
+    /// The HDR scene color, tonemapped at the end of the frame.
+    Color,
+    Depth,
+    /// Accumulation and revealage of order-independent transparency.
+    Transparency,
+    /// The swap chain image.
+    Output,
+}
+
+/// Which of the passes of a `Frame` a node draws in, deciding its targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassTarget {
+    /// `Frame::begin_render_pass`, clearing color and depth.
+    Scene,
+    /// `Frame::begin_decal_pass`.
+    Decal,
+    /// `Frame::begin_transparent_pass`, skipped when order-independent
+    /// transparency is disabled.
+    Transparent,
+    /// `Frame::begin_overlay_pass`, after post-processing.
+    Overlay,
+}
+
+impl PassTarget {
+    /// What has to be drawn before the pass.
+    pub fn inputs(self) -> &'static [Attachment] {
+        match self {
+            PassTarget::Scene => &[],
+            PassTarget::Decal => &[Attachment::Depth],
+            // transparency is composited over the color, and the pass
+            // discards depth, so it goes after everything drawing either
+            PassTarget::Transparent => &[Attachment::Depth, Attachment::Color],
+            PassTarget::Overlay => &[Attachment::Color, Attachment::Transparency],
+        }
+    }
+
+    /// What the pass draws to.
+    pub fn outputs(self) -> &'static [Attachment] {
+        match self {
+            PassTarget::Scene => &[Attachment::Color, Attachment::Depth],
+            PassTarget::Decal => &[Attachment::Color],
+            PassTarget::Transparent => &[Attachment::Transparency],
+            PassTarget::Overlay => &[Attachment::Output],
+        }
+    }
+}
+
+/// Something drawn in one pass of a frame, such as the scene or the HUD.
+pub trait RenderNode {
+    fn target(&self) -> PassTarget;
+
+    /// Attachments that have to be drawn before this node, the ones its pass
+    /// needs by default.
+    fn inputs(&self) -> &[Attachment] {
+        self.target().inputs()
+    }
+
+    fn draw<'a>(&'a self, pass: &mut Pass<'a, 'a>);
+}
+
+/// The nodes drawing a frame, run in an order where every node comes after
+/// the ones drawing its inputs, and otherwise in the order they were added.
+#[derive(Default)]
+pub struct RenderGraph<'n> {
+    nodes: Vec<&'n dyn RenderNode>,
+}
+
+impl<'n> RenderGraph<'n> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, node: &'n dyn RenderNode) {
+        self.nodes.push(node);
+    }
+
+    /// Indices of the nodes in the order they run.
+    ///
+    /// Panics if nodes depend on each other in a cycle.
+    pub fn order(&self) -> Vec<usize> {
+        let depends_on = |node: usize, other: usize| {
+            node != other
+                && self.nodes[other]
+                    .target()
+                    .outputs()
+                    .iter()
+                    .any(|it| self.nodes[node].inputs().contains(it))
+        };
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut done = vec![false; self.nodes.len()];
+        while order.len() < self.nodes.len() {
+            let next = (0..self.nodes.len())
+                .find(|&node| {
+                    !done[node] && (0..self.nodes.len()).all(|it| done[it] || !depends_on(node, it))
+                })
+                .expect("render graph nodes shouldn't depend on each other in a cycle");
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+
+    /// Draws every node in its own pass of `frame`, returning the stats of
+    /// all of them together.
+    pub fn execute(self, frame: &mut Frame) -> RenderStats {
+        let mut stats = RenderStats::default();
+        for index in self.order() {
+            let node = self.nodes[index];
+            let mut pass = match node.target() {
+                PassTarget::Scene => frame.begin_render_pass(PassOps::default()),
+                PassTarget::Decal => frame.begin_decal_pass(),
+                PassTarget::Transparent => match frame.begin_transparent_pass() {
+                    Some(pass) => pass,
+                    None => continue,
+                },
+                PassTarget::Overlay => frame.begin_overlay_pass(),
+            };
+            node.draw(&mut pass);
+            let pass_stats = pass.stats();
+            stats.draw_calls += pass_stats.draw_calls;
+            stats.state_changes += pass_stats.state_changes;
+        }
+        stats
+    }
+}
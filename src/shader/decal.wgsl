@@ -0,0 +1,57 @@
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[block]]
+struct Decal {
+    mvp: mat4x4<f32>;
+    // from normalized device coordinates back to the decal's cube
+    inverse_mvp: mat4x4<f32>;
+    color: vec4<f32>;
+    // size and top left corner of the part of the targets the scene was
+    // rendered to, in pixels
+    viewport_size: vec2<f32>;
+    viewport_origin: vec2<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> decal: Decal;
+
+[[group(1), binding(0)]]
+var decal_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var decal_sampler: sampler;
+
+[[group(2), binding(0)]]
+var depth_texture: texture_depth_2d;
+
+// drawn with a cube from -1 to 1, its back faces only so it still covers the
+// screen with the camera inside it
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = decal.mvp * position;
+    return out;
+}
+
+// finds the scene surface behind each pixel from the depth buffer, and
+// projects the texture onto it along the cube's z axis if it's in the cube
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let depth = textureLoad(depth_texture, vec2<i32>(in.position.xy), 0);
+    let ndc = vec2<f32>(
+        (in.position.x - decal.viewport_origin.x) / decal.viewport_size.x * 2.0 - 1.0,
+        1.0 - (in.position.y - decal.viewport_origin.y) / decal.viewport_size.y * 2.0,
+    );
+    let local = decal.inverse_mvp * vec4<f32>(ndc, depth, 1.0);
+    let p = local.xyz / local.w;
+    if (abs(p.x) > 1.0 || abs(p.y) > 1.0 || abs(p.z) > 1.0) {
+        discard;
+    }
+
+    // neighboring pixels can land on unrelated surfaces, so derivatives
+    // (and with them mipmaps) are meaningless here
+    let tex_coord = vec2<f32>(p.x * 0.5 + 0.5, 0.5 - p.y * 0.5);
+    return textureSampleLevel(decal_texture, decal_sampler, tex_coord, 0.0) * decal.color;
+}
@@ -0,0 +1,19 @@
+// drawn with the cube from -1 to 1, once per tested box, with the box's
+// model-view-projection as the instance
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec4<f32>,
+    [[location(1)]] mvp_x: vec4<f32>,
+    [[location(2)]] mvp_y: vec4<f32>,
+    [[location(3)]] mvp_z: vec4<f32>,
+    [[location(4)]] mvp_w: vec4<f32>,
+) -> [[builtin(position)]] vec4<f32> {
+    return mat4x4<f32>(mvp_x, mvp_y, mvp_z, mvp_w) * position;
+}
+
+// writes nothing, it only has to run for the invocations to be counted,
+// which the depth test skips for hidden fragments
+[[stage(fragment)]]
+fn fs_main() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(0.0);
+}
@@ -0,0 +1,43 @@
+[[block]]
+struct Outline {
+    mvp: mat4x4<f32>;
+    color: vec4<f32>;
+    // size of the part of the targets the scene is rendered to, in pixels
+    viewport_size: vec2<f32>;
+    // in pixels
+    width: f32;
+};
+[[group(0), binding(0)]]
+var<uniform> outline: Outline;
+
+// the mesh as it is, marking its pixels in the stencil buffer
+[[stage(vertex)]]
+fn vs_mask(
+    [[location(0)]] position: vec4<f32>,
+) -> [[builtin(position)]] vec4<f32> {
+    return outline.mvp * position;
+}
+
+// the mesh pushed out along its normals by the width of the outline on
+// screen, whatever its distance from the camera
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec4<f32>,
+    [[location(1)]] normal: vec3<f32>,
+) -> [[builtin(position)]] vec4<f32> {
+    let clip = outline.mvp * position;
+    // direction of the normal on screen, from a point a bit along it
+    let along = outline.mvp * vec4<f32>(position.xyz + normal * 0.01, 1.0);
+    let direction = along.xy / along.w - clip.xy / clip.w;
+    if (length(direction) == 0.0) {
+        return clip;
+    }
+    // pixels to normalized device coordinates, which span 2 units
+    let offset = normalize(direction) * outline.width * 2.0 / outline.viewport_size;
+    return vec4<f32>(clip.xy + offset * clip.w, clip.zw);
+}
+
+[[stage(fragment)]]
+fn fs_main() -> [[location(0)]] vec4<f32> {
+    return outline.color;
+}
@@ -0,0 +1,44 @@
+struct VertexOutput {
+    [[location(0)]] tex_coord: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[block]]
+struct Particles {
+    view_projection: mat4x4<f32>;
+    // world space directions of the screen's x and y axes
+    camera_right: vec4<f32>;
+    camera_up: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> particles: Particles;
+
+[[group(1), binding(0)]]
+var particle_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var particle_sampler: sampler;
+
+// drawn with the shared quad mesh, whose positions go from -1 to 1, once per
+// particle
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec4<f32>,
+    [[location(2)]] tex_coord: vec2<f32>,
+    [[location(3)]] center_size: vec4<f32>,
+    [[location(4)]] color: vec4<f32>,
+) -> VertexOutput {
+    let half_size = center_size.w * 0.5;
+    let offset = (particles.camera_right.xyz * position.x + particles.camera_up.xyz * position.y) * half_size;
+
+    var out: VertexOutput;
+    out.position = particles.view_projection * vec4<f32>(center_size.xyz + offset, 1.0);
+    out.tex_coord = tex_coord;
+    out.color = color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(particle_texture, particle_sampler, in.tex_coord) * in.color;
+}
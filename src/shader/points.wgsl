@@ -0,0 +1,46 @@
+struct VertexOutput {
+    [[location(0)]] corner: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[block]]
+struct Points {
+    model: mat4x4<f32>;
+    view_projection: mat4x4<f32>;
+    // world space directions of the screen's x and y axes
+    camera_right: vec4<f32>;
+    camera_up: vec4<f32>;
+    color: vec4<f32>;
+    point_size: f32;
+};
+[[group(0), binding(0)]]
+var<uniform> points: Points;
+
+// drawn with the shared quad mesh, whose positions go from -1 to 1, once per
+// point
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] corner: vec4<f32>,
+    [[location(1)]] position: vec3<f32>,
+    [[location(2)]] color: vec4<f32>,
+) -> VertexOutput {
+    let center = points.model * vec4<f32>(position, 1.0);
+    let half_size = points.point_size * 0.5;
+    let offset = (points.camera_right.xyz * corner.x + points.camera_up.xyz * corner.y) * half_size;
+
+    var out: VertexOutput;
+    out.position = points.view_projection * vec4<f32>(center.xyz / center.w + offset, 1.0);
+    out.corner = corner.xy;
+    out.color = color * points.color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // round, rather than the square drawn
+    if (dot(in.corner, in.corner) > 1.0) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, 1.0);
+}
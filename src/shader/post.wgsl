@@ -0,0 +1,144 @@
+struct VertexOutput {
+    [[location(0)]] tex_coord: vec2<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[block]]
+struct PostUniforms {
+    exposure: f32;
+    // 0 is Reinhard, 1 is ACES
+    tonemap: u32;
+    // size of a pixel of the target, in texture coordinates
+    texel_size: vec2<f32>;
+    // part of the HDR target the scene was rendered to, in texture coordinates
+    render_scale: vec2<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> post: PostUniforms;
+
+[[group(1), binding(0)]]
+var source_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var source_sampler: sampler;
+
+// only read when resolving transparency, with the accumulation as the source
+[[group(2), binding(0)]]
+var revealage_texture: texture_2d<f32>;
+
+// drawn with the shared quad mesh, covering the whole target
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec4<f32>,
+    [[location(2)]] tex_coord: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = position;
+    out.tex_coord = tex_coord;
+    return out;
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let numerator = color * (2.51 * color + vec3<f32>(0.03));
+    let denominator = color * (2.43 * color + vec3<f32>(0.59)) + vec3<f32>(0.14);
+    return clamp(numerator / denominator, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+[[stage(fragment)]]
+fn fs_tonemap(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // stop half a texel short of the rendered part, so filtering doesn't
+    // pick up stale texels past its edge
+    let tex_coord = min(in.tex_coord * post.render_scale, post.render_scale - post.texel_size * 0.5);
+    let hdr = textureSample(source_texture, source_sampler, tex_coord);
+    let color = hdr.rgb * post.exposure;
+
+    var mapped: vec3<f32>;
+    if (post.tonemap == u32(1)) {
+        mapped = aces(color);
+    } else {
+        mapped = color / (vec3<f32>(1.0) + color);
+    }
+
+    // the swap chain is sRGB, so gamma is applied when writing
+    return vec4<f32>(mapped, 1.0);
+}
+
+// log of the luminance of the rendered part, to be averaged by fs_downsample
+// down to a single texel
+[[stage(fragment)]]
+fn fs_luminance(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let hdr = textureSample(source_texture, source_sampler, in.tex_coord * post.render_scale);
+    let luminance = dot(hdr.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return vec4<f32>(log(luminance + 0.0001), 0.0, 0.0, 1.0);
+}
+
+// drawn to a target half the size of the source, with filtering averaging
+// the four texels around each pixel
+[[stage(fragment)]]
+fn fs_downsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(source_texture, source_sampler, in.tex_coord);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    // the source is sampled as linear, edges are found in perceptual space
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+let FXAA_SPAN_MAX: f32 = 8.0;
+let FXAA_REDUCE_MUL: f32 = 0.125;
+let FXAA_REDUCE_MIN: f32 = 0.0078125;
+
+// Lottes' FXAA: blurs along the edge direction estimated from the luma of the
+// four diagonal neighbors, unless that picks up colors outside the local range
+[[stage(fragment)]]
+fn fs_fxaa(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let t = post.texel_size;
+    let uv = in.tex_coord;
+
+    let luma_nw = luma(textureSample(source_texture, source_sampler, uv + vec2<f32>(-1.0, -1.0) * t).rgb);
+    let luma_ne = luma(textureSample(source_texture, source_sampler, uv + vec2<f32>(1.0, -1.0) * t).rgb);
+    let luma_sw = luma(textureSample(source_texture, source_sampler, uv + vec2<f32>(-1.0, 1.0) * t).rgb);
+    let luma_se = luma(textureSample(source_texture, source_sampler, uv + vec2<f32>(1.0, 1.0) * t).rgb);
+    let luma_m = luma(textureSample(source_texture, source_sampler, uv).rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    let edge = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+        FXAA_REDUCE_MIN,
+    );
+    let scale = 1.0 / (min(abs(edge.x), abs(edge.y)) + reduce);
+    let dir = clamp(edge * scale, vec2<f32>(-FXAA_SPAN_MAX), vec2<f32>(FXAA_SPAN_MAX)) * t;
+
+    let rgb_a = 0.5 * (
+        textureSample(source_texture, source_sampler, uv + dir * (1.0 / 3.0 - 0.5)).rgb
+        + textureSample(source_texture, source_sampler, uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let rgb_b = rgb_a * 0.5 + 0.25 * (
+        textureSample(source_texture, source_sampler, uv + dir * -0.5).rgb
+        + textureSample(source_texture, source_sampler, uv + dir * 0.5).rgb
+    );
+
+    let luma_b = luma(rgb_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return vec4<f32>(rgb_a, 1.0);
+    }
+    return vec4<f32>(rgb_b, 1.0);
+}
+
+// composites the transparency accumulated by shader.wgsl's fs_oit over the
+// scene, blended by the returned alpha
+[[stage(fragment)]]
+fn fs_oit_resolve(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    let accum = textureLoad(source_texture, texel, 0);
+    let revealage = textureLoad(revealage_texture, texel, 0).r;
+
+    let color = accum.rgb / max(accum.a, 0.00001);
+    return vec4<f32>(color, 1.0 - revealage);
+}
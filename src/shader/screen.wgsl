@@ -0,0 +1,38 @@
+struct VertexOutput {
+    [[location(0)]] tex_coord: vec2<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[block]]
+struct ScreenQuad {
+    // left, top, right, bottom in normalized device coordinates
+    rect: vec4<f32>;
+    // left, top, right, bottom in texture coordinates
+    uv_rect: vec4<f32>;
+    color: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> quad: ScreenQuad;
+
+[[group(1), binding(0)]]
+var quad_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var quad_sampler: sampler;
+
+// drawn with the shared quad mesh, whose positions go from -1 to 1
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec4<f32>,
+) -> VertexOutput {
+    let t = vec2<f32>((position.x + 1.0) * 0.5, (1.0 - position.y) * 0.5);
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(mix(quad.rect.xy, quad.rect.zw, t), 0.0, 1.0);
+    out.tex_coord = mix(quad.uv_rect.xy, quad.uv_rect.zw, t);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(quad_texture, quad_sampler, in.tex_coord) * quad.color;
+}
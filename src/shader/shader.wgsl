@@ -2,6 +2,9 @@ struct VertexOutput {
     [[location(0)]] tex_coord: vec2<f32>;
     [[location(1)]] normal: vec3<f32>;
     [[location(2)]] norpos: vec3<f32>;
+    [[location(3)]] tangent: vec4<f32>;
+    // distance from the camera along its forward axis
+    [[location(4)]] view_depth: f32;
     [[builtin(position)]] position: vec4<f32>;
 };
 
@@ -9,26 +12,114 @@ struct VertexOutput {
 struct Uniforms {
     mvp: mat4x4<f32>;
     transpose_inverse_modelview: mat4x4<f32>;
+    // for turning view space normals back to world space
+    inverse_view: mat4x4<f32>;
     light_direction: vec4<f32>;
     base_diffuse_color: vec4<f32>;
+    // multiplies the shaded color, white unless the node is tinted
+    tint: vec4<f32>;
+    // only used by fs_wireframe
+    wireframe_color: vec4<f32>;
+    fog_color: vec4<f32>;
+    // ambient light from above and from below, times intensity
+    ambient_sky: vec4<f32>;
+    ambient_ground: vec4<f32>;
+    // the world up axis in view space
+    up: vec4<f32>;
+    // rows of a 2x3 matrix offsetting, rotating and scaling texture
+    // coordinates, one per texture
+    diffuse_uv_transform: array<vec4<f32>, 2>;
+    normal_uv_transform: array<vec4<f32>, 2>;
+    orm_uv_transform: array<vec4<f32>, 2>;
+    // added to the mip level of the diffuse, normal and ORM textures
+    lod_bias: vec4<f32>;
+    // tints the specular reflection of non-metals, rgb only
+    specular_color: vec4<f32>;
     has_diffuse_texture: u32;
     has_normal_texture: u32;
     shaded: u32;
+    has_occlusion_texture: u32;
+    has_metallic_roughness_texture: u32;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    occlusion_strength: f32;
+    morph_target_count: u32;
+    vertex_count: u32;
+    // 1 is fully visible, below that pixels are dithered away
+    fade: f32;
+    // lowers alpha, a stand-in for refraction
+    transmission_factor: f32;
+    // index of refraction, for how much a transmissive surface reflects
+    ior: f32;
+    clearcoat_factor: f32;
+    clearcoat_roughness_factor: f32;
+    // 0 is regular shading, 1 shows world space normals and 2 UVs
+    debug_view: u32;
+    // 0 is no fog, 1 linear from fog_start to fog_end and 2 exponential
+    fog_mode: u32;
+    fog_start: f32;
+    fog_end: f32;
+    fog_density: f32;
+    // 1 when metallic surfaces reflect the reflection probe
+    reflection_probe: u32;
+    morph_weights: array<vec4<f32>, 2>;
 };
 [[group(0), binding(0)]]
 var<uniform> uniforms: Uniforms;
 
+struct MorphDelta {
+    position: vec4<f32>;
+    normal: vec4<f32>;
+};
+
+// one delta per vertex for each morph target, target after target
+[[block]]
+struct MorphDeltas {
+    deltas: [[stride(32)]] array<MorphDelta>;
+};
+[[group(0), binding(1)]]
+var<storage> morph: [[access(read)]] MorphDeltas;
+
+// the scene around a point, looked up with z flipped, see
+// GraphicsContext::update_reflection_probe
+[[group(0), binding(2)]]
+var reflection_probe: texture_cube<f32>;
+[[group(0), binding(3)]]
+var reflection_probe_sampler: sampler;
+
 [[stage(vertex)]]
 fn vs_main(
     [[location(0)]] position: vec4<f32>,
     [[location(1)]] normal: vec3<f32>,
     [[location(2)]] tex_coord: vec2<f32>,
+    [[location(3)]] tangent: vec4<f32>,
+    [[builtin(vertex_index)]] vertex_index: u32,
 ) -> VertexOutput {
+    var morphed_position: vec4<f32> = position;
+    var morphed_normal: vec3<f32> = normal;
+    var i: u32 = u32(0);
+    loop {
+        if (i >= uniforms.morph_target_count) {
+            break;
+        }
+        let weight = uniforms.morph_weights[i / u32(4)][i % u32(4)];
+        let delta = morph.deltas[i * uniforms.vertex_count + vertex_index];
+        morphed_position = morphed_position + vec4<f32>(delta.position.xyz * weight, 0.0);
+        morphed_normal = morphed_normal + delta.normal.xyz * weight;
+        continuing {
+            i = i + u32(1);
+        }
+    }
+
     var out: VertexOutput;
-    out.normal = (uniforms.transpose_inverse_modelview * vec4<f32>(normal, 0.0)).xyz;
-    out.position = uniforms.mvp * position;
+    out.normal = (uniforms.transpose_inverse_modelview * vec4<f32>(morphed_normal, 0.0)).xyz;
+    out.position = uniforms.mvp * morphed_position;
     out.norpos = out.position.xyz / out.position.w;
+    // w of a perspective projection is the view space depth
+    out.view_depth = out.position.w;
     out.tex_coord = tex_coord;
+    // the same matrix as the normal, exact for rotations and uniform scales
+    out.tangent = vec4<f32>((uniforms.transpose_inverse_modelview * vec4<f32>(tangent.xyz, 0.0)).xyz, tangent.w);
     return out;
 }
 
@@ -57,42 +148,185 @@ var normal_texture: texture_2d<f32>;
 [[group(2), binding(1)]]
 var normal_sampler: sampler;
 
-[[stage(fragment)]]
-fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+// occlusion in r, roughness in g, metallic in b
+[[group(3), binding(0)]]
+var orm_texture: texture_2d<f32>;
+[[group(3), binding(1)]]
+var orm_sampler: sampler;
+
+fn transform_uv(row_u: vec4<f32>, row_v: vec4<f32>, uv: vec2<f32>) -> vec2<f32> {
+    let p = vec3<f32>(uv, 1.0);
+    return vec2<f32>(dot(row_u.xyz, p), dot(row_v.xyz, p));
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let diffuse_uv = transform_uv(uniforms.diffuse_uv_transform[0], uniforms.diffuse_uv_transform[1], in.tex_coord);
+    let normal_uv = transform_uv(uniforms.normal_uv_transform[0], uniforms.normal_uv_transform[1], in.tex_coord);
+    let orm_uv = transform_uv(uniforms.orm_uv_transform[0], uniforms.orm_uv_transform[1], in.tex_coord);
+
+    var alpha: f32 = uniforms.base_diffuse_color.a;
+    if (uniforms.has_diffuse_texture > u32(0)) {
+        alpha = textureSampleBias(diffuse_texture, diffuse_sampler, diffuse_uv, uniforms.lod_bias.x).a;
+    }
+    // what isn't transmitted is reflected, more so at grazing angles
+    // (Schlick's approximation of Fresnel)
+    let r = (uniforms.ior - 1.0) / (uniforms.ior + 1.0);
+    let cos_theta = max(dot(normalize(in.normal), normalize(-in.norpos)), 0.0);
+    let fresnel = r * r + (1.0 - r * r) * pow(1.0 - cos_theta, 5.0);
+    alpha = alpha * (1.0 - uniforms.transmission_factor * (1.0 - fresnel));
+
     if (uniforms.shaded == u32(0)) {
         var diffuse_color: vec3<f32>;
         if (uniforms.has_diffuse_texture > u32(0)) {
-            diffuse_color = textureSample(diffuse_texture, diffuse_sampler, in.tex_coord).rgb;
+            diffuse_color = textureSampleBias(diffuse_texture, diffuse_sampler, diffuse_uv, uniforms.lod_bias.x).rgb;
         } else {
             diffuse_color = uniforms.base_diffuse_color.rgb;
         }
-        return vec4<f32>(diffuse_color, 1.0);
+        return vec4<f32>(diffuse_color, alpha);
     } else {
     
         var real_normal: vec3<f32>;
         if (uniforms.has_normal_texture > u32(0)) {
-            real_normal = textureSample(normal_texture, normal_sampler, in.tex_coord).rgb;
+            real_normal = textureSampleBias(normal_texture, normal_sampler, normal_uv, uniforms.lod_bias.y).rgb;
         } else {
             real_normal = in.normal;
         }
 
+        let orm = textureSampleBias(orm_texture, orm_sampler, orm_uv, uniforms.lod_bias.z).rgb;
+
+        var occlusion: f32 = 1.0;
+        if (uniforms.has_occlusion_texture > u32(0)) {
+            occlusion = 1.0 + uniforms.occlusion_strength * (orm.r - 1.0);
+        }
+
+        var shininess: f32 = 16.0;
+        var metallic: f32 = 0.0;
+        // how much of the reflection probe shows, for smooth metals
+        var reflectivity: f32 = 0.0;
+        if (uniforms.has_metallic_roughness_texture > u32(0)) {
+            let roughness = uniforms.roughness_factor * orm.g;
+            shininess = mix(64.0, 2.0, roughness);
+            metallic = uniforms.metallic_factor * orm.b;
+            reflectivity = metallic * (1.0 - roughness);
+        }
+
         let diffuse = max(dot(normalize(real_normal), normalize(uniforms.light_direction.xyz)), 0.0);
 
         let camera_dir = normalize(-in.norpos);
         let half_direction = normalize(normalize(uniforms.light_direction.xyz) + camera_dir);
-        let tbn = cotangent_frame(in.normal, in.norpos, in.tex_coord);
-        let specular = pow(max(dot(half_direction, normalize(tbn * -(real_normal * 2.0 - 1.0))), 0.0), 16.0);
+        var tbn: mat3x3<f32>;
+        if (in.tangent.w != 0.0) {
+            let n = normalize(in.normal);
+            let t = normalize(in.tangent.xyz);
+            let b = cross(n, t) * sign(in.tangent.w);
+            tbn = mat3x3<f32>(t, b, n);
+        } else {
+            tbn = cotangent_frame(in.normal, in.norpos, normal_uv);
+        }
+        let specular = pow(max(dot(half_direction, normalize(tbn * -(real_normal * 2.0 - 1.0))), 0.0), shininess);
 
         var diffuse_color: vec3<f32>;
         if (uniforms.has_diffuse_texture > u32(0)) {
-            diffuse_color = textureSample(diffuse_texture, diffuse_sampler, in.tex_coord).rgb;
+            diffuse_color = textureSampleBias(diffuse_texture, diffuse_sampler, diffuse_uv, uniforms.lod_bias.x).rgb;
         } else {
             diffuse_color = uniforms.base_diffuse_color.rgb;
         }
-        let ambient_color = diffuse_color * 0.1;
+        // hemispheric: sky on surfaces facing up, ground facing down
+        let sky = dot(normalize(in.normal), uniforms.up.xyz) * 0.5 + 0.5;
+        let ambient = mix(uniforms.ambient_ground.rgb, uniforms.ambient_sky.rgb, vec3<f32>(sky));
+        let ambient_color = diffuse_color * ambient * occlusion;
+
+        let specular_color = mix(uniforms.specular_color.rgb, diffuse_color, vec3<f32>(metallic, metallic, metallic));
+        var base: vec3<f32> = ambient_color + diffuse * diffuse_color + specular * specular_color;
+        if (uniforms.reflection_probe > u32(0)) {
+            // the view direction mirrored about the surface, in world space
+            let reflected = reflect(-camera_dir, normalize(in.normal));
+            let direction = (uniforms.inverse_view * vec4<f32>(reflected, 0.0)).xyz;
+            let probe = textureSample(reflection_probe, reflection_probe_sampler, vec3<f32>(direction.xy, -direction.z)).rgb;
+            base = mix(base, probe * specular_color * occlusion, vec3<f32>(reflectivity));
+        }
 
-        let specular_color = vec3<f32>(1.0, 1.0, 1.0);
+        // clearcoat: a white specular lobe on the geometric normal, since the
+        // coat is smooth over whatever the normal map does below it
+        let coat_shininess = mix(256.0, 2.0, uniforms.clearcoat_roughness_factor);
+        let coat_specular = pow(max(dot(half_direction, normalize(in.normal)), 0.0), coat_shininess);
+        // the coat reflects about 4% of the light before it reaches the base
+        let coat = uniforms.clearcoat_factor;
+        let color = base * (1.0 - 0.04 * coat) + vec3<f32>(coat_specular * coat);
         // return vec4<f32>(1.0, 0.0, 0.0, 1.0);
-        return vec4<f32>(ambient_color + diffuse * diffuse_color + specular * specular_color, 1.0);
+        return vec4<f32>(color, alpha);
     }
+}
+
+// threshold from 0 to 1 of a 4x4 ordered dither (Bayer) pattern at a pixel
+fn dither_threshold(position: vec2<f32>) -> f32 {
+    let p = floor(position) % vec2<f32>(4.0);
+    let fine = p % vec2<f32>(2.0);
+    let coarse = floor(p * 0.5);
+    let bayer = 4.0 * ((2.0 * fine.x + 3.0 * fine.y) % 4.0) + (2.0 * coarse.x + 3.0 * coarse.y) % 4.0;
+    return (bayer + 0.5) / 16.0;
+}
+
+// blends towards the fog color by the view space depth of the fragment
+fn apply_fog(color: vec4<f32>, depth: f32) -> vec4<f32> {
+    var visibility: f32 = 1.0;
+    if (uniforms.fog_mode == u32(1)) {
+        visibility = clamp((uniforms.fog_end - depth) / (uniforms.fog_end - uniforms.fog_start), 0.0, 1.0);
+    } elseif (uniforms.fog_mode == u32(2)) {
+        visibility = exp(-uniforms.fog_density * max(depth, 0.0));
+    }
+    return vec4<f32>(mix(uniforms.fog_color.rgb, color.rgb, vec3<f32>(visibility)), color.a);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (uniforms.fade < dither_threshold(in.position.xy)) {
+        discard;
+    }
+    if (uniforms.debug_view == u32(1)) {
+        let normal = (uniforms.inverse_view * vec4<f32>(normalize(in.normal), 0.0)).xyz;
+        return vec4<f32>(normal * 0.5 + vec3<f32>(0.5), 1.0);
+    }
+    if (uniforms.debug_view == u32(2)) {
+        return vec4<f32>(fract(in.tex_coord), 0.0, 1.0);
+    }
+    return apply_fog(shade(in) * uniforms.tint, in.view_depth);
+}
+
+// drawn additively without depth testing
+[[stage(fragment)]]
+fn fs_overdraw(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(0.08, 0.03, 0.01, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_wireframe(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (uniforms.fade < dither_threshold(in.position.xy)) {
+        discard;
+    }
+    return uniforms.wireframe_color;
+}
+
+struct OitOutput {
+    [[location(0)]] accum: vec4<f32>;
+    [[location(1)]] revealage: vec4<f32>;
+};
+
+// weighted blended order-independent transparency (McGuire and Bavoil 2013):
+// colors are summed weighted by alpha and depth, and the product of
+// (1 - alpha) is kept in the revealage target, to be composited afterwards
+[[stage(fragment)]]
+fn fs_oit(in: VertexOutput) -> OitOutput {
+    if (uniforms.fade < dither_threshold(in.position.xy)) {
+        discard;
+    }
+    let color = apply_fog(shade(in) * uniforms.tint, in.view_depth);
+    let a = color.a;
+    let z = in.position.z;
+    let weight = clamp(pow(min(1.0, a * 10.0) + 0.01, 3.0) * 100000000.0 * pow(1.0 - z * 0.9, 3.0), 0.01, 3000.0);
+
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * a, a) * weight;
+    out.revealage = vec4<f32>(a, a, a, a);
+    return out;
 }
\ No newline at end of file
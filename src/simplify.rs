@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::Vec3;
+
+/// Finest grid `simplify` tries, in cells along the longest side of the
+/// bounding box.
+pub const MAX_GRID_RESOLUTION: u32 = 1024;
+
+/// Indices of at most `target_triangles` triangles approximating the ones of
+/// `indices`, by vertex clustering: vertices falling in the same cell of a
+/// grid over the bounding box are merged into the first of them, and
+/// triangles left with no area are dropped. The finest grid that fits the
+/// target is picked. Vertices are only referred to, never moved or created,
+/// so the vertex attributes, morph targets and skin weights still apply,
+/// though unused vertices stay in the buffers. Small targets can leave no
+/// triangles at all.
+pub fn simplify(positions: &[Vec3], indices: &[u32], target_triangles: usize) -> Vec<u32> {
+    if indices.len() / 3 <= target_triangles {
+        return indices.to_vec();
+    }
+
+    let used = || indices.iter().map(|&it| positions[it as usize]);
+    let min = used().fold(Vec3::splat(f32::INFINITY), Vec3::min);
+    let max = used().fold(Vec3::splat(f32::NEG_INFINITY), Vec3::max);
+    let extent = (max - min).max_element();
+
+    // coarser grids merge more vertices, so the triangle count mostly
+    // shrinks with the resolution
+    let (mut low, mut high) = (1, MAX_GRID_RESOLUTION);
+    let mut best = cluster(positions, indices, min, extent, low);
+    while low < high {
+        let resolution = high - (high - low) / 2;
+        let clustered = cluster(positions, indices, min, extent, resolution);
+        if clustered.len() / 3 <= target_triangles {
+            best = clustered;
+            low = resolution;
+        } else {
+            high = resolution - 1;
+        }
+    }
+    best
+}
+
+/// `indices` with every vertex replaced by the first one in its cell of a
+/// `resolution` cells wide grid starting at `min`, without the triangles that
+/// end up degenerate or repeated.
+fn cluster(
+    positions: &[Vec3],
+    indices: &[u32],
+    min: Vec3,
+    extent: f32,
+    resolution: u32,
+) -> Vec<u32> {
+    let cell_size = (extent / resolution as f32).max(f32::MIN_POSITIVE);
+    let mut representatives = HashMap::new();
+    let mut representative = |index: u32| {
+        let cell = ((positions[index as usize] - min) / cell_size).floor();
+        let last = resolution as i32 - 1;
+        let cell = (
+            (cell.x as i32).min(last),
+            (cell.y as i32).min(last),
+            (cell.z as i32).min(last),
+        );
+        *representatives.entry(cell).or_insert(index)
+    };
+
+    let mut seen = HashSet::new();
+    let mut clustered = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(&mut representative);
+        if a == b || b == c || a == c {
+            continue;
+        }
+        // rotated to start at the smallest index, keeping the winding
+        let key = if a < b && a < c {
+            (a, b, c)
+        } else if b < c {
+            (b, c, a)
+        } else {
+            (c, a, b)
+        };
+        if seen.insert(key) {
+            clustered.extend_from_slice(&[a, b, c]);
+        }
+    }
+    clustered
+}
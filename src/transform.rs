@@ -1,6 +1,9 @@
 use glam::{Mat4, Quat, Vec3, Vec4};
 
 pub const GLOBAL_FORWARD: [f32; 3] = [0.0, 0.0, 1.0];
+/// The default up axis, +Y like glTF. Cameras (`Camera::set_up`) and
+/// billboards (`GraphicsContext::set_world_up`) can be given another one, such
+/// as +Z for scenes imported with `ImportOptions::z_up`.
 pub const GLOBAL_UP: [f32; 3] = [0.0, 1.0, 0.0];
 pub const GLOBAL_LEFT: [f32; 3] = [1.0, 0.0, 0.0];
 
@@ -14,10 +17,30 @@ impl From<Mat4> for Transform {
 }
 
 impl Transform {
+    /// A transform placed at `eye` whose `forward` points at `target`.
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        // glam's look_at builds a view matrix, which looks down -Z; mirroring
+        // the target makes our +Z forward face it instead
+        let view = Mat4::look_at_rh(eye, eye - (target - eye), up);
+        Self(view.inverse())
+    }
+
     pub fn mat4(&self) -> Mat4 {
         self.0
     }
 
+    /// Splits the transform into translation, rotation and scale, which
+    /// compose back into it (see `Trs::mat4`) if it has no shear. A mirroring
+    /// transform comes out with a negative x scale, whichever axes it flips.
+    pub fn decompose(&self) -> Trs {
+        let (scale, rotation, translation) = self.0.to_scale_rotation_translation();
+        Trs {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
     pub fn scale(&self) -> Vec3 {
         let (scale, _, _) = self.0.to_scale_rotation_translation();
         scale
@@ -44,6 +67,27 @@ impl Transform {
     }
 }
 
+/// A transform kept as separate translation, rotation and scale, so each
+/// channel can be animated on its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trs {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Trs {
+    pub fn mat4(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl From<Trs> for Transform {
+    fn from(trs: Trs) -> Self {
+        Self(trs.mat4())
+    }
+}
+
 // fn calculate_forward_direction(yaw: f32, pitch: f32) -> Vec3 {
 //     let result: Vec3 = [
 //         (-yaw).sin() * pitch.cos(),
@@ -53,3 +97,35 @@ impl Transform {
 //     .into();
 //     result.normalize()
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn trs() -> impl Strategy<Value = Trs> {
+        let translation = prop::array::uniform3(-1.0e5f32..1.0e5);
+        let rotation = prop::array::uniform4(-1.0f32..1.0)
+            .prop_filter("a rotation has to have some length", |it| {
+                Vec4::from(*it).length() > 0.1
+            });
+        let scale = prop::array::uniform3((0.1f32..10.0, any::<bool>()))
+            .prop_map(|it| it.map(|(size, negative)| if negative { -size } else { size }));
+        (translation, rotation, scale).prop_map(|(translation, rotation, scale)| Trs {
+            translation: translation.into(),
+            rotation: Quat::from_vec4(Vec4::from(rotation).normalize()),
+            scale: scale.into(),
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn decompose_round_trips(trs in trs()) {
+            let decomposed = Transform::from(trs).decompose();
+
+            prop_assert!(decomposed.translation.abs_diff_eq(trs.translation, 1.0e-3));
+            prop_assert!(decomposed.scale.abs().abs_diff_eq(trs.scale.abs(), 1.0e-3));
+            prop_assert!(decomposed.mat4().abs_diff_eq(trs.mat4(), 1.0e-3));
+        }
+    }
+}